@@ -0,0 +1,159 @@
+//! A small command line tool over the crate's high level operations, handy
+//! for checking its behavior against the lvm tools.
+//!
+//! cargo run --example lvm-rs -- list
+
+use std::env;
+use std::process;
+
+use lvm::json::Value;
+use lvm::provision::LvSpec;
+use lvm::snapshots::RetentionPolicy;
+use lvm::{LogicalVolume, Lvm, LvmError, LvmResult, LvmThinPolicy, OpenMode, VolumeGroup};
+
+const USAGE: &str = "usage: lvm-rs <command> [args]
+
+commands:
+  list                                     VGs and their LVs as JSON
+  report <vg>                              one VG and its LVs as JSON
+  ensure-lv <vg> <lv> <size> [tag...]      create or grow an LV
+  snapshot <vg> <lv> <prefix> [size]       take a timestamped snapshot
+  prune <vg> <prefix> <last> [daily] [weekly]
+                                           remove old timestamped snapshots
+  thin-create <vg> <pool> <size>           create a thin pool
+
+sizes are bytes or take a K, M, G or T suffix";
+
+fn usage_error() -> LvmError {
+    LvmError::new((errno::Errno(libc::EINVAL), USAGE.to_string()))
+}
+
+fn parse_size(size: &str) -> LvmResult<u64> {
+    let (number, shift) = match size.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&size[..size.len() - 1], 10),
+        Some('M') => (&size[..size.len() - 1], 20),
+        Some('G') => (&size[..size.len() - 1], 30),
+        Some('T') => (&size[..size.len() - 1], 40),
+        _ => (size, 0),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| {
+            LvmError::new((
+                errno::Errno(libc::EINVAL),
+                format!("Invalid size: {}", size),
+            ))
+        })
+}
+
+fn parse_count(count: Option<&String>) -> LvmResult<usize> {
+    match count {
+        Some(count) => count.parse().map_err(|_| usage_error()),
+        None => Ok(0),
+    }
+}
+
+fn lv_json(lv: &LogicalVolume<'_, '_>) -> LvmResult<Value> {
+    Ok(Value::object(vec![
+        ("name", lv.try_name()?.into()),
+        ("path", lv.path()?.to_string_lossy().into_owned().into()),
+        ("uuid", lv.try_uuid()?.into()),
+        ("size", lv.get_size().into()),
+        ("attributes", lv.try_attributes()?.into()),
+        ("origin", lv.get_origin().into()),
+        ("tags", lv.get_tags()?.into()),
+    ]))
+}
+
+fn vg_json(vg: &VolumeGroup<'_>) -> LvmResult<Value> {
+    let lvs = vg
+        .list_lvs()?
+        .iter()
+        .map(lv_json)
+        .collect::<LvmResult<Vec<Value>>>()?;
+    Ok(Value::object(vec![
+        ("name", vg.get_name()?.into()),
+        ("uuid", vg.try_uuid()?.into()),
+        ("size", vg.get_size().into()),
+        ("free", vg.get_free_size().into()),
+        ("extent_size", vg.get_extent_size().into()),
+        ("lvs", Value::Array(lvs)),
+    ]))
+}
+
+fn run(lvm: &Lvm, args: &[String]) -> LvmResult<Value> {
+    let arg = |i: usize| args.get(i).map(|a| a.as_str()).ok_or_else(usage_error);
+    match arg(0)? {
+        "list" => {
+            let mut vgs = vec![];
+            for name in lvm.get_volume_group_names()? {
+                let vg = lvm.vg_open(&name, &OpenMode::Read)?;
+                vgs.push(vg_json(&vg)?);
+            }
+            Ok(Value::Array(vgs))
+        }
+        "report" => {
+            let vg = lvm.vg_open(arg(1)?, &OpenMode::Read)?;
+            vg_json(&vg)
+        }
+        "ensure-lv" => {
+            let mut vg = lvm.vg_open(arg(1)?, &OpenMode::Write)?;
+            let mut spec = LvSpec::new(arg(2)?, parse_size(arg(3)?)?);
+            if args.len() > 4 {
+                let tags: Vec<&str> = args[4..].iter().map(|t| t.as_str()).collect();
+                spec = spec.tags(&tags);
+            }
+            let changes = vg.ensure_lv(&spec)?;
+            Ok(changes
+                .iter()
+                .map(|c| format!("{:?}", c))
+                .collect::<Vec<String>>()
+                .into())
+        }
+        "snapshot" => {
+            let vg = lvm.vg_open(arg(1)?, &OpenMode::Write)?;
+            let mut origin = vg.lv_from_name(arg(2)?)?;
+            let size = match args.get(4) {
+                Some(size) => parse_size(size)?,
+                None => 0,
+            };
+            let snapshot = origin.create_timestamped_snapshot(arg(3)?, size)?;
+            lv_json(&snapshot)
+        }
+        "prune" => {
+            let mut vg = lvm.vg_open(arg(1)?, &OpenMode::Write)?;
+            let policy = RetentionPolicy {
+                keep_last: parse_count(args.get(3))?,
+                keep_daily: parse_count(args.get(4))?,
+                keep_weekly: parse_count(args.get(5))?,
+            };
+            Ok(vg.prune_snapshots(arg(2)?, &policy)?.into())
+        }
+        "thin-create" => {
+            let mut vg = lvm.vg_open(arg(1)?, &OpenMode::Write)?;
+            vg.create_thin_pool(
+                arg(2)?,
+                parse_size(arg(3)?)?,
+                0,
+                0,
+                &LvmThinPolicy::Passdown,
+            )?;
+            lv_json(&vg.lv_from_name(arg(2)?)?)
+        }
+        _ => Err(usage_error()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = Lvm::new(None).and_then(|lvm| run(&lvm, &args));
+    match result {
+        Ok(output) => println!("{}", output),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Activating or deactivating many LVs at once, like vgchange -a, with what
+//! happened to each LV reported rather than stopping at the first failure.
+
+use std::collections::HashMap;
+
+use crate::{cli, LogicalVolume, Lvm, LvmError, LvmResult, OpenMode, VolumeGroup};
+
+/// What activate_all or deactivate_all did.  LVs are named vgname/lvname.
+#[derive(Debug, Default)]
+pub struct ActivationReport {
+    /// LVs that were activated or deactivated
+    pub changed: Vec<String>,
+    /// LVs that were already in the state asked for, or skip activation
+    pub skipped: Vec<String>,
+    pub failed: Vec<(String, LvmError)>,
+}
+
+impl ActivationReport {
+    /// Check if every LV ended up in the state asked for
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    pub(crate) fn record(&mut self, lv: String, result: LvmResult<()>) {
+        match result {
+            Ok(()) => self.changed.push(lv),
+            Err(e) => self.failed.push((lv, e)),
+        }
+    }
+}
+
+/// LVs built on others, snapshots and thin LVs, have to be deactivated
+/// before what they're built on
+fn depends_on_another(lv: &LogicalVolume<'_, '_>) -> bool {
+    let thin = lv.try_attributes().ok().and_then(|a| a.chars().next()) == Some('V');
+    thin || lv.get_origin().is_some()
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Activate every LV of the VG that isn't active, like vgchange -ay.
+    /// LVs that skip activation are left alone.
+    pub fn activate_all(&self) -> LvmResult<ActivationReport> {
+        let vg_name = self.get_name()?;
+        let mut report = ActivationReport::default();
+        for mut lv in self.list_lvs()? {
+            let name = format!("{}/{}", vg_name, lv.try_name()?);
+            if lv.try_is_active()? || lv.activation_skip()? {
+                report.skipped.push(name);
+                continue;
+            }
+            let result = lv.activate();
+            report.record(name, result);
+        }
+        Ok(report)
+    }
+
+    /// Deactivate every active LV of the VG, like vgchange -an.  Snapshots
+    /// and thin LVs go first so their origins and pools can follow.
+    pub fn deactivate_all(&self) -> LvmResult<ActivationReport> {
+        let vg_name = self.get_name()?;
+        let mut report = ActivationReport::default();
+        let mut lvs = self.list_lvs()?;
+        lvs.sort_by_key(|lv| !depends_on_another(lv));
+        for mut lv in lvs {
+            let name = format!("{}/{}", vg_name, lv.try_name()?);
+            if !lv.try_is_active()? {
+                report.skipped.push(name);
+                continue;
+            }
+            let result = lv.deactivate();
+            report.record(name, result);
+        }
+        Ok(report)
+    }
+}
+
+impl Lvm {
+    /// Bring up storage at boot, like `vgchange -aay` on every VG: scan
+    /// the devices, then activate every LV that may be autoactivated and
+    /// doesn't skip activation.  lvm releases without the autoactivation
+    /// setting autoactivate everything.  lvm.conf's
+    /// auto_activation_volume_list isn't consulted.  VGs that can't be
+    /// opened are reported as failed under their name.
+    pub fn activate_all_autoactivatable(&self) -> LvmResult<ActivationReport> {
+        self.scan()?;
+        let mut report = ActivationReport::default();
+        for vg_name in self.get_volume_group_names()? {
+            let vg = match self.vg_open(&vg_name, &OpenMode::Read) {
+                Ok(vg) => vg,
+                Err(e) => {
+                    report.failed.push((vg_name, e));
+                    continue;
+                }
+            };
+            if !vg.autoactivation().unwrap_or(true) {
+                for lv in vg.list_lvs()? {
+                    report
+                        .skipped
+                        .push(format!("{}/{}", vg_name, lv.try_name()?));
+                }
+                continue;
+            }
+            // One report for the whole VG rather than one per LV
+            let lv_autoactivation: HashMap<String, bool> =
+                cli::report(self, "lvs", &["lv_name", "autoactivation"], &vg_name)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|row| match row.as_slice() {
+                        [name, value] => Some((name.clone(), value == "enabled")),
+                        _ => None,
+                    })
+                    .collect();
+            for mut lv in vg.list_lvs()? {
+                let lv_name = lv.try_name()?;
+                let name = format!("{}/{}", vg_name, lv_name);
+                let autoactivate = lv_autoactivation.get(&lv_name).copied().unwrap_or(true);
+                if !autoactivate || lv.try_is_active()? || lv.activation_skip()? {
+                    report.skipped.push(name);
+                    continue;
+                }
+                let result = lv.activate();
+                report.record(name, result);
+            }
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,170 @@
+//! An audit trail of every call that changes LVM state, through liblvm or
+//! the lvm tools, or the data on an LV, through tools like mkfs or
+//! cryptsetup.  Records of commands carry their full argument list.
+//! Records are numbered per Lvm handle so a sink that chains or signs them
+//! can tell when one is missing.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{Lvm, LvmResult};
+
+/// One mutating call and how it went
+#[derive(Clone, Debug)]
+pub struct AuditRecord {
+    /// Position of the record among those of its Lvm handle, from 1
+    pub sequence: u64,
+    /// When the call started
+    pub time: SystemTime,
+    /// The liblvm function or lvm command, ie: lvm_lv_resize or lvcreate
+    pub operation: String,
+    /// The VG, LV, PV or device operated on
+    pub target: String,
+    pub parameters: Vec<(String, String)>,
+    /// The error message if the call failed
+    pub result: Result<(), String>,
+    pub duration: Duration,
+}
+
+/// Receives an AuditRecord for each mutating call.  It's called on the
+/// thread that made the call, after the call returns.
+pub trait Auditor {
+    fn record(&self, record: &AuditRecord);
+}
+
+impl<F> Auditor for F
+where
+    F: Fn(&AuditRecord),
+{
+    fn record(&self, record: &AuditRecord) {
+        self(record)
+    }
+}
+
+/// The auditor of an Lvm handle, shared with the workers of timed calls
+#[derive(Clone, Default)]
+pub(crate) struct AuditHook {
+    auditor: Option<Arc<dyn Auditor + Send + Sync>>,
+    sequence: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for AuditHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditHook")
+            .field("enabled", &self.auditor.is_some())
+            .field("sequence", &self.sequence.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+impl Lvm {
+    /// Send a record of every mutating call made through this handle to
+    /// auditor, replacing any earlier one
+    pub fn set_auditor<A: Auditor + Send + Sync + 'static>(&self, auditor: A) {
+        self.audit.borrow_mut().auditor = Some(Arc::new(auditor));
+    }
+
+    /// Stop auditing calls
+    pub fn clear_auditor(&self) {
+        self.audit.borrow_mut().auditor = None;
+    }
+
+    fn record<T>(
+        &self,
+        operation: &str,
+        target: &str,
+        parameters: &[(&str, String)],
+        time: SystemTime,
+        duration: Duration,
+        result: &LvmResult<T>,
+    ) {
+        let hook = self.audit.borrow().clone();
+        hook.record(operation, target, parameters, time, duration, result);
+    }
+
+    /// Run f, a mutating liblvm call, through ffi_call and audit it
+    pub(crate) fn audited<T, F>(
+        &self,
+        op: &'static str,
+        target: &str,
+        parameters: &[(&str, String)],
+        f: F,
+    ) -> LvmResult<T>
+    where
+        F: FnOnce() -> LvmResult<T>,
+    {
+        let time = SystemTime::now();
+        let start = Instant::now();
+        let result = self.ffi_call(op, target, parameters, f);
+        self.record(op, target, parameters, time, start.elapsed(), &result);
+        result
+    }
+
+    /// Run f, which runs the lvm command args, and audit it.  The command
+    /// is the operation and its last argument the target.
+    pub(crate) fn audited_command<T, F>(&self, args: &[&str], f: F) -> LvmResult<T>
+    where
+        F: FnOnce() -> LvmResult<T>,
+    {
+        let time = SystemTime::now();
+        let start = Instant::now();
+        let result = f();
+        self.record_command(args, time, start.elapsed(), &result);
+        result
+    }
+
+    /// Audit an lvm command that was run elsewhere, ie: on another thread
+    pub(crate) fn record_command<T>(
+        &self,
+        args: &[&str],
+        time: SystemTime,
+        duration: Duration,
+        result: &LvmResult<T>,
+    ) {
+        let hook = self.audit.borrow().clone();
+        hook.record_command(args, time, duration, result);
+    }
+}
+
+impl AuditHook {
+    fn record<T>(
+        &self,
+        operation: &str,
+        target: &str,
+        parameters: &[(&str, String)],
+        time: SystemTime,
+        duration: Duration,
+        result: &LvmResult<T>,
+    ) {
+        if let Some(ref auditor) = self.auditor {
+            auditor.record(&AuditRecord {
+                sequence: self.sequence.fetch_add(1, Ordering::SeqCst) + 1,
+                time,
+                operation: operation.to_string(),
+                target: target.to_string(),
+                parameters: parameters
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.clone()))
+                    .collect(),
+                result: result.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+                duration,
+            });
+        }
+    }
+
+    /// Audit an lvm command, for threads that only have the handle's hook
+    pub(crate) fn record_command<T>(
+        &self,
+        args: &[&str],
+        time: SystemTime,
+        duration: Duration,
+        result: &LvmResult<T>,
+    ) {
+        let operation = args.first().copied().unwrap_or("");
+        let target = args.last().copied().unwrap_or("");
+        let parameters = [("args", args.join(" "))];
+        self.record(operation, target, &parameters, time, duration, result);
+    }
+}
@@ -0,0 +1,50 @@
+//! Whether VGs and LVs are activated automatically, ie: by pvscan when
+//! their PVs appear at boot.  Only lvm 2.03.12 and later have the setting
+//! and liblvm can't see it, it's read and set with the lvm tools.
+
+use crate::{cli, LogicalVolume, Lvm, LvmResult, VolumeGroup};
+
+/// Read a report field that's "enabled" when set
+fn enabled(lvm: &Lvm, command: &str, field: &str, target: &str) -> LvmResult<bool> {
+    let rows = cli::report(lvm, command, &[field], target)?;
+    let value = rows.first().and_then(|row| row.first());
+    Ok(value.map(|v| v.as_str()) == Some("enabled"))
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Check if the VG's LVs may be autoactivated
+    pub fn autoactivation(&self) -> LvmResult<bool> {
+        enabled(self.lvm, "vgs", "autoactivation", &self.get_name()?)
+    }
+
+    /// Allow or stop autoactivation of the VG's LVs, vgchange
+    /// --setautoactivation
+    pub fn set_autoactivation(&mut self, enabled: bool) -> LvmResult<()> {
+        let name = self.get_name()?;
+        self.run_command(&[
+            "vgchange",
+            "--setautoactivation",
+            if enabled { "y" } else { "n" },
+            &name,
+        ])?;
+        Ok(())
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Check if the LV may be autoactivated
+    pub fn autoactivation(&self) -> LvmResult<bool> {
+        enabled(self.lvm, "lvs", "autoactivation", &self.full_name()?)
+    }
+
+    /// Allow or stop autoactivation of the LV, lvchange
+    /// --setautoactivation.  An LV is only autoactivated if its VG is too.
+    pub fn set_autoactivation(&mut self, enabled: bool) -> LvmResult<()> {
+        self.run_command(&[
+            "lvchange",
+            "--setautoactivation",
+            if enabled { "y" } else { "n" },
+        ])?;
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+//! Block device ioctls used on the device nodes of active LVs, and FITRIM
+//! and freezing for the filesystems mounted on them
+
+use std::fs::{File, OpenOptions};
+use std::io::Error as IOError;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::LvmResult;
+
+/// _IO(0x12, 119) from linux/fs.h
+const BLKDISCARD: libc::c_ulong = 0x1277;
+/// _IO(0x12, 127) from linux/fs.h
+const BLKZEROOUT: libc::c_ulong = 0x127f;
+/// _IOWR('X', 121, struct fstrim_range) from linux/fs.h
+const FITRIM: libc::c_ulong = 0xc018_5879;
+/// _IOWR('X', 119, int) from linux/fs.h
+const FIFREEZE: libc::c_ulong = 0xc004_5877;
+/// _IOWR('X', 120, int) from linux/fs.h
+const FITHAW: libc::c_ulong = 0xc004_5878;
+
+fn range_ioctl(device: &Path, request: libc::c_ulong, offset: u64, len: u64) -> LvmResult<()> {
+    let dev = OpenOptions::new().write(true).open(device)?;
+    let range: [u64; 2] = [offset, len];
+    let ret = unsafe { libc::ioctl(dev.as_raw_fd(), request, range.as_ptr()) };
+    if ret < 0 {
+        return Err(IOError::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Discard len bytes of a block device starting at offset
+pub(crate) fn discard(device: &Path, offset: u64, len: u64) -> LvmResult<()> {
+    range_ioctl(device, BLKDISCARD, offset, len)
+}
+
+/// Overwrite len bytes of a block device starting at offset with zeros
+pub(crate) fn zero(device: &Path, offset: u64, len: u64) -> LvmResult<()> {
+    range_ioctl(device, BLKZEROOUT, offset, len)
+}
+
+/// Discard the unused blocks of the filesystem mounted at mount_point and
+/// return how many bytes were trimmed
+pub(crate) fn fitrim(mount_point: &Path) -> LvmResult<u64> {
+    let dir = File::open(mount_point)?;
+    // struct fstrim_range { u64 start; u64 len; u64 minlen; }, len is
+    // updated with the number of bytes trimmed
+    let mut range: [u64; 3] = [0, u64::MAX, 0];
+    let ret = unsafe { libc::ioctl(dir.as_raw_fd(), FITRIM, range.as_mut_ptr()) };
+    if ret < 0 {
+        return Err(IOError::last_os_error().into());
+    }
+    Ok(range[1])
+}
+
+/// A filesystem frozen by freeze, thawed again when dropped
+pub(crate) struct Frozen {
+    dir: File,
+    mount_point: PathBuf,
+}
+
+impl Drop for Frozen {
+    fn drop(&mut self) {
+        let ret = unsafe { libc::ioctl(self.dir.as_raw_fd(), FITHAW, 0) };
+        if ret < 0 {
+            error!(
+                "Failed to thaw {}: {}",
+                self.mount_point.display(),
+                IOError::last_os_error()
+            );
+        }
+    }
+}
+
+/// Flush the filesystem mounted at mount_point and block writes to it
+/// until the returned Frozen is dropped, like fsfreeze
+pub(crate) fn freeze(mount_point: &Path) -> LvmResult<Frozen> {
+    let dir = File::open(mount_point)?;
+    let ret = unsafe { libc::ioctl(dir.as_raw_fd(), FIFREEZE, 0) };
+    if ret < 0 {
+        return Err(IOError::last_os_error().into());
+    }
+    Ok(Frozen {
+        dir,
+        mount_point: mount_point.to_path_buf(),
+    })
+}
@@ -0,0 +1,324 @@
+//! Making LV mounts and swap persist across reboots, as fstab lines or
+//! systemd .mount and .swap units.  Devices are named by their filesystem
+//! UUID so entries survive LV renames.  Inserting an entry that's already
+//! there, or removing one that isn't, changes nothing.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use errno::Errno;
+
+use crate::{LogicalVolume, LvmError, LvmResult};
+
+/// A filesystem or swap area to set up at boot
+#[derive(Clone, Debug, PartialEq)]
+pub struct BootEntry {
+    /// Filesystem UUID of the device
+    pub uuid: String,
+    /// Mount point, None for swap
+    pub target: Option<PathBuf>,
+    /// ie: ext4, or swap
+    pub fs_type: String,
+    /// Comma separated mount options, ie: defaults or noatime,discard
+    pub options: String,
+    /// fsck pass, 0 skips checking, 1 for the root filesystem and 2 for
+    /// everything else
+    pub pass: u32,
+}
+
+/// Escape a path the way systemd-escape --path does for unit names
+fn systemd_escape_path(path: &str) -> String {
+    let trimmed = path.trim_matches('/');
+    if trimmed.is_empty() {
+        return "-".to_string();
+    }
+    let mut escaped = String::new();
+    for (i, b) in trimmed.bytes().enumerate() {
+        match b {
+            b'/' => escaped.push('-'),
+            b'.' if i == 0 => escaped.push_str("\\x2e"),
+            b if b.is_ascii_alphanumeric() || b == b':' || b == b'_' || b == b'.' => {
+                escaped.push(b as char)
+            }
+            b => escaped.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    escaped
+}
+
+/// Escape the characters fstab separates or escapes fields with as octal,
+/// ie: a space as \040
+fn fstab_escape(field: &str) -> String {
+    let mut escaped = String::new();
+    for c in field.chars() {
+        match c {
+            ' ' => escaped.push_str("\\040"),
+            '\t' => escaped.push_str("\\011"),
+            '\n' => escaped.push_str("\\012"),
+            '\\' => escaped.push_str("\\134"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl BootEntry {
+    fn what(&self) -> String {
+        format!("/dev/disk/by-uuid/{}", self.uuid)
+    }
+
+    /// The entry as an fstab line.  Spaces, tabs, newlines and backslashes
+    /// in the target are escaped as octal, as fstab expects.
+    pub fn fstab_line(&self) -> String {
+        let target = match self.target {
+            Some(ref target) => fstab_escape(&target.to_string_lossy()),
+            None => "none".to_string(),
+        };
+        format!(
+            "UUID={} {} {} {} 0 {}",
+            self.uuid, target, self.fs_type, self.options, self.pass
+        )
+    }
+
+    /// The name systemd expects the entry's unit file to have, ie:
+    /// srv-data.mount
+    pub fn systemd_unit_name(&self) -> String {
+        match self.target {
+            Some(ref target) => format!("{}.mount", systemd_escape_path(&target.to_string_lossy())),
+            None => format!("{}.swap", systemd_escape_path(&self.what())),
+        }
+    }
+
+    /// The contents of the entry's systemd unit file
+    pub fn systemd_unit(&self) -> String {
+        match self.target {
+            Some(ref target) => format!(
+                "[Unit]\nDescription=Mount {}\n\n[Mount]\nWhat={}\nWhere={}\nType={}\n\
+                 Options={}\n\n[Install]\nWantedBy=local-fs.target\n",
+                target.display(),
+                self.what(),
+                target.display(),
+                self.fs_type,
+                self.options
+            ),
+            None => format!(
+                "[Unit]\nDescription=Swap on {}\n\n[Swap]\nWhat={}\nOptions={}\n\n\
+                 [Install]\nWantedBy=swap.target\n",
+                self.what(),
+                self.what(),
+                self.options
+            ),
+        }
+    }
+}
+
+/// Write contents to path through a temporary file so a crash can't leave
+/// it half written
+fn replace_file(path: &Path, contents: &str) -> LvmResult<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// The fstab line whose device is UUID=uuid, if any
+fn fstab_line_for<'f>(fstab: &'f str, uuid: &str) -> Option<&'f str> {
+    let device = format!("UUID={}", uuid);
+    fstab
+        .lines()
+        .find(|line| line.split_whitespace().next() == Some(device.as_str()))
+}
+
+/// Add entry to the fstab at path, replacing any line for the same device.
+/// Returns whether the file changed.
+pub fn fstab_insert(path: &Path, entry: &BootEntry) -> LvmResult<bool> {
+    let fstab = fs::read_to_string(path)?;
+    let line = entry.fstab_line();
+    let mut lines: Vec<&str> = match fstab_line_for(&fstab, &entry.uuid) {
+        Some(existing) if existing == line => return Ok(false),
+        Some(existing) => fstab.lines().filter(|l| *l != existing).collect(),
+        None => fstab.lines().collect(),
+    };
+    lines.push(&line);
+    replace_file(path, &(lines.join("\n") + "\n"))?;
+    Ok(true)
+}
+
+/// Remove the line for the device with filesystem UUID uuid from the fstab
+/// at path.  Returns whether the file changed.
+pub fn fstab_remove(path: &Path, uuid: &str) -> LvmResult<bool> {
+    let fstab = fs::read_to_string(path)?;
+    let existing = match fstab_line_for(&fstab, uuid) {
+        Some(existing) => existing,
+        None => return Ok(false),
+    };
+    let lines: Vec<&str> = fstab.lines().filter(|l| *l != existing).collect();
+    replace_file(path, &(lines.join("\n") + "\n"))?;
+    Ok(true)
+}
+
+/// Write entry's unit into dir, ie: /etc/systemd/system.  Returns whether
+/// the file changed.  systemd only sees it after a daemon-reload and only
+/// starts it at boot once the unit is enabled.
+pub fn install_unit(dir: &Path, entry: &BootEntry) -> LvmResult<bool> {
+    let path = dir.join(entry.systemd_unit_name());
+    let unit = entry.systemd_unit();
+    if fs::read_to_string(&path).ok().as_ref() == Some(&unit) {
+        return Ok(false);
+    }
+    replace_file(&path, &unit)?;
+    Ok(true)
+}
+
+/// Remove entry's unit from dir.  Returns whether there was one.
+pub fn remove_unit(dir: &Path, entry: &BootEntry) -> LvmResult<bool> {
+    let path = dir.join(entry.systemd_unit_name());
+    if !path.exists() {
+        return Ok(false);
+    }
+    fs::remove_file(&path)?;
+    Ok(true)
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Describe mounting the filesystem on an active LV at target, or
+    /// using it as swap if target is None.  The filesystem's type and UUID
+    /// are read with blkid.
+    pub fn boot_entry(&self, target: Option<&Path>, options: &str) -> LvmResult<BootEntry> {
+        let name = self.try_name()?;
+        let signature = self.blkid()?.ok_or_else(|| {
+            LvmError::new((Errno(libc::EINVAL), format!("{} holds no filesystem", name)))
+        })?;
+        let uuid = match signature.uuid {
+            Some(ref uuid) => uuid.clone(),
+            None => {
+                return Err(LvmError::new((
+                    Errno(libc::EINVAL),
+                    format!("The {} on {} has no UUID", signature.fs_type, name),
+                )))
+            }
+        };
+        if target.is_none() != (signature.fs_type == "swap") {
+            return Err(LvmError::new((
+                Errno(libc::EINVAL),
+                format!(
+                    "{} holds {}, which needs {}",
+                    name,
+                    signature.fs_type,
+                    if target.is_none() {
+                        "a mount point"
+                    } else {
+                        "no mount point"
+                    }
+                ),
+            )));
+        }
+        Ok(BootEntry {
+            uuid,
+            target: target.map(|t| t.to_path_buf()),
+            // Filesystems without fsck, ie: xfs and btrfs, ignore the pass
+            pass: if target.is_some() { 2 } else { 0 },
+            fs_type: signature.fs_type,
+            options: options.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(target: Option<&str>) -> BootEntry {
+        BootEntry {
+            uuid: "0a1b2c3d-1111-2222-3333-444455556666".to_string(),
+            target: target.map(PathBuf::from),
+            fs_type: if target.is_some() { "ext4" } else { "swap" }.to_string(),
+            options: "defaults".to_string(),
+            pass: if target.is_some() { 2 } else { 0 },
+        }
+    }
+
+    /// A scratch fstab holding contents
+    fn fstab(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("lvm-fstab-{}-{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    const FSTAB: &str = "# /etc/fstab\nUUID=root / ext4 defaults 0 1\n";
+
+    #[test]
+    fn escapes_systemd_paths() {
+        assert_eq!(systemd_escape_path("/"), "-");
+        assert_eq!(systemd_escape_path(""), "-");
+        assert_eq!(systemd_escape_path("/srv/data"), "srv-data");
+        assert_eq!(systemd_escape_path("/srv/data/"), "srv-data");
+        assert_eq!(systemd_escape_path("/srv/my-data"), "srv-my\\x2ddata");
+        assert_eq!(systemd_escape_path("/.snapshots/a.b"), "\\x2esnapshots-a.b");
+        assert_eq!(systemd_escape_path("/srv/a b"), "srv-a\\x20b");
+        assert_eq!(systemd_escape_path("/srv/x_y:z"), "srv-x_y:z");
+        assert_eq!(systemd_escape_path("/srv/caf\u{e9}"), "srv-caf\\xc3\\xa9");
+    }
+
+    #[test]
+    fn unit_names() {
+        assert_eq!(
+            entry(Some("/srv/data")).systemd_unit_name(),
+            "srv-data.mount"
+        );
+        assert_eq!(
+            entry(None).systemd_unit_name(),
+            "dev-disk-by\\x2duuid-0a1b2c3d\\x2d1111\\x2d2222\\x2d3333\\x2d444455556666.swap"
+        );
+    }
+
+    #[test]
+    fn fstab_lines() {
+        assert_eq!(
+            entry(Some("/srv/data")).fstab_line(),
+            "UUID=0a1b2c3d-1111-2222-3333-444455556666 /srv/data ext4 defaults 0 2"
+        );
+        assert_eq!(
+            entry(None).fstab_line(),
+            "UUID=0a1b2c3d-1111-2222-3333-444455556666 none swap defaults 0 0"
+        );
+        let line = entry(Some("/srv/my data\tx\\y")).fstab_line();
+        assert!(
+            line.contains(" /srv/my\\040data\\011x\\134y ext4 "),
+            "{}",
+            line
+        );
+        assert_eq!(line.split_whitespace().count(), 6);
+    }
+
+    #[test]
+    fn fstab_insert_is_idempotent() {
+        let path = fstab("insert", FSTAB);
+        let data = entry(Some("/srv/data"));
+        assert!(fstab_insert(&path, &data).unwrap());
+        assert!(!fstab_insert(&path, &data).unwrap());
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, format!("{}{}\n", FSTAB, data.fstab_line()));
+
+        // A changed entry replaces the line for the same device
+        let mut changed = data.clone();
+        changed.options = "noatime".to_string();
+        assert!(fstab_insert(&path, &changed).unwrap());
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, format!("{}{}\n", FSTAB, changed.fstab_line()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fstab_remove_keeps_other_lines() {
+        let data = entry(Some("/srv/data"));
+        let path = fstab("remove", &format!("{}{}\n", FSTAB, data.fstab_line()));
+        assert!(fstab_remove(&path, &data.uuid).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), FSTAB);
+        assert!(!fstab_remove(&path, &data.uuid).unwrap());
+        // Only whole device fields match
+        assert!(!fstab_remove(&path, "roo").unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), FSTAB);
+        fs::remove_file(&path).unwrap();
+    }
+}
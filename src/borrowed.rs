@@ -0,0 +1,190 @@
+//! Accessors that borrow strings from liblvm instead of copying them into a
+//! String, for callers walking thousands of LVs.  liblvm keeps the strings
+//! in the VG's memory pool, which reopening the VG after an lvm command
+//! frees, so each borrowed string pins the VG and lvm commands run through
+//! it or any of its LVs and PVs fail with EBUSY until the string is
+//! dropped.  The string can't outlive what pins the VG:
+//!
+//! ```compile_fail
+//! # use std::ffi::CStr;
+//! # fn name(vg: &lvm::VolumeGroup) {
+//! let name: &CStr = {
+//!     let borrowed = vg.name_cstr();
+//!     &borrowed
+//! };
+//! # }
+//! ```
+
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::fmt;
+use std::ops::Deref;
+use std::os::raw::c_char;
+use std::ptr;
+
+use lvm_sys::*;
+
+use crate::{LogicalVolume, PhysicalVolume, VolumeGroup};
+
+/// Borrow a string liblvm returned, an empty one if it returned none
+unsafe fn borrowed<'s>(ptr: *const c_char) -> &'s CStr {
+    if ptr.is_null() {
+        return CStr::from_bytes_with_nul_unchecked(b"\0");
+    }
+    CStr::from_ptr(ptr)
+}
+
+/// Keeps a VG from being reopened while alive
+struct Pin<'v> {
+    pins: &'v Cell<usize>,
+}
+
+impl<'v> Pin<'v> {
+    fn new(pins: &'v Cell<usize>) -> Pin<'v> {
+        pins.set(pins.get() + 1);
+        Pin { pins }
+    }
+}
+
+impl<'v> Clone for Pin<'v> {
+    fn clone(&self) -> Pin<'v> {
+        Pin::new(self.pins)
+    }
+}
+
+impl<'v> Drop for Pin<'v> {
+    fn drop(&mut self) {
+        self.pins.set(self.pins.get() - 1);
+    }
+}
+
+/// A string borrowed from liblvm, derefs to a CStr
+pub struct Borrowed<'v> {
+    ptr: *const c_char,
+    _pin: Pin<'v>,
+}
+
+impl<'v> Borrowed<'v> {
+    fn new(vg: &'v VolumeGroup<'_>, ptr: *const c_char) -> Borrowed<'v> {
+        Borrowed {
+            ptr,
+            _pin: Pin::new(&vg.pins),
+        }
+    }
+}
+
+impl<'v> Deref for Borrowed<'v> {
+    type Target = CStr;
+
+    fn deref(&self) -> &CStr {
+        unsafe { borrowed(self.ptr) }
+    }
+}
+
+impl<'v> fmt::Debug for Borrowed<'v> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// The tags of a VG, LV or PV, see tags_iter
+pub struct Tags<'v> {
+    head: *const dm_list,
+    next: *mut dm_list,
+    pin: Pin<'v>,
+}
+
+impl<'v> Tags<'v> {
+    unsafe fn new(vg: &'v VolumeGroup<'_>, head: *mut dm_list) -> Tags<'v> {
+        let next = if head.is_null() {
+            head
+        } else {
+            dm_list_first(head)
+        };
+        Tags {
+            head,
+            next,
+            pin: Pin::new(&vg.pins),
+        }
+    }
+}
+
+impl<'v> Iterator for Tags<'v> {
+    type Item = Borrowed<'v>;
+
+    fn next(&mut self) -> Option<Borrowed<'v>> {
+        if self.next.is_null() {
+            return None;
+        }
+        unsafe {
+            let str_list = self.next as *mut lvm_str_list;
+            self.next = dm_list_next(self.head, self.next);
+            Some(Borrowed {
+                ptr: (*str_list).str,
+                _pin: self.pin.clone(),
+            })
+        }
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Borrow the name of a volume group, see get_name for an owned one
+    pub fn name_cstr(&self) -> Borrowed<'_> {
+        match self.handle() {
+            Ok(handle) => Borrowed::new(self, unsafe { lvm_vg_get_name(handle) }),
+            Err(_) => Borrowed::new(self, ptr::null()),
+        }
+    }
+
+    /// Iterate over the tags of a volume group without copying them
+    pub fn tags_iter(&self) -> Tags<'_> {
+        match self.handle() {
+            Ok(handle) => unsafe { Tags::new(self, lvm_vg_get_tags(handle)) },
+            Err(_) => unsafe { Tags::new(self, ptr::null_mut()) },
+        }
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Borrow the name of a logical volume, see try_name for an owned one
+    pub fn name_cstr(&self) -> Borrowed<'_> {
+        match self.handle() {
+            Ok(handle) => Borrowed::new(self.vg, unsafe { lvm_lv_get_name(handle) }),
+            Err(_) => Borrowed::new(self.vg, ptr::null()),
+        }
+    }
+
+    /// Borrow the attributes of a logical volume
+    pub fn attributes_cstr(&self) -> Borrowed<'_> {
+        match self.handle() {
+            Ok(handle) => Borrowed::new(self.vg, unsafe { lvm_lv_get_attr(handle) }),
+            Err(_) => Borrowed::new(self.vg, ptr::null()),
+        }
+    }
+
+    /// Iterate over the tags of a logical volume without copying them
+    pub fn tags_iter(&self) -> Tags<'_> {
+        match self.handle() {
+            Ok(handle) => unsafe { Tags::new(self.vg, lvm_lv_get_tags(handle)) },
+            Err(_) => unsafe { Tags::new(self.vg, ptr::null_mut()) },
+        }
+    }
+}
+
+impl<'a> PhysicalVolume<'a> {
+    /// Borrow the name of a physical volume, see try_name for an owned one
+    pub fn name_cstr(&self) -> Borrowed<'_> {
+        match self.handle() {
+            Ok(handle) => Borrowed::new(self.vg, unsafe { lvm_pv_get_name(handle) }),
+            Err(_) => Borrowed::new(self.vg, ptr::null()),
+        }
+    }
+
+    /// Iterate over the tags of a physical volume without copying them
+    pub fn tags_iter(&self) -> Tags<'_> {
+        match self.handle() {
+            Ok(handle) => unsafe { Tags::new(self.vg, lvm_pv_get_tags(handle)) },
+            Err(_) => unsafe { Tags::new(self.vg, ptr::null_mut()) },
+        }
+    }
+}
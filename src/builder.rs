@@ -0,0 +1,122 @@
+//! Typed construction of an Lvm handle for embedded and container use,
+//! where LVM keeps its state and how it locks and talks to udev all
+//! need to be set in one place rather than in lvm.conf.
+
+use std::path::{Path, PathBuf};
+
+use crate::{Lvm, LvmResult};
+
+/// How liblvm locks VGs, see locking_type in lvm.conf
+#[derive(Clone, Copy, Debug)]
+pub enum LockingType {
+    /// No locking at all.  Only safe if nothing else touches the VGs.
+    None,
+    /// File based locking in the locking directory
+    Local,
+    /// Clustered locking through clvmd
+    Clustered,
+    /// Read only locking, anything that would change metadata fails
+    ReadOnly,
+}
+
+impl LockingType {
+    fn as_arg(&self) -> &'static str {
+        match self {
+            LockingType::None => "0",
+            LockingType::Local => "1",
+            LockingType::Clustered => "3",
+            LockingType::ReadOnly => "4",
+        }
+    }
+}
+
+/// Builder for an Lvm handle
+#[derive(Debug, Default)]
+pub struct LvmBuilder {
+    system_dir: Option<PathBuf>,
+    locking_dir: Option<PathBuf>,
+    locking_type: Option<LockingType>,
+    use_udev: Option<bool>,
+    container_mode: bool,
+    overrides: Vec<String>,
+}
+
+impl LvmBuilder {
+    /// Use an alternative LVM system directory instead of /etc/lvm, for
+    /// liblvm and the lvm commands run for it
+    pub fn system_dir(mut self, dir: &Path) -> Self {
+        self.system_dir = Some(dir.to_path_buf());
+        self
+    }
+
+    /// Keep lock files in dir instead of /run/lock/lvm
+    pub fn locking_dir(mut self, dir: &Path) -> Self {
+        self.locking_dir = Some(dir.to_path_buf());
+        self
+    }
+
+    pub fn locking_type(mut self, locking_type: LockingType) -> Self {
+        self.locking_type = Some(locking_type);
+        self
+    }
+
+    /// Whether to wait for udev to create device nodes and to take the
+    /// device list from udev.  Turn off where udev isn't running.
+    pub fn use_udev(mut self, use_udev: bool) -> Self {
+        self.use_udev = Some(use_udev);
+        self
+    }
+
+    /// Run without udev, see Lvm::set_container_mode
+    pub fn container_mode(mut self, enabled: bool) -> Self {
+        self.container_mode = enabled;
+        self
+    }
+
+    /// Override any other setting, see Lvm::config_override
+    pub fn config_override(mut self, path: &str, value: &str) -> Self {
+        self.overrides.push(format!("{}={}", path, value));
+        self
+    }
+
+    /// Create the handle and apply the settings
+    pub fn build(self) -> LvmResult<Lvm> {
+        let system_dir = self
+            .system_dir
+            .as_ref()
+            .map(|dir| dir.to_string_lossy().into_owned());
+        let lvm = Lvm::new(system_dir.as_deref())?;
+        if self.container_mode {
+            lvm.set_container_mode(true)?;
+        }
+        let mut settings = vec![];
+        if let Some(ref dir) = self.locking_dir {
+            settings.push(format!("global/locking_dir=\"{}\"", dir.display()));
+        }
+        if let Some(locking_type) = self.locking_type {
+            settings.push(format!("global/locking_type={}", locking_type.as_arg()));
+        }
+        if let Some(use_udev) = self.use_udev {
+            let value = use_udev as u8;
+            settings.push(format!("activation/udev_sync={}", value));
+            settings.push(format!("activation/udev_rules={}", value));
+            settings.push(format!("devices/obtain_device_list_from_udev={}", value));
+        }
+        settings.extend(self.overrides);
+        if settings.is_empty() {
+            return Ok(lvm);
+        }
+        for setting in &settings {
+            lvm.config_override(setting)?;
+        }
+        lvm.reload_config()?;
+        Ok(lvm)
+    }
+}
+
+impl Lvm {
+    /// Start building a handle with custom settings
+    pub fn builder() -> LvmBuilder {
+        LvmBuilder::default()
+    }
+}
@@ -0,0 +1,60 @@
+//! liblvm keeps what it learned from scanning devices in a cache and a VG
+//! handle holds the metadata that was read when it was opened.  Neither
+//! notices changes made by other processes, ie: the lvm tools, so
+//! listings and properties can be stale until the cache is invalidated or
+//! the VG is refreshed.  Changes made through this crate's handles,
+//! including the ones that run the lvm tools, are always reflected.
+
+use errno::Errno;
+
+use crate::{cli, Lvm, LvmError, LvmResult, VolumeGroup};
+
+impl Lvm {
+    /// Drop everything liblvm has cached about devices and VGs and rescan,
+    /// so VG listings and newly opened VGs reflect changes made by other
+    /// processes.  VG handles that are already open aren't affected, see
+    /// VolumeGroup::refresh.
+    pub fn invalidate_cache(&self) -> LvmResult<()> {
+        // Reloading the config rebuilds liblvm's command context along with
+        // its device and metadata caches
+        self.reload_config()?;
+        self.scan()
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// The metadata sequence number currently on disk
+    fn disk_seq_number(&self) -> LvmResult<u64> {
+        let name = self.get_name()?;
+        let rows = cli::report(self.lvm, "vgs", &["vg_seqno"], &name)?;
+        let field = rows.first().and_then(|row| row.first()).ok_or_else(|| {
+            LvmError::new((Errno(libc::ENOENT), format!("VG {} not found", name)))
+        })?;
+        field.parse().map_err(|_| {
+            LvmError::new((
+                Errno(libc::EINVAL),
+                format!("Invalid sequence number: {}", field),
+            ))
+        })
+    }
+
+    /// Check if the VG's metadata was changed on disk since this handle
+    /// read it
+    pub fn is_stale(&self) -> LvmResult<bool> {
+        Ok(self.disk_seq_number()? != self.get_seq_number())
+    }
+
+    /// Re-read the VG's metadata if it changed on disk, keeping this
+    /// handle usable.  Returns whether anything was re-read.  liblvm can't
+    /// refresh an open handle so the VG is briefly closed and reopened in
+    /// the same mode.
+    pub fn refresh(&mut self) -> LvmResult<bool> {
+        if !self.is_stale()? {
+            return Ok(false);
+        }
+        let name = self.get_name()?;
+        self.close_handle(&name)?;
+        self.reopen(&name)?;
+        Ok(true)
+    }
+}
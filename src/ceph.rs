@@ -0,0 +1,80 @@
+//! One LV per disk provisioning in the style of `ceph-volume lvm batch`.
+//! A device is turned into a PV, given its own uniquely named VG and a
+//! single LV using all of it, and the LV is tagged with the ceph.* metadata
+//! ceph-volume uses to find OSDs again.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use errno::Errno;
+use uuid::Uuid;
+
+use crate::{Lvm, LvmError, LvmResult};
+
+/// The LV prepared for an OSD
+#[derive(Debug)]
+pub struct OsdDevice {
+    pub vg_name: String,
+    pub lv_name: String,
+    pub lv_path: PathBuf,
+    pub lv_uuid: String,
+    /// Every tag set on the LV
+    pub tags: Vec<String>,
+}
+
+/// A random UUID from the kernel
+fn new_uuid() -> LvmResult<Uuid> {
+    let uuid = fs::read_to_string("/proc/sys/kernel/random/uuid")?;
+    Ok(Uuid::parse_str(uuid.trim())?)
+}
+
+impl Lvm {
+    /// Prepare device for an OSD: pvcreate, vgcreate ceph-<uuid> and
+    /// lvcreate osd-block-<uuid> using 100% of the VG.  The LV is tagged
+    /// with ceph.type=block, ceph.block_device and ceph.block_uuid plus a
+    /// ceph.<key>=<value> tag for every entry in tags, ie: ("osd_fsid", ..).
+    /// Devices that already belong to a VG are refused with EBUSY.
+    pub fn prepare_osd_device(&self, device: &Path, tags: &[(&str, &str)]) -> LvmResult<OsdDevice> {
+        if let Some(vg) = self.vg_name_from_device(&device.to_string_lossy())? {
+            return Err(LvmError::new((
+                Errno(libc::EBUSY),
+                format!("{} already belongs to VG {}", device.display(), vg),
+            )));
+        }
+        self.ensure_pv(device)?;
+
+        let vg_name = format!("ceph-{}", new_uuid()?);
+        let lv_name = format!("osd-block-{}", new_uuid()?);
+        let mut vg = self.vg_create(&vg_name)?;
+        vg.extend(device)?;
+        vg.run_command(&[
+            "lvcreate",
+            "--yes",
+            "--extents",
+            "100%FREE",
+            "--name",
+            &lv_name,
+            &vg_name,
+        ])?;
+
+        let mut lv = vg.lv_from_name(&lv_name)?;
+        let lv_path = lv.path()?;
+        let lv_uuid = lv.try_uuid()?;
+        let mut all_tags = vec![
+            "ceph.type=block".to_string(),
+            format!("ceph.block_device={}", lv_path.display()),
+            format!("ceph.block_uuid={}", lv_uuid),
+        ];
+        all_tags.extend(tags.iter().map(|(k, v)| format!("ceph.{}={}", k, v)));
+        let tag_refs: Vec<&str> = all_tags.iter().map(|t| t.as_str()).collect();
+        lv.add_tags(&tag_refs)?;
+
+        Ok(OsdDevice {
+            vg_name,
+            lv_name,
+            lv_path,
+            lv_uuid,
+            tags: all_tags,
+        })
+    }
+}
@@ -0,0 +1,138 @@
+//! Operations liblvm doesn't expose are run through the lvm command line
+//! tools.  Callers must make sure no VG handle they need is holding the VG
+//! lock while a command runs or the command will block waiting for it.
+
+use std::path::Path;
+use std::process::Command;
+
+use errno::Errno;
+
+use crate::{trace, Lvm, LvmError, LvmResult};
+
+/// Run `lvm <args>` and return its stdout.  system_dir, if given, is
+/// passed on as LVM_SYSTEM_DIR so the tools read the same lvm.conf and
+/// archive as the handle.
+pub(crate) fn run(system_dir: Option<&Path>, args: &[&str]) -> LvmResult<String> {
+    let mut command = Command::new("lvm");
+    if let Some(dir) = system_dir {
+        command.env("LVM_SYSTEM_DIR", dir);
+    }
+    execute(command, "lvm", args)
+}
+
+/// Run one of the other device-mapper/lvm related tools, ie: dmsetup, and
+/// return its stdout
+pub(crate) fn run_program(program: &str, args: &[&str]) -> LvmResult<String> {
+    execute(Command::new(program), program, args)
+}
+
+fn execute(mut cmd: Command, program: &str, args: &[&str]) -> LvmResult<String> {
+    let command = args.join(" ");
+    debug!("running {} {}", program, command);
+    trace::call("command", &command, || {
+        let output = cmd.args(args).output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(LvmError::new((
+                Errno(libc::EIO),
+                format!("{} {} failed: {}", program, command, stderr.trim()),
+            )));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    })
+}
+
+/// Run a reporting command, ie: lvs, for target and return one row of
+/// field values per object.  Reports are run with --readonly so they don't
+/// need the VG lock and work while a VG handle is open, and with the
+/// handle's test mode and config overrides like Lvm::run_command.
+pub(crate) fn report(
+    lvm: &Lvm,
+    command: &str,
+    fields: &[&str],
+    target: &str,
+) -> LvmResult<Vec<Vec<String>>> {
+    report_with(
+        lvm.system_dir.as_deref(),
+        &lvm.command_args(&[command]),
+        &[],
+        fields,
+        target,
+    )
+}
+
+/// Like report but includes hidden internal LVs, ie: thin pool data and
+/// metadata sub-LVs, whose names are reported in [brackets]
+pub(crate) fn report_all(
+    lvm: &Lvm,
+    command: &str,
+    fields: &[&str],
+    target: &str,
+) -> LvmResult<Vec<Vec<String>>> {
+    report_with(
+        lvm.system_dir.as_deref(),
+        &lvm.command_args(&[command]),
+        &["--all"],
+        fields,
+        target,
+    )
+}
+
+/// Like report but with a row per segment, ie: for pvs a row for each
+/// allocated or free run of extents on a PV
+pub(crate) fn report_segments(
+    lvm: &Lvm,
+    command: &str,
+    fields: &[&str],
+    target: &str,
+) -> LvmResult<Vec<Vec<String>>> {
+    report_with(
+        lvm.system_dir.as_deref(),
+        &lvm.command_args(&[command]),
+        &["--segments"],
+        fields,
+        target,
+    )
+}
+
+/// Run a report given command, the reporting command with Lvm::command_args
+/// already applied, and the handle's system dir, for callers that can't
+/// hold on to the handle
+pub(crate) fn report_with(
+    system_dir: Option<&Path>,
+    command: &[String],
+    extra: &[&str],
+    fields: &[&str],
+    target: &str,
+) -> LvmResult<Vec<Vec<String>>> {
+    let fields = fields.join(",");
+    let mut args: Vec<&str> = command.iter().map(|a| a.as_str()).collect();
+    args.extend_from_slice(&[
+        "--readonly",
+        "--noheadings",
+        "--nosuffix",
+        "--units",
+        "b",
+        "--separator",
+        "|",
+    ]);
+    args.extend_from_slice(extra);
+    args.extend_from_slice(&["--options", &fields]);
+    // An empty target reports every object
+    if !target.is_empty() {
+        args.push(target);
+    }
+    let output = run(system_dir, &args)?;
+
+    Ok(output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.trim()
+                .split('|')
+                .map(|f| f.trim().to_string())
+                .collect()
+        })
+        .collect())
+}
@@ -0,0 +1,124 @@
+//! Running inside containers without udev.  Container mode stops liblvm
+//! waiting for udev or asking it for the device list, and device nodes of
+//! active LVs are created directly with mknod: /dev/mapper/<dm name> and
+//! the /dev/<vg>/<lv> symlink udev would normally make.
+
+use std::ffi::CString;
+use std::fs;
+use std::io::Error as IOError;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, FileTypeExt, MetadataExt};
+use std::path::Path;
+
+use crate::{LogicalVolume, Lvm, LvmResult, OpenMode};
+
+/// Settings that keep liblvm and the lvm tools away from udev
+const NO_UDEV_SETTINGS: &[&str] = &[
+    "activation/udev_sync",
+    "activation/udev_rules",
+    "activation/verify_udev_operations",
+    "devices/obtain_device_list_from_udev",
+];
+
+const EXTERNAL_DEVICE_INFO_SOURCE: &str = "devices/external_device_info_source";
+
+/// Make sure path is the block device major:minor
+fn make_block_node(path: &Path, major: u32, minor: u32) -> LvmResult<()> {
+    let dev = libc::makedev(major, minor);
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if meta.file_type().is_block_device() && meta.rdev() == dev {
+            return Ok(());
+        }
+        fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let ret = unsafe { libc::mknod(c_path.as_ptr(), libc::S_IFBLK | 0o660, dev) };
+    if ret < 0 {
+        return Err(IOError::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Make sure link is a symlink to target
+fn make_symlink(target: &Path, link: &Path) -> LvmResult<()> {
+    if let Ok(existing) = fs::read_link(link) {
+        if existing == target {
+            return Ok(());
+        }
+    }
+    if fs::symlink_metadata(link).is_ok() {
+        fs::remove_file(link)?;
+    }
+    if let Some(parent) = link.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    symlink(target, link)?;
+    Ok(())
+}
+
+fn remove_if_present(path: &Path) -> LvmResult<()> {
+    if fs::symlink_metadata(path).is_ok() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+impl Lvm {
+    /// Check if container mode is on
+    pub fn is_container_mode(&self) -> bool {
+        self.container_mode.get()
+    }
+
+    /// Turn container mode on or off.  While it's on udev is never used and
+    /// activating or deactivating an LV creates or removes its device nodes
+    /// directly.  Turning it off drops the overrides it added, so lvm.conf
+    /// decides again.
+    pub fn set_container_mode(&self, enabled: bool) -> LvmResult<()> {
+        let mut paths = NO_UDEV_SETTINGS.to_vec();
+        paths.push(EXTERNAL_DEVICE_INFO_SOURCE);
+        let settings: Vec<String> = if enabled {
+            NO_UDEV_SETTINGS
+                .iter()
+                .map(|setting| format!("{}=0", setting))
+                .chain(Some(format!("{}=\"none\"", EXTERNAL_DEVICE_INFO_SOURCE)))
+                .collect()
+        } else {
+            vec![]
+        };
+        self.replace_overrides(&paths, &settings)?;
+        self.reload_config()?;
+        self.container_mode.set(enabled);
+        Ok(())
+    }
+
+    /// Create the device nodes of every active LV and remove those of
+    /// inactive ones, ie: after LVs were activated by another process
+    pub fn sync_device_nodes(&self) -> LvmResult<()> {
+        for vg_name in self.get_volume_group_names()? {
+            let vg = self.vg_open(&vg_name, &OpenMode::Read)?;
+            for lv in vg.list_lvs()? {
+                lv.sync_device_node()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Create this LV's /dev/mapper node and /dev/<vg>/<lv> symlink if it's
+    /// active, or remove them if it isn't
+    pub fn sync_device_node(&self) -> LvmResult<()> {
+        let node = self.dm_path()?;
+        let link = self.path()?;
+        if !self.try_is_active()? {
+            remove_if_present(&link)?;
+            return remove_if_present(&node);
+        }
+        let (major, minor) = self.major_minor()?;
+        make_block_node(&node, major, minor)?;
+        make_symlink(&Path::new("../mapper").join(self.dm_name()?), &link)
+    }
+}
@@ -0,0 +1,214 @@
+//! Change the type of an existing logical volume in place (lvconvert).
+//! liblvm has no support for these so they're run through the lvm tools.
+//! Conversions that need to resync data return as soon as the new layout
+//! is in place; use `VolumeGroup::wait_for_sync` to follow their progress.
+
+use std::thread;
+use std::time::Duration;
+
+use crate::{AllocPolicy, LvmResult, LvmThinPolicy, VolumeGroup};
+
+/// RAID levels an LV can be converted to
+#[derive(Debug)]
+pub enum RaidLevel {
+    Raid0,
+    Raid1,
+    Raid4,
+    Raid5,
+    Raid6,
+    Raid10,
+}
+
+impl RaidLevel {
+    fn as_arg(&self) -> &'static str {
+        match self {
+            RaidLevel::Raid0 => "raid0",
+            RaidLevel::Raid1 => "raid1",
+            RaidLevel::Raid4 => "raid4",
+            RaidLevel::Raid5 => "raid5",
+            RaidLevel::Raid6 => "raid6",
+            RaidLevel::Raid10 => "raid10",
+        }
+    }
+}
+
+/// Parameters for converting a linear LV to raid1
+#[derive(Debug)]
+pub struct Raid1Conversion {
+    /// Number of additional copies of the data
+    pub mirrors: u32,
+    /// Allocate the new images with this policy instead of the LV's
+    pub alloc: Option<AllocPolicy>,
+}
+
+/// Parameters for changing the RAID level of an LV
+#[derive(Debug)]
+pub struct RaidConversion {
+    pub level: RaidLevel,
+    /// Number of data stripes, None keeps the current count
+    pub stripes: Option<u32>,
+    /// Allocate any new images with this policy instead of the LV's
+    pub alloc: Option<AllocPolicy>,
+}
+
+/// Parameters for combining two existing LVs into a thin pool
+#[derive(Debug)]
+pub struct ThinPoolConversion<'a> {
+    /// LV that becomes the pool's data device
+    pub data_lv: &'a str,
+    /// LV that becomes the pool's metadata device
+    pub metadata_lv: &'a str,
+    /// Chunk size in bytes, None uses the lvm default
+    pub chunk_size: Option<u64>,
+    pub discards: Option<LvmThinPolicy>,
+    /// Zero newly provisioned blocks
+    pub zero: Option<bool>,
+}
+
+/// Parameters for converting an LV to a thin LV in a pool.  The original
+/// LV becomes a read only external origin of the new thin LV.
+#[derive(Debug)]
+pub struct ThinConversion<'a> {
+    pub lv: &'a str,
+    pub thin_pool: &'a str,
+}
+
+/// Parameters for splitting images off a mirrored or raid1 LV
+#[derive(Debug)]
+pub struct MirrorSplit<'a> {
+    pub lv: &'a str,
+    /// Number of images to split off
+    pub count: u32,
+    /// Name of the new LV made from the split images
+    pub new_name: &'a str,
+}
+
+impl<'a> VolumeGroup<'a> {
+    fn lv_path(&self, lv_name: &str) -> LvmResult<String> {
+        Ok(format!("{}/{}", self.get_name()?, lv_name))
+    }
+
+    /// Convert a linear LV to raid1
+    pub fn convert_to_raid1(&mut self, lv_name: &str, params: &Raid1Conversion) -> LvmResult<()> {
+        let lv_path = self.lv_path(lv_name)?;
+        let mirrors = params.mirrors.to_string();
+        let mut args = vec![
+            "lvconvert",
+            "--yes",
+            "--type",
+            "raid1",
+            "--mirrors",
+            &mirrors,
+        ];
+        if let Some(ref policy) = params.alloc {
+            policy.prepare(self.lvm)?;
+            args.extend_from_slice(&["--alloc", policy.as_arg()]);
+        }
+        args.push(&lv_path);
+        self.run_command(&args)?;
+        Ok(())
+    }
+
+    /// Change the RAID level of an LV, ie: raid1 to raid5
+    pub fn convert_raid_level(&mut self, lv_name: &str, params: &RaidConversion) -> LvmResult<()> {
+        let lv_path = self.lv_path(lv_name)?;
+        let stripes = params.stripes.map(|s| s.to_string());
+        let mut args = vec!["lvconvert", "--yes", "--type", params.level.as_arg()];
+        if let Some(ref stripes) = stripes {
+            args.extend_from_slice(&["--stripes", stripes]);
+        }
+        if let Some(ref policy) = params.alloc {
+            policy.prepare(self.lvm)?;
+            args.extend_from_slice(&["--alloc", policy.as_arg()]);
+        }
+        args.push(&lv_path);
+        self.run_command(&args)?;
+        Ok(())
+    }
+
+    /// Combine a data LV and a metadata LV into a thin pool.  The pool
+    /// keeps the data LV's name.
+    pub fn convert_to_thin_pool(&mut self, params: &ThinPoolConversion<'_>) -> LvmResult<()> {
+        let data_path = self.lv_path(params.data_lv)?;
+        let metadata_path = self.lv_path(params.metadata_lv)?;
+        let chunk_size = params.chunk_size.map(|c| format!("{}b", c));
+        let mut args = vec![
+            "lvconvert",
+            "--yes",
+            "--type",
+            "thin-pool",
+            "--poolmetadata",
+            &metadata_path,
+        ];
+        if let Some(ref chunk_size) = chunk_size {
+            args.extend_from_slice(&["--chunksize", chunk_size]);
+        }
+        if let Some(ref discards) = params.discards {
+            args.extend_from_slice(&["--discards", discards.as_arg()]);
+        }
+        if let Some(zero) = params.zero {
+            args.extend_from_slice(&["--zero", if zero { "y" } else { "n" }]);
+        }
+        args.push(&data_path);
+        self.run_command(&args)?;
+        Ok(())
+    }
+
+    /// Convert an LV, ie: a snapshot, into a thin LV of a thin pool
+    pub fn convert_to_thin(&mut self, params: &ThinConversion<'_>) -> LvmResult<()> {
+        let lv_path = self.lv_path(params.lv)?;
+        let pool_path = self.lv_path(params.thin_pool)?;
+        self.run_command(&[
+            "lvconvert",
+            "--yes",
+            "--type",
+            "thin",
+            "--thinpool",
+            &pool_path,
+            &lv_path,
+        ])?;
+        Ok(())
+    }
+
+    /// Split images off a mirrored LV into a new linear LV
+    pub fn split_mirrors(&mut self, params: &MirrorSplit<'_>) -> LvmResult<()> {
+        let lv_path = self.lv_path(params.lv)?;
+        self.run_command(&[
+            "lvconvert",
+            "--yes",
+            "--splitmirrors",
+            &params.count.to_string(),
+            "--name",
+            params.new_name,
+            &lv_path,
+        ])?;
+        Ok(())
+    }
+
+    /// Block until a mirror or raid LV has finished syncing, calling
+    /// progress with the sync percentage every interval
+    pub fn wait_for_sync<F>(
+        &self,
+        lv_name: &str,
+        interval: Duration,
+        mut progress: F,
+    ) -> LvmResult<()>
+    where
+        F: FnMut(f64),
+    {
+        loop {
+            let lv = self.lv_from_name(lv_name)?;
+            match lv.mirror_sync_percent()? {
+                Some(percent) => {
+                    progress(percent);
+                    if percent >= 100.0 {
+                        return Ok(());
+                    }
+                }
+                // Not a mirror so there's nothing to wait for
+                None => return Ok(()),
+            }
+            thread::sleep(interval);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Space used by classic copy on write snapshots.  A classic snapshot that
+//! runs out of space is invalidated by the kernel and its contents are
+//! lost, thin snapshots don't have the problem.
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use errno::Errno;
+
+use crate::retry::{self, RetryPolicy};
+use crate::{audit, cli, LogicalVolume, Lvm, LvmError, LvmResult};
+
+/// How full a classic snapshot is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SnapshotUsage {
+    /// Percentage of the snapshot's space holding copied blocks
+    pub allocated_percent: f64,
+    /// The snapshot overflowed, or failed, and the kernel dropped it.  Its
+    /// contents can't be read anymore, all that's left is to remove it.
+    pub invalid: bool,
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Get how full a classic snapshot is.  Fails with EINVAL for LVs that
+    /// aren't classic snapshots.
+    pub fn snapshot_usage(&self) -> LvmResult<SnapshotUsage> {
+        let attr = self.try_attributes()?;
+        let mut chars = attr.chars();
+        if !matches!(chars.next(), Some('s') | Some('S')) {
+            return Err(LvmError::new((
+                Errno(libc::EINVAL),
+                format!("{} is not a classic snapshot", self.try_name()?),
+            )));
+        }
+        // The state is the fifth attribute, I and S mark an invalid
+        // snapshot, S one that's also suspended
+        let invalid = matches!(chars.nth(3), Some('I') | Some('S'));
+        let allocated_percent = match self.get_percent_property("snap_percent")? {
+            Some(percent) => percent,
+            None if invalid => 100.0,
+            None => 0.0,
+        };
+        Ok(SnapshotUsage {
+            allocated_percent,
+            invalid,
+        })
+    }
+}
+
+/// When a SnapshotGuard grows its snapshot
+#[derive(Clone, Debug)]
+pub struct ExtendPolicy {
+    /// Grow the snapshot once this percentage of it is used
+    pub threshold_percent: f64,
+    /// Grow by this percentage of the snapshot's current size
+    pub extend_percent: f64,
+    /// How often to check the usage
+    pub interval: Duration,
+    /// Never grow the snapshot past this many bytes
+    pub max_size: Option<u64>,
+}
+
+impl Default for ExtendPolicy {
+    /// The same as lvm.conf's snapshot_autoextend defaults of 70 and 20,
+    /// checked every 10 seconds
+    fn default() -> Self {
+        ExtendPolicy {
+            threshold_percent: 70.0,
+            extend_percent: 20.0,
+            interval: Duration::from_secs(10),
+            max_size: None,
+        }
+    }
+}
+
+/// A classic snapshot that's grown as it fills and removed when the guard
+/// is dropped, for taking a backup from.  Usage is checked on a thread of
+/// its own through the lvm tools, so no VG handle may hold the VG lock for
+/// long while the guard lives or the growing has to wait for it.
+pub struct SnapshotGuard<'a> {
+    lvm: &'a Lvm,
+    vg_name: String,
+    name: String,
+    monitor: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl<'a> SnapshotGuard<'a> {
+    /// Snapshot vg_name/origin as name with size bytes of space, see lvm's
+    /// rounding of sizes to extents, and start watching it
+    pub fn create(
+        lvm: &'a Lvm,
+        vg_name: &str,
+        origin: &str,
+        name: &str,
+        size: u64,
+        policy: &ExtendPolicy,
+    ) -> LvmResult<SnapshotGuard<'a>> {
+        let origin = format!("{}/{}", vg_name, origin);
+        let size = format!("{}b", size);
+        lvm.run_command(
+            lvm.lock_retry.get(),
+            &[
+                "lvcreate",
+                "--snapshot",
+                "--size",
+                &size,
+                "--name",
+                name,
+                &origin,
+            ],
+        )?;
+        let mut guard = SnapshotGuard {
+            lvm,
+            vg_name: vg_name.to_string(),
+            name: name.to_string(),
+            monitor: None,
+        };
+        let (stop, stopped) = mpsc::channel();
+        let monitor = Monitor {
+            target: guard.target(),
+            lvs: lvm.command_args(&["lvs"]),
+            lvextend: lvm.command_args(&["lvextend"]),
+            system_dir: lvm.system_dir.clone(),
+            lock_retry: lvm.lock_retry.get(),
+            audit: lvm.audit.borrow().clone(),
+            policy: policy.clone(),
+        };
+        let handle = thread::Builder::new()
+            .name(format!("snapshot-{}", name))
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) =
+                    stopped.recv_timeout(monitor.policy.interval)
+                {
+                    if !monitor.check() {
+                        break;
+                    }
+                }
+            })?;
+        guard.monitor = Some((stop, handle));
+        Ok(guard)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The device path of the snapshot, ie: /dev/vgname/lvname
+    pub fn path(&self) -> PathBuf {
+        Path::new("/dev").join(&self.vg_name).join(&self.name)
+    }
+
+    fn target(&self) -> String {
+        format!("{}/{}", self.vg_name, self.name)
+    }
+
+    fn stop(&mut self) {
+        if let Some((stop, handle)) = self.monitor.take() {
+            drop(stop);
+            let _ = handle.join();
+        }
+    }
+
+    /// Stop watching and remove the snapshot, reporting a failure that
+    /// drop would only log
+    pub fn remove(mut self) -> LvmResult<()> {
+        self.stop();
+        let target = self.target();
+        let result = self
+            .lvm
+            .run_command(self.lvm.lock_retry.get(), &["lvremove", "--force", &target]);
+        // Nothing left for drop to do
+        self.name.clear();
+        result.map(|_| ())
+    }
+}
+
+impl<'a> Drop for SnapshotGuard<'a> {
+    fn drop(&mut self) {
+        self.stop();
+        if self.name.is_empty() {
+            return;
+        }
+        let target = self.target();
+        if let Err(e) = self
+            .lvm
+            .run_command(self.lvm.lock_retry.get(), &["lvremove", "--force", &target])
+        {
+            error!("Failed to remove snapshot {}: {}", target, e);
+        }
+    }
+}
+
+/// What the watching thread of a SnapshotGuard needs, the Lvm handle can't
+/// be shared with it
+struct Monitor {
+    target: String,
+    /// lvs and lvextend with the handle's test mode and config overrides
+    lvs: Vec<String>,
+    lvextend: Vec<String>,
+    system_dir: Option<PathBuf>,
+    lock_retry: RetryPolicy,
+    audit: audit::AuditHook,
+    policy: ExtendPolicy,
+}
+
+impl Monitor {
+    /// Grow the snapshot if it's past the threshold.  Returns false once
+    /// there's nothing left to watch.
+    fn check(&self) -> bool {
+        let rows = match cli::report_with(
+            self.system_dir.as_deref(),
+            &self.lvs,
+            &[],
+            &["snap_percent", "lv_size"],
+            &self.target,
+        ) {
+            Ok(rows) => rows,
+            Err(e) => {
+                warn!("Failed to check snapshot {}: {}", self.target, e);
+                return true;
+            }
+        };
+        let (percent, size) = match rows.first().map(|row| row.as_slice()) {
+            Some([percent, size]) => (percent.parse::<f64>(), size.parse::<u64>()),
+            _ => {
+                warn!("Snapshot {} is gone, no longer watching it", self.target);
+                return false;
+            }
+        };
+        let (percent, size) = match (percent, size) {
+            (Ok(percent), Ok(size)) => (percent, size),
+            _ => {
+                warn!("Snapshot {} is invalid, no longer watching it", self.target);
+                return false;
+            }
+        };
+        if percent < self.policy.threshold_percent {
+            return true;
+        }
+
+        let grow_by = (size as f64 * self.policy.extend_percent / 100.0) as u64;
+        let mut new_size = size.saturating_add(grow_by.max(1));
+        if let Some(max_size) = self.policy.max_size {
+            new_size = new_size.min(max_size);
+        }
+        if new_size <= size {
+            warn!(
+                "Snapshot {} is {:.1}% full and can't grow past {} bytes",
+                self.target, percent, size
+            );
+            return true;
+        }
+        info!(
+            "Snapshot {} is {:.1}% full, growing it to {} bytes",
+            self.target, percent, new_size
+        );
+        let new_size = format!("{}b", new_size);
+        let args = ["lvextend", "--size", &new_size, &self.target];
+        let mut full_args: Vec<&str> = self.lvextend.iter().map(|a| a.as_str()).collect();
+        full_args.extend_from_slice(&args[1..]);
+        let time = SystemTime::now();
+        let start = Instant::now();
+        let result = retry::retry(self.lock_retry, || {
+            cli::run(self.system_dir.as_deref(), &full_args)
+        });
+        self.audit
+            .record_command(&args, time, start.elapsed(), &result);
+        if let Err(e) = result {
+            warn!("Failed to grow snapshot {}: {}", self.target, e);
+        }
+        true
+    }
+}
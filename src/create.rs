@@ -0,0 +1,102 @@
+//! Create logical volumes with options liblvm's lvm_vg_create_lv_linear
+//! doesn't offer.  LVs are created through lvcreate so the builder takes the
+//! VG mutably while the command runs.
+
+use std::path::Path;
+
+use crate::{AllocPolicy, LogicalVolume, LvmResult, VolumeGroup};
+
+/// Builder for a new linear logical volume
+#[derive(Debug)]
+pub struct LvCreateBuilder {
+    name: String,
+    size: u64,
+    zero: Option<bool>,
+    discard_on_create: bool,
+    contiguous: bool,
+    alloc: Option<AllocPolicy>,
+    /// PVs, with optional extent ranges, to allocate from
+    pvs: Vec<String>,
+}
+
+impl LvCreateBuilder {
+    /// Start building an LV of size bytes
+    pub fn new(name: &str, size: u64) -> Self {
+        LvCreateBuilder {
+            name: name.to_string(),
+            size,
+            zero: None,
+            discard_on_create: false,
+            contiguous: false,
+            alloc: None,
+            pvs: vec![],
+        }
+    }
+
+    /// Zero the first 4KiB of the new LV so stale signatures aren't
+    /// picked up.  lvm zeroes by default.
+    pub fn zero(mut self, zero: bool) -> Self {
+        self.zero = Some(zero);
+        self
+    }
+
+    /// Discard the entire LV once it's created
+    pub fn discard_on_create(mut self, discard: bool) -> Self {
+        self.discard_on_create = discard;
+        self
+    }
+
+    /// Require the LV's extents to be contiguous.  Creation fails if there's
+    /// no free range large enough.
+    pub fn contiguous(mut self, contiguous: bool) -> Self {
+        self.contiguous = contiguous;
+        self
+    }
+
+    /// Allocate the LV's extents with this policy instead of the VG's
+    pub fn alloc_policy(mut self, policy: AllocPolicy) -> Self {
+        self.alloc = Some(policy);
+        self
+    }
+
+    /// Only allocate extents from this PV.  May be called more than once to
+    /// allow several PVs.
+    pub fn on_pv(mut self, device: &Path) -> Self {
+        self.pvs.push(device.to_string_lossy().into_owned());
+        self
+    }
+
+    /// Only allocate physical extents first..=last of this PV, the same as
+    /// passing /dev/sdb:100-500 to lvcreate
+    pub fn on_pv_extents(mut self, device: &Path, first: u64, last: u64) -> Self {
+        self.pvs
+            .push(format!("{}:{}-{}", device.to_string_lossy(), first, last));
+        self
+    }
+
+    /// Create the LV in vg
+    pub fn create<'v>(self, vg: &'v mut VolumeGroup<'_>) -> LvmResult<LogicalVolume<'v, 'v>> {
+        let vg_name = vg.get_name()?;
+        let size = format!("{}b", self.size);
+        let mut args = vec!["lvcreate", "--yes", "--size", &size, "--name", &self.name];
+        if let Some(zero) = self.zero {
+            args.extend_from_slice(&["--zero", if zero { "y" } else { "n" }]);
+        }
+        if self.contiguous {
+            args.extend_from_slice(&["--contiguous", "y"]);
+        }
+        if let Some(ref policy) = self.alloc {
+            policy.prepare(vg.lvm)?;
+            args.extend_from_slice(&["--alloc", policy.as_arg()]);
+        }
+        args.push(&vg_name);
+        args.extend(self.pvs.iter().map(|pv| pv.as_str()));
+        vg.run_command(&args)?;
+
+        let mut lv = vg.lv_from_name(&self.name)?;
+        if self.discard_on_create {
+            lv.discard_all()?;
+        }
+        Ok(lv)
+    }
+}
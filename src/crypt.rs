@@ -0,0 +1,138 @@
+//! LUKS encryption on top of an LV through cryptsetup.  Keys are handed to
+//! cryptsetup on its stdin so they never show up in the process list.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use errno::Errno;
+
+use crate::{LogicalVolume, Lvm, LvmError, LvmResult};
+
+/// Where cryptsetup gets the key from
+#[derive(Clone, Copy, Debug)]
+pub enum LuksKey<'k> {
+    /// Used as is, a trailing newline is part of the passphrase
+    Passphrase(&'k [u8]),
+    KeyFile(&'k Path),
+}
+
+/// Settings for a new LUKS2 header.  Anything left as None uses
+/// cryptsetup's default.
+#[derive(Clone, Debug, Default)]
+pub struct Luks2Options {
+    /// ie: aes-xts-plain64
+    pub cipher: Option<String>,
+    /// Key size in bits
+    pub key_size: Option<u32>,
+    /// Key derivation function: argon2id, argon2i or pbkdf2
+    pub pbkdf: Option<String>,
+    /// Encryption sector size in bytes, 512 to 4096
+    pub sector_size: Option<u32>,
+    pub label: Option<String>,
+}
+
+/// Run cryptsetup with key, if any, on its stdin
+fn cryptsetup(args: &[&str], key: Option<LuksKey<'_>>) -> LvmResult<()> {
+    let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+    let mut stdin = None;
+    match key {
+        Some(LuksKey::Passphrase(passphrase)) => {
+            args.extend_from_slice(&["--key-file".to_string(), "-".to_string()]);
+            stdin = Some(passphrase);
+        }
+        Some(LuksKey::KeyFile(path)) => {
+            args.extend_from_slice(&["--key-file".to_string(), path.display().to_string()]);
+        }
+        None => {}
+    }
+    debug!("running cryptsetup {}", args.join(" "));
+    let mut child = Command::new("cryptsetup")
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Dropping stdin closes it so cryptsetup sees the end of the key
+    if let Some(mut pipe) = child.stdin.take() {
+        if let Some(passphrase) = stdin {
+            pipe.write_all(passphrase)?;
+        }
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(LvmError::new((
+            Errno(libc::EIO),
+            format!("cryptsetup {} failed: {}", args.join(" "), stderr.trim()),
+        )));
+    }
+    Ok(())
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Format an active LV as LUKS2 with key in its first key slot.  This
+    /// destroys whatever the LV held, mounted LVs are refused.
+    pub fn luks_format(&mut self, key: LuksKey<'_>, options: &Luks2Options) -> LvmResult<()> {
+        self.check_not_mounted()?;
+        let device = self.path()?.to_string_lossy().into_owned();
+        let key_size = options.key_size.map(|k| k.to_string());
+        let sector_size = options.sector_size.map(|s| s.to_string());
+        let mut args = vec!["luksFormat", "--batch-mode", "--type", "luks2"];
+        if let Some(ref cipher) = options.cipher {
+            args.extend_from_slice(&["--cipher", cipher]);
+        }
+        if let Some(ref key_size) = key_size {
+            args.extend_from_slice(&["--key-size", key_size]);
+        }
+        if let Some(ref pbkdf) = options.pbkdf {
+            args.extend_from_slice(&["--pbkdf", pbkdf]);
+        }
+        if let Some(ref sector_size) = sector_size {
+            args.extend_from_slice(&["--sector-size", sector_size]);
+        }
+        if let Some(ref label) = options.label {
+            args.extend_from_slice(&["--label", label]);
+        }
+        args.push(&device);
+        let params = [("args", format!("cryptsetup {}", args.join(" ")))];
+        self.vg
+            .lvm
+            .audited("cryptsetup_luks_format", &device, &params, || {
+                cryptsetup(&args, Some(key))
+            })
+    }
+
+    /// Unlock a LUKS formatted LV as /dev/mapper/name, which is returned
+    pub fn luks_open(&self, name: &str, key: LuksKey<'_>) -> LvmResult<PathBuf> {
+        let device = self.path()?.to_string_lossy().into_owned();
+        let args = ["open", "--type", "luks", device.as_str(), name];
+        let params = [
+            ("name", name.to_string()),
+            ("args", format!("cryptsetup {}", args.join(" "))),
+        ];
+        self.vg
+            .lvm
+            .audited("cryptsetup_open", &device, &params, || {
+                cryptsetup(&args, Some(key))
+            })?;
+        Ok(Path::new("/dev/mapper").join(name))
+    }
+
+    /// Check if the LV holds a LUKS header
+    pub fn is_luks(&self) -> LvmResult<bool> {
+        let device = self.path()?.to_string_lossy().into_owned();
+        Ok(cryptsetup(&["isLuks", &device], None).is_ok())
+    }
+}
+
+impl Lvm {
+    /// Lock the LUKS device luks_open unlocked as name.  It must be closed
+    /// before the LV under it can be deactivated.
+    pub fn luks_close(&self, name: &str) -> LvmResult<()> {
+        let params = [("args", format!("cryptsetup close {}", name))];
+        self.audited("cryptsetup_close", name, &params, || {
+            cryptsetup(&["close", name], None)
+        })
+    }
+}
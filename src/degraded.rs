@@ -0,0 +1,55 @@
+//! Recovery of VGs with missing PVs.  A VG missing PVs is partial, LVs
+//! with extents on the missing PVs can only be activated in partial mode
+//! and the missing PVs have to be removed before the VG can be changed
+//! normally again.
+
+use crate::{LogicalVolume, LvmResult, PhysicalVolume, VolumeGroup};
+
+impl<'a> VolumeGroup<'a> {
+    /// List the PVs of this VG whose devices are missing
+    pub fn missing_pvs(&self) -> LvmResult<Vec<PhysicalVolume<'_>>> {
+        let mut missing = vec![];
+        for pv in self.list_pvs()? {
+            if pv.is_missing()? {
+                missing.push(pv);
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Remove missing PVs from this VG, like vgreduce --removemissing.
+    /// Without force only PVs no LV uses are removed.  With force LVs with
+    /// extents on a missing PV are removed too, losing their data.
+    pub fn remove_missing_pvs(&mut self, force: bool) -> LvmResult<()> {
+        let vg_name = self.get_name()?;
+        let mut args = vec!["vgreduce", "--removemissing"];
+        if force {
+            args.push("--force");
+        }
+        args.push(&vg_name);
+        self.run_command(&args)?;
+        Ok(())
+    }
+
+    /// Activate an LV even though some of its PVs are missing.  Reads from
+    /// the missing parts fail, so this is for copying off what survived.
+    pub fn activate_lv_partial(&mut self, lv_name: &str) -> LvmResult<()> {
+        let lv_path = format!("{}/{}", self.get_name()?, lv_name);
+        self.run_command(&[
+            "lvchange",
+            "--activate",
+            "y",
+            "--activationmode",
+            "partial",
+            &lv_path,
+        ])?;
+        Ok(())
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Check if some of this LV's extents are on missing PVs
+    pub fn is_partial(&self) -> LvmResult<bool> {
+        Ok(self.try_attributes()?.chars().nth(8) == Some('p'))
+    }
+}
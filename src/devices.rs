@@ -0,0 +1,132 @@
+//! Block devices and what they're part of, read from sysfs, to pick
+//! devices for new PVs without taking one that is already a partitioned
+//! disk, an md RAID member or under a device mapper device.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use errno::Errno;
+
+use crate::{multipath, LvmError, LvmResult};
+
+/// A block device and its relations to other block devices
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockDevice {
+    /// ie: /dev/sda1
+    pub path: PathBuf,
+    /// Size in bytes
+    pub size: u64,
+    /// The disk a partition is on
+    pub partition_of: Option<PathBuf>,
+    /// Partitions on a disk
+    pub partitions: Vec<PathBuf>,
+    /// md RAID arrays the device is a member of, ie: /dev/md0
+    pub md_member_of: Vec<PathBuf>,
+    /// Device mapper devices built on the device, ie: /dev/mapper/vg-lv or
+    /// /dev/mapper/mpatha
+    pub dm_holders: Vec<PathBuf>,
+}
+
+impl BlockDevice {
+    /// Check if anything else uses the device, making it unfit for pvcreate
+    pub fn is_in_use(&self) -> bool {
+        !self.partitions.is_empty() || !self.md_member_of.is_empty() || !self.dm_holders.is_empty()
+    }
+}
+
+/// The /dev path of the block device at sysfs dir, device mapper devices
+/// by their /dev/mapper name
+fn dev_path(dir: &Path) -> PathBuf {
+    if let Ok(name) = fs::read_to_string(dir.join("dm/name")) {
+        return Path::new("/dev/mapper").join(name.trim());
+    }
+    let name = dir
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    Path::new("/dev").join(name)
+}
+
+fn read_device(dir: &Path) -> LvmResult<BlockDevice> {
+    let sectors = fs::read_to_string(dir.join("size"))?;
+    let is_partition = dir.join("partition").exists();
+    let mut device = BlockDevice {
+        path: dev_path(dir),
+        // sysfs counts 512 byte sectors whatever the device's sector size
+        size: sectors.trim().parse::<u64>().unwrap_or(0) * 512,
+        partition_of: if is_partition {
+            dir.parent().map(dev_path)
+        } else {
+            None
+        },
+        partitions: vec![],
+        md_member_of: vec![],
+        dm_holders: vec![],
+    };
+    if !is_partition {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?.path();
+            if entry.join("partition").exists() {
+                device.partitions.push(dev_path(&entry));
+            }
+        }
+        device.partitions.sort();
+    }
+    if let Ok(holders) = fs::read_dir(dir.join("holders")) {
+        for holder in holders {
+            let holder = fs::canonicalize(holder?.path())?;
+            if holder.join("md").exists() {
+                device.md_member_of.push(dev_path(&holder));
+            } else if holder.join("dm").exists() {
+                device.dm_holders.push(dev_path(&holder));
+            }
+        }
+    }
+    device.md_member_of.sort();
+    device.dm_holders.sort();
+    Ok(device)
+}
+
+/// Every block device the kernel knows about, partitions included
+pub fn block_devices() -> LvmResult<Vec<BlockDevice>> {
+    let mut devices = vec![];
+    for entry in fs::read_dir("/sys/class/block")? {
+        let dir = fs::canonicalize(entry?.path())?;
+        devices.push(read_device(&dir)?);
+    }
+    devices.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(devices)
+}
+
+/// The block device at path, ie: /dev/sda or a symlink to it
+pub fn block_device(path: &Path) -> LvmResult<BlockDevice> {
+    match multipath::sysfs_dir(path) {
+        Some(dir) => read_device(&dir),
+        None => Err(LvmError::new((
+            Errno(libc::ENOENT),
+            format!("{} is not a block device", path.display()),
+        ))),
+    }
+}
+
+/// Refuse a device for a new PV with EBUSY if it is a path of a multipath
+/// device or a member of an md RAID array
+pub(crate) fn check_pv_candidate(path: &Path) -> LvmResult<()> {
+    // Paths that aren't block devices are left for lvm to report
+    let device = match block_device(path) {
+        Ok(device) => device,
+        Err(_) => return Ok(()),
+    };
+    multipath::check_not_mpath_path(path)?;
+    if let Some(md) = device.md_member_of.first() {
+        return Err(LvmError::new((
+            Errno(libc::EBUSY),
+            format!(
+                "{} is a member of md RAID array {}",
+                path.display(),
+                md.display()
+            ),
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,188 @@
+//! Minimal device-mapper ioctl interface, see linux/dm-ioctl.h.  liblvm
+//! doesn't expose the dm status of LVs or the kernel's target list so these
+//! talk to /dev/mapper/control directly.
+
+use std::fs::OpenOptions;
+use std::io::Error as IOError;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+
+use errno::Errno;
+
+use crate::{LvmError, LvmResult};
+
+const DM_CONTROL: &str = "/dev/mapper/control";
+const DM_NAME_LEN: usize = 128;
+const DM_UUID_LEN: usize = 129;
+const DM_IOCTL_TYPE: libc::c_ulong = 0xfd;
+
+const DM_VERSION_CMD: libc::c_ulong = 0;
+const DM_DEV_STATUS_CMD: libc::c_ulong = 7;
+const DM_TABLE_STATUS_CMD: libc::c_ulong = 12;
+const DM_LIST_VERSIONS_CMD: libc::c_ulong = 13;
+
+/// Set by the kernel when the result didn't fit in the buffer
+const DM_BUFFER_FULL_FLAG: u32 = 1 << 8;
+
+const INITIAL_BUFFER_SIZE: usize = 16 * 1024;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct DmIoctl {
+    pub version: [u32; 3],
+    pub data_size: u32,
+    pub data_start: u32,
+    pub target_count: u32,
+    pub open_count: i32,
+    pub flags: u32,
+    pub event_nr: u32,
+    padding: u32,
+    pub dev: u64,
+    name: [u8; DM_NAME_LEN],
+    uuid: [u8; DM_UUID_LEN],
+    data: [u8; 7],
+}
+
+/// _IOWR(DM_IOCTL, cmd, struct dm_ioctl)
+fn request(cmd: libc::c_ulong) -> libc::c_ulong {
+    (3 << 30) | ((mem::size_of::<DmIoctl>() as libc::c_ulong) << 16) | (DM_IOCTL_TYPE << 8) | cmd
+}
+
+/// Issue a dm ioctl, optionally against a named device, and return the
+/// reply header along with the data the kernel wrote after it
+pub(crate) fn ioctl(
+    cmd: libc::c_ulong,
+    name: Option<&str>,
+    flags: u32,
+) -> LvmResult<(DmIoctl, Vec<u8>)> {
+    let control = OpenOptions::new().read(true).write(true).open(DM_CONTROL)?;
+    let header_size = mem::size_of::<DmIoctl>();
+    let mut header: DmIoctl = unsafe { mem::zeroed() };
+    header.version = [4, 0, 0];
+    header.data_start = header_size as u32;
+    header.flags = flags;
+    if let Some(name) = name {
+        if name.len() >= DM_NAME_LEN {
+            return Err(LvmError::new((
+                Errno(libc::ENAMETOOLONG),
+                format!("Device name too long: {}", name),
+            )));
+        }
+        header.name[..name.len()].copy_from_slice(name.as_bytes());
+    }
+
+    let mut size = INITIAL_BUFFER_SIZE;
+    loop {
+        header.data_size = size as u32;
+        // u64s keep the buffer aligned for the header
+        let mut buf = vec![0u64; size / 8];
+        let reply = unsafe {
+            ptr::write(buf.as_mut_ptr() as *mut DmIoctl, header);
+            if libc::ioctl(control.as_raw_fd(), request(cmd), buf.as_mut_ptr()) < 0 {
+                return Err(IOError::last_os_error().into());
+            }
+            ptr::read(buf.as_ptr() as *const DmIoctl)
+        };
+        if reply.flags & DM_BUFFER_FULL_FLAG != 0 {
+            size *= 2;
+            continue;
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const u8, size) };
+        let start = (reply.data_start as usize).min(size);
+        let end = (reply.data_size as usize).min(size).max(start);
+        return Ok((reply, bytes[start..end].to_vec()));
+    }
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    let mut b = [0u8; 8];
+    b.copy_from_slice(bytes);
+    Some(u64::from_ne_bytes(b))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Read a nul terminated string starting at offset
+pub(crate) fn read_cstr(data: &[u8], offset: usize) -> String {
+    let rest = data.get(offset..).unwrap_or(&[]);
+    let end = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+    String::from_utf8_lossy(&rest[..end]).into_owned()
+}
+
+/// Version of the device-mapper kernel driver
+pub(crate) fn driver_version() -> LvmResult<[u32; 3]> {
+    let (reply, _) = ioctl(DM_VERSION_CMD, None, 0)?;
+    Ok(reply.version)
+}
+
+/// Number of times a device is held open
+pub(crate) fn open_count(name: &str) -> LvmResult<i32> {
+    let (reply, _) = ioctl(DM_DEV_STATUS_CMD, Some(name), 0)?;
+    Ok(reply.open_count)
+}
+
+/// Targets registered with the kernel and their versions
+pub(crate) fn list_versions() -> LvmResult<Vec<(String, [u32; 3])>> {
+    let (_, data) = ioctl(DM_LIST_VERSIONS_CMD, None, 0)?;
+    let mut targets = vec![];
+    let mut offset = 0;
+    // struct dm_target_versions { u32 next; u32 version[3]; char name[]; }
+    while let (Some(next), Some(major), Some(minor), Some(patch)) = (
+        read_u32(&data, offset),
+        read_u32(&data, offset + 4),
+        read_u32(&data, offset + 8),
+        read_u32(&data, offset + 12),
+    ) {
+        targets.push((read_cstr(&data, offset + 16), [major, minor, patch]));
+        if next == 0 {
+            break;
+        }
+        offset += next as usize;
+    }
+    Ok(targets)
+}
+
+/// One target line of a device's table or status
+#[derive(Debug)]
+pub(crate) struct TargetLine {
+    /// Start of the segment in 512 byte sectors
+    pub start: u64,
+    /// Length of the segment in 512 byte sectors
+    pub length: u64,
+    pub target_type: String,
+    pub params: String,
+}
+
+/// Status lines of every target of an active device
+pub(crate) fn table_status(name: &str) -> LvmResult<Vec<TargetLine>> {
+    let (reply, data) = ioctl(DM_TABLE_STATUS_CMD, Some(name), 0)?;
+    let mut lines = vec![];
+    let mut offset = 0;
+    // struct dm_target_spec { u64 sector_start; u64 length; i32 status;
+    //   u32 next; char target_type[16]; } followed by the params string.
+    // next is relative to the start of the data.
+    for _ in 0..reply.target_count {
+        let (start, length, next) = match (
+            read_u64(&data, offset),
+            read_u64(&data, offset + 8),
+            read_u32(&data, offset + 20),
+        ) {
+            (Some(start), Some(length), Some(next)) => (start, length, next),
+            _ => break,
+        };
+        let target_type = read_cstr(&data[..(offset + 40).min(data.len())], offset + 24);
+        lines.push(TargetLine {
+            start,
+            length,
+            target_type,
+            params: read_cstr(&data, offset + 40),
+        });
+        offset = next as usize;
+    }
+    Ok(lines)
+}
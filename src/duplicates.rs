@@ -0,0 +1,75 @@
+//! VGs that share a name, ie: after attaching disks from another system.
+//! liblvm opens VGs by name so a VG whose name is taken can only be reached
+//! by its UUID, and the way out is renaming it by UUID.
+
+use errno::Errno;
+
+use crate::{cli, Lvm, LvmError, LvmResult, OpenMode, VolumeGroup};
+
+/// A VG name used by more than one VG
+#[derive(Debug)]
+pub struct DuplicateVg {
+    pub name: String,
+    /// UUIDs of every VG using the name
+    pub uuids: Vec<String>,
+}
+
+impl Lvm {
+    /// Name and UUID of every VG on the system, duplicates included
+    fn vg_names_and_uuids(&self) -> LvmResult<Vec<(String, String)>> {
+        Ok(cli::report(self, "vgs", &["vg_name", "vg_uuid"], "")?
+            .into_iter()
+            .filter(|row| row.len() >= 2)
+            .map(|row| (row[0].clone(), row[1].clone()))
+            .collect())
+    }
+
+    /// Find VG names shared by more than one VG
+    pub fn find_duplicate_vg_names(&self) -> LvmResult<Vec<DuplicateVg>> {
+        let mut vgs: Vec<DuplicateVg> = vec![];
+        for (name, uuid) in self.vg_names_and_uuids()? {
+            match vgs.iter_mut().find(|vg| vg.name == name) {
+                Some(vg) => vg.uuids.push(uuid),
+                None => vgs.push(DuplicateVg {
+                    name,
+                    uuids: vec![uuid],
+                }),
+            }
+        }
+        vgs.retain(|vg| vg.uuids.len() > 1);
+        Ok(vgs)
+    }
+
+    /// Open the VG with this UUID, in lvm's format ie:
+    /// Ngj5Ih-gH7Q-vxv4-2n6D-aO1Z-q9oV-hEBqAi.  VGs whose name is shared by
+    /// another VG can't be opened and fail with EEXIST, rename them with
+    /// vg_rename_by_uuid first.
+    pub fn vg_open_by_uuid(&self, uuid: &str, mode: &OpenMode) -> LvmResult<VolumeGroup<'_>> {
+        let vgs = self.vg_names_and_uuids()?;
+        let name = match vgs.iter().find(|(_, id)| id == uuid) {
+            Some((name, _)) => name,
+            None => {
+                return Err(LvmError::new((
+                    Errno(libc::ENOENT),
+                    format!("No VG with UUID {}", uuid),
+                )))
+            }
+        };
+        if vgs.iter().filter(|(n, _)| n == name).count() > 1 {
+            return Err(LvmError::new((
+                Errno(libc::EEXIST),
+                format!(
+                    "VG {} with UUID {} shares its name with another VG",
+                    name, uuid
+                ),
+            )));
+        }
+        self.vg_open(name, mode)
+    }
+
+    /// Rename the VG with this UUID, the way to resolve a duplicate name
+    pub fn vg_rename_by_uuid(&self, uuid: &str, new_name: &str) -> LvmResult<()> {
+        self.run_command(self.lock_retry.get(), &["vgrename", uuid, new_name])?;
+        Ok(())
+    }
+}
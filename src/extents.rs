@@ -0,0 +1,361 @@
+//! Where the free extents of a VG are.  A VG's free extent count says
+//! nothing about whether they're contiguous, so an allocation that needs
+//! one run of extents, ie: --contiguous, can fail with plenty of space
+//! free.  compact moves allocated extents to collect the free ones at
+//! the end of each PV.
+
+use std::path::{Path, PathBuf};
+
+use errno::Errno;
+
+use crate::{cli, LvmError, LvmResult, VolumeGroup};
+
+/// A run of physical extents on a PV
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExtentRange {
+    /// First physical extent
+    pub start: u64,
+    /// Number of extents
+    pub count: u64,
+}
+
+impl ExtentRange {
+    /// Last physical extent of the range
+    pub fn last(&self) -> u64 {
+        self.start + self.count.saturating_sub(1)
+    }
+}
+
+/// The free ranges of one PV, in order of their start
+#[derive(Debug)]
+pub struct PvFreeExtents {
+    pub pv: PathBuf,
+    pub ranges: Vec<ExtentRange>,
+}
+
+impl PvFreeExtents {
+    /// The longest free range of the PV
+    pub fn largest(&self) -> Option<ExtentRange> {
+        // max_by_key returns the last maximum, prefer the first
+        self.ranges
+            .iter()
+            .rev()
+            .max_by_key(|range| range.count)
+            .copied()
+    }
+
+    /// Total free extents on the PV
+    pub fn free_extents(&self) -> u64 {
+        self.ranges.iter().map(|range| range.count).sum()
+    }
+}
+
+fn parse_u64(field: &str) -> LvmResult<u64> {
+    field.parse().map_err(|_| {
+        LvmError::new((
+            Errno(libc::EINVAL),
+            format!("Invalid extent number: {}", field),
+        ))
+    })
+}
+
+/// A run of extents moved by compact
+#[derive(Clone, Debug)]
+pub struct ExtentMove {
+    pub pv: PathBuf,
+    /// LV the extents belong to
+    pub lv: String,
+    pub from: ExtentRange,
+    pub to: ExtentRange,
+}
+
+/// What compact should do
+#[derive(Clone, Debug, Default)]
+pub struct CompactOptions {
+    /// Only plan the moves, don't run them
+    pub dry_run: bool,
+    /// Only compact these PVs, all of the VG's PVs if empty
+    pub pvs: Vec<PathBuf>,
+}
+
+/// One row of pvs --segments
+struct PvSegment {
+    pv: PathBuf,
+    range: ExtentRange,
+    /// None for free space
+    lv: Option<String>,
+}
+
+/// Group segments, sorted by PV and start, into the free ranges of each PV
+fn free_ranges(segments: Vec<PvSegment>) -> Vec<PvFreeExtents> {
+    let mut pvs: Vec<PvFreeExtents> = vec![];
+    for segment in segments {
+        if pvs.last().map(|p| p.pv != segment.pv).unwrap_or(true) {
+            pvs.push(PvFreeExtents {
+                pv: segment.pv.clone(),
+                ranges: vec![],
+            });
+        }
+        if segment.lv.is_none() {
+            if let Some(pv) = pvs.last_mut() {
+                pv.ranges.push(segment.range);
+            }
+        }
+    }
+    pvs
+}
+
+/// The moves compact makes for segments, sorted by PV and start, on the
+/// PVs in only, or every PV if it's empty
+fn plan_moves(segments: &[PvSegment], only: &[PathBuf]) -> Vec<ExtentMove> {
+    let mut moves = vec![];
+    let mut pvs: Vec<&PathBuf> = segments.iter().map(|s| &s.pv).collect();
+    pvs.dedup();
+    for pv in pvs {
+        if !only.is_empty() && !only.contains(pv) {
+            continue;
+        }
+        let on_pv = || segments.iter().filter(move |s| &s.pv == pv);
+        let mut free: Vec<ExtentRange> = on_pv()
+            .filter(|s| s.lv.is_none())
+            .map(|s| s.range)
+            .collect();
+        let allocated = on_pv().filter_map(|s| s.lv.as_ref().map(|lv| (lv, s.range)));
+        for (lv, from) in allocated.rev() {
+            let gap = free
+                .iter_mut()
+                .find(|gap| gap.start < from.start && gap.count >= from.count);
+            if let Some(gap) = gap {
+                let to = ExtentRange {
+                    start: gap.start,
+                    count: from.count,
+                };
+                gap.start += from.count;
+                gap.count -= from.count;
+                moves.push(ExtentMove {
+                    pv: pv.clone(),
+                    lv: lv.clone(),
+                    from,
+                    to,
+                });
+            }
+        }
+    }
+    moves
+}
+
+/// The smallest free range holding count extents, the first of them if
+/// several are as small
+fn best_fit(pvs: &[PvFreeExtents], count: u64) -> Option<(PathBuf, ExtentRange)> {
+    let mut best: Option<(PathBuf, ExtentRange)> = None;
+    for pv in pvs {
+        for range in &pv.ranges {
+            let better = match &best {
+                Some((_, current)) => range.count < current.count,
+                None => true,
+            };
+            if range.count >= count && better {
+                best = Some((pv.pv.clone(), *range));
+            }
+        }
+    }
+    best
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Every allocated and free segment of the VG's PVs
+    fn pv_segments(&self) -> LvmResult<Vec<PvSegment>> {
+        let vg_name = self.get_name()?;
+        let rows = cli::report_segments(
+            self.lvm,
+            "pvs",
+            &["pv_name", "vg_name", "pvseg_start", "pvseg_size", "lv_name"],
+            "",
+        )?;
+        let mut segments = vec![];
+        for row in rows {
+            if row.len() < 5 || row[1] != vg_name {
+                continue;
+            }
+            segments.push(PvSegment {
+                pv: PathBuf::from(&row[0]),
+                range: ExtentRange {
+                    start: parse_u64(&row[2])?,
+                    count: parse_u64(&row[3])?,
+                },
+                lv: if row[4].is_empty() {
+                    None
+                } else {
+                    Some(row[4].clone())
+                },
+            });
+        }
+        segments.sort_by(|a, b| a.pv.cmp(&b.pv).then(a.range.start.cmp(&b.range.start)));
+        Ok(segments)
+    }
+
+    /// Names of the LVs with extents allocated on device
+    pub fn lvs_on_pv(&self, device: &Path) -> LvmResult<Vec<String>> {
+        let mut lvs: Vec<String> = vec![];
+        for segment in self.pv_segments()? {
+            if segment.pv != device {
+                continue;
+            }
+            if let Some(lv) = segment.lv {
+                if !lvs.contains(&lv) {
+                    lvs.push(lv);
+                }
+            }
+        }
+        Ok(lvs)
+    }
+
+    /// Get the free extent ranges of every PV in the VG
+    pub fn free_extent_ranges(&self) -> LvmResult<Vec<PvFreeExtents>> {
+        Ok(free_ranges(self.pv_segments()?))
+    }
+
+    /// Move allocated extents into free gaps nearer the start of their PV
+    /// so the free space collects at the end, ie: before shrinking a PV.
+    /// Segments are moved whole, starting from the end of the PV, into the
+    /// first gap they fit.  Returns the moves, which are only planned with
+    /// options.dry_run.  Moves run with pvmove one at a time; if one fails
+    /// the ones before it stay done.
+    pub fn compact(&mut self, options: &CompactOptions) -> LvmResult<Vec<ExtentMove>> {
+        let moves = plan_moves(&self.pv_segments()?, &options.pvs);
+        if options.dry_run {
+            return Ok(moves);
+        }
+
+        for m in &moves {
+            let pv = m.pv.to_string_lossy();
+            let from = format!("{}:{}-{}", pv, m.from.start, m.from.last());
+            let to = format!("{}:{}-{}", pv, m.to.start, m.to.last());
+            self.run_command(&["pvmove", "--alloc", "anywhere", &from, &to])?;
+        }
+        Ok(moves)
+    }
+
+    /// Find where count contiguous extents could be allocated, choosing
+    /// the smallest free range that fits to leave larger ones intact
+    pub fn find_contiguous_extents(&self, count: u64) -> LvmResult<Option<(PathBuf, ExtentRange)>> {
+        Ok(best_fit(&self.free_extent_ranges()?, count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, count: u64) -> ExtentRange {
+        ExtentRange { start, count }
+    }
+
+    /// Segments of one PV given as (start, count, lv), "" for free space
+    fn segments(pv: &str, layout: &[(u64, u64, &str)]) -> Vec<PvSegment> {
+        layout
+            .iter()
+            .map(|&(start, count, lv)| PvSegment {
+                pv: PathBuf::from(pv),
+                range: range(start, count),
+                lv: if lv.is_empty() {
+                    None
+                } else {
+                    Some(lv.to_string())
+                },
+            })
+            .collect()
+    }
+
+    fn moves(planned: &[ExtentMove]) -> Vec<(&str, ExtentRange, ExtentRange)> {
+        planned
+            .iter()
+            .map(|m| (m.lv.as_str(), m.from, m.to))
+            .collect()
+    }
+
+    #[test]
+    fn moves_segments_into_earlier_gaps() {
+        let layout = segments(
+            "/dev/sda",
+            &[
+                (0, 10, "a"),
+                (10, 5, ""),
+                (15, 10, "b"),
+                (25, 3, "c"),
+                (28, 2, "d"),
+                (30, 70, ""),
+            ],
+        );
+        // d and c fit the gap, b doesn't and nothing fits after it's full
+        assert_eq!(
+            moves(&plan_moves(&layout, &[])),
+            vec![
+                ("d", range(28, 2), range(10, 2)),
+                ("c", range(25, 3), range(12, 3))
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_compact_pvs_alone() {
+        let layout = segments("/dev/sda", &[(0, 10, "a"), (10, 90, "")]);
+        assert!(plan_moves(&layout, &[]).is_empty());
+        // A segment never moves towards the end of the PV
+        let layout = segments("/dev/sda", &[(0, 10, ""), (10, 90, "a")]);
+        assert!(plan_moves(&layout, &[]).is_empty());
+    }
+
+    #[test]
+    fn only_plans_the_pvs_asked_for() {
+        let mut layout = segments("/dev/sda", &[(0, 5, ""), (5, 5, "a")]);
+        layout.extend(segments("/dev/sdb", &[(0, 5, ""), (5, 5, "b")]));
+        let planned = plan_moves(&layout, &[PathBuf::from("/dev/sdb")]);
+        assert_eq!(moves(&planned), vec![("b", range(5, 5), range(0, 5))]);
+        assert_eq!(planned[0].pv, PathBuf::from("/dev/sdb"));
+        assert_eq!(plan_moves(&layout, &[]).len(), 2);
+    }
+
+    #[test]
+    fn groups_free_ranges_by_pv() {
+        let mut layout = segments("/dev/sda", &[(0, 5, ""), (5, 5, "a"), (10, 3, "")]);
+        layout.extend(segments("/dev/sdb", &[(0, 10, "b")]));
+        let pvs = free_ranges(layout);
+        assert_eq!(pvs.len(), 2);
+        assert_eq!(pvs[0].ranges, vec![range(0, 5), range(10, 3)]);
+        assert_eq!(pvs[0].free_extents(), 8);
+        assert_eq!(pvs[0].largest(), Some(range(0, 5)));
+        assert!(pvs[1].ranges.is_empty());
+        assert_eq!(pvs[1].largest(), None);
+    }
+
+    #[test]
+    fn best_fit_picks_the_smallest_range_that_fits() {
+        let mut layout = segments("/dev/sda", &[(0, 50, ""), (50, 10, "a"), (60, 8, "")]);
+        layout.extend(segments(
+            "/dev/sdb",
+            &[(0, 6, ""), (6, 4, "b"), (10, 8, "")],
+        ));
+        let pvs = free_ranges(layout);
+        assert_eq!(
+            best_fit(&pvs, 7),
+            Some((PathBuf::from("/dev/sda"), range(60, 8)))
+        );
+        assert_eq!(
+            best_fit(&pvs, 6),
+            Some((PathBuf::from("/dev/sdb"), range(0, 6)))
+        );
+        assert_eq!(
+            best_fit(&pvs, 9),
+            Some((PathBuf::from("/dev/sda"), range(0, 50)))
+        );
+        assert_eq!(best_fit(&pvs, 51), None);
+    }
+
+    #[test]
+    fn last_extent() {
+        assert_eq!(range(10, 5).last(), 14);
+        assert_eq!(range(10, 1).last(), 10);
+        assert_eq!(range(10, 0).last(), 10);
+    }
+}
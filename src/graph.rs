@@ -0,0 +1,324 @@
+//! How the LVs of a VG depend on each other: snapshots on their origins,
+//! thin LVs on their pool, cached LVs on their cache pool and pools on
+//! their hidden data and metadata sub-LVs.  Tools use it to work out what
+//! has to be removed or deactivated before something else can be.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{cli, LogicalVolume, LvmError, LvmResult, VolumeGroup};
+
+/// Why one LV depends on another
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DependencyKind {
+    /// A snapshot on the LV it was taken of
+    Origin,
+    /// A thin LV on its thin pool
+    ThinPool,
+    /// A cached LV on its cache pool or cache volume
+    CachePool,
+    /// A pool on its data sub-LV
+    Data,
+    /// A pool on its metadata sub-LV
+    Metadata,
+}
+
+/// dependent needs dependency to exist
+#[derive(Debug)]
+pub struct Dependency {
+    pub dependent: String,
+    pub dependency: String,
+    pub kind: DependencyKind,
+}
+
+/// An LV in the graph
+#[derive(Debug)]
+pub struct LvNode {
+    pub name: String,
+    /// Internal LVs like pool sub-LVs that are managed through their parent
+    pub hidden: bool,
+}
+
+#[derive(Debug)]
+pub struct LvGraph {
+    pub lvs: Vec<LvNode>,
+    pub dependencies: Vec<Dependency>,
+}
+
+/// lvs reports hidden LVs as [name]
+fn strip_hidden(name: &str) -> (String, bool) {
+    if name.starts_with('[') && name.ends_with(']') && name.len() >= 2 {
+        (name[1..name.len() - 1].to_string(), true)
+    } else {
+        (name.to_string(), false)
+    }
+}
+
+impl LvGraph {
+    /// LVs that depend directly on name
+    pub fn dependents(&self, name: &str) -> Vec<&Dependency> {
+        self.dependencies
+            .iter()
+            .filter(|d| d.dependency == name)
+            .collect()
+    }
+
+    /// LVs that name depends on directly
+    pub fn dependencies_of(&self, name: &str) -> Vec<&Dependency> {
+        self.dependencies
+            .iter()
+            .filter(|d| d.dependent == name)
+            .collect()
+    }
+
+    /// Every LV that depends on name directly or indirectly
+    pub fn all_dependents(&self, name: &str) -> Vec<String> {
+        let mut found = vec![];
+        let mut seen = HashSet::new();
+        let mut pending = vec![name.to_string()];
+        while let Some(current) = pending.pop() {
+            for dep in self.dependents(&current) {
+                if seen.insert(dep.dependent.clone()) {
+                    found.push(dep.dependent.clone());
+                    pending.push(dep.dependent.clone());
+                }
+            }
+        }
+        found
+    }
+
+    /// Order the LVs so every LV comes before the LVs it depends on, the
+    /// order to remove or deactivate them in.  LVs caught in a cycle, which
+    /// lvm shouldn't produce, are appended at the end.
+    pub fn removal_order(&self) -> Vec<String> {
+        let mut remaining: HashMap<&str, usize> =
+            self.lvs.iter().map(|lv| (lv.name.as_str(), 0)).collect();
+        for dep in &self.dependencies {
+            if let Some(count) = remaining.get_mut(dep.dependency.as_str()) {
+                *count += 1;
+            }
+        }
+        let mut order = vec![];
+        let mut ready: Vec<&str> = self
+            .lvs
+            .iter()
+            .map(|lv| lv.name.as_str())
+            .filter(|name| remaining.get(name) == Some(&0))
+            .collect();
+        while let Some(name) = ready.pop() {
+            remaining.remove(name);
+            order.push(name.to_string());
+            for dep in self.dependencies_of(name) {
+                if let Some(count) = remaining.get_mut(dep.dependency.as_str()) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push(dep.dependency.as_str());
+                    }
+                }
+            }
+        }
+        for lv in &self.lvs {
+            if remaining.contains_key(lv.name.as_str()) {
+                order.push(lv.name.clone());
+            }
+        }
+        order
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Build the dependency graph of every LV in this VG, including hidden
+    /// sub-LVs
+    pub fn lv_dependency_graph(&self) -> LvmResult<LvGraph> {
+        let rows = cli::report_all(
+            self.lvm,
+            "lvs",
+            &[
+                "lv_name",
+                "segtype",
+                "origin",
+                "pool_lv",
+                "data_lv",
+                "metadata_lv",
+            ],
+            &self.get_name()?,
+        )?;
+        let mut graph = LvGraph {
+            lvs: vec![],
+            dependencies: vec![],
+        };
+        let mut seen = HashSet::new();
+        for row in rows {
+            if row.len() < 6 {
+                continue;
+            }
+            let (name, hidden) = strip_hidden(&row[0]);
+            // LVs with several segments are reported once per segment
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            let pool_kind = if row[1] == "thin" {
+                DependencyKind::ThinPool
+            } else {
+                DependencyKind::CachePool
+            };
+            let related = [
+                (&row[2], DependencyKind::Origin),
+                (&row[3], pool_kind),
+                (&row[4], DependencyKind::Data),
+                (&row[5], DependencyKind::Metadata),
+            ];
+            for (other, kind) in related.iter() {
+                if other.is_empty() {
+                    continue;
+                }
+                graph.dependencies.push(Dependency {
+                    dependent: name.clone(),
+                    dependency: strip_hidden(other).0,
+                    kind: *kind,
+                });
+            }
+            graph.lvs.push(LvNode { name, hidden });
+        }
+        Ok(graph)
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Deactivate and remove an LV, first checking nothing depends on it.
+    /// If snapshots, thin LVs or cached LVs depend on this LV the removal is
+    /// refused with LvmError::HasDependents, unless force is set in which
+    /// case the dependents are deactivated and removed first, dependents of
+    /// dependents before them.
+    pub fn remove_safely(mut self, force: bool) -> LvmResult<()> {
+        let name = self.try_name()?;
+        let graph = self.vg.lv_dependency_graph()?;
+        let dependents = graph.all_dependents(&name);
+        if !dependents.is_empty() {
+            if !force {
+                return Err(LvmError::HasDependents(
+                    format!("{} has dependents: {}", name, dependents.join(", ")),
+                    dependents,
+                ));
+            }
+            for dependent in graph.removal_order() {
+                if !dependents.contains(&dependent) {
+                    continue;
+                }
+                let mut lv = self.vg.lv_from_name(&dependent)?;
+                if lv.try_is_active()? {
+                    lv.deactivate()?;
+                }
+                lv.remove()?;
+            }
+        }
+        if self.try_is_active()? {
+            self.deactivate()?;
+        }
+        self.remove()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(lvs: &[&str], dependencies: &[(&str, &str, DependencyKind)]) -> LvGraph {
+        LvGraph {
+            lvs: lvs
+                .iter()
+                .map(|name| {
+                    let (name, hidden) = strip_hidden(name);
+                    LvNode { name, hidden }
+                })
+                .collect(),
+            dependencies: dependencies
+                .iter()
+                .map(|&(dependent, dependency, kind)| Dependency {
+                    dependent: dependent.to_string(),
+                    dependency: dependency.to_string(),
+                    kind,
+                })
+                .collect(),
+        }
+    }
+
+    fn thin_vg() -> LvGraph {
+        graph(
+            &[
+                "pool",
+                "[pool_tdata]",
+                "[pool_tmeta]",
+                "thin",
+                "snap",
+                "snap2",
+                "plain",
+            ],
+            &[
+                ("pool", "pool_tdata", DependencyKind::Data),
+                ("pool", "pool_tmeta", DependencyKind::Metadata),
+                ("thin", "pool", DependencyKind::ThinPool),
+                ("snap", "thin", DependencyKind::Origin),
+                ("snap2", "snap", DependencyKind::Origin),
+            ],
+        )
+    }
+
+    fn position(order: &[String], name: &str) -> usize {
+        order.iter().position(|lv| lv == name).unwrap()
+    }
+
+    #[test]
+    fn hidden_names() {
+        let g = thin_vg();
+        assert_eq!(g.lvs[1].name, "pool_tdata");
+        assert!(g.lvs[1].hidden);
+        assert!(!g.lvs[0].hidden);
+        assert_eq!(strip_hidden("[]"), (String::new(), true));
+        assert_eq!(strip_hidden("["), ("[".to_string(), false));
+    }
+
+    #[test]
+    fn all_dependents() {
+        let g = thin_vg();
+        let mut pool = g.all_dependents("pool");
+        pool.sort();
+        assert_eq!(pool, vec!["snap", "snap2", "thin"]);
+        let mut data = g.all_dependents("pool_tdata");
+        data.sort();
+        assert_eq!(data, vec!["pool", "snap", "snap2", "thin"]);
+        assert_eq!(g.all_dependents("snap"), vec!["snap2"]);
+        assert!(g.all_dependents("snap2").is_empty());
+        assert!(g.all_dependents("plain").is_empty());
+        assert!(g.all_dependents("missing").is_empty());
+    }
+
+    #[test]
+    fn removal_order() {
+        let g = thin_vg();
+        let order = g.removal_order();
+        assert_eq!(order.len(), g.lvs.len());
+        for dep in &g.dependencies {
+            assert!(
+                position(&order, &dep.dependent) < position(&order, &dep.dependency),
+                "{} must come before {} in {:?}",
+                dep.dependent,
+                dep.dependency,
+                order
+            );
+        }
+    }
+
+    #[test]
+    fn removal_order_with_a_cycle() {
+        let g = graph(
+            &["a", "b", "c"],
+            &[
+                ("a", "b", DependencyKind::Origin),
+                ("b", "a", DependencyKind::Origin),
+            ],
+        );
+        let order = g.removal_order();
+        assert_eq!(order[0], "c");
+        assert_eq!(&order[1..], &["a".to_string(), "b".to_string()]);
+    }
+}
@@ -0,0 +1,238 @@
+//! Growing an LV and its filesystem into all the space the disks under it
+//! have, the first boot expansion of cloud images whose disk was made
+//! bigger than the image.  Every step runs through the command line tools:
+//! growpart from cloud-utils for partitions, then pvresize, lvextend and
+//! the filesystem's own grow tool.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use errno::Errno;
+
+use crate::{cli, mount, Lvm, LvmError, LvmResult};
+
+/// How a step of grow_lv_to_fill went
+#[derive(Debug)]
+pub enum StepResult {
+    Done,
+    /// Nothing to do, and why
+    Skipped(String),
+    /// The step failed and no later step was run
+    Failed(LvmError),
+}
+
+/// One step of grow_lv_to_fill, ie: "pvresize /dev/sda2"
+#[derive(Debug)]
+pub struct GrowStep {
+    pub step: String,
+    pub result: StepResult,
+}
+
+/// What grow_lv_to_fill did
+#[derive(Debug, Default)]
+pub struct GrowReport {
+    pub steps: Vec<GrowStep>,
+    /// Size of the LV in bytes before and after
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+impl GrowReport {
+    /// Check if no step failed
+    pub fn is_ok(&self) -> bool {
+        !self
+            .steps
+            .iter()
+            .any(|s| matches!(s.result, StepResult::Failed(_)))
+    }
+
+    /// Record a step, returning false if it failed
+    fn record(&mut self, step: String, result: LvmResult<StepResult>) -> bool {
+        let result = result.unwrap_or_else(StepResult::Failed);
+        let ok = !matches!(result, StepResult::Failed(_));
+        self.steps.push(GrowStep { step, result });
+        ok
+    }
+}
+
+/// The disk and partition number of a partition, None for whole disks
+fn partition_of(device: &Path) -> LvmResult<Option<(String, String)>> {
+    let resolved = fs::canonicalize(device)?;
+    let name = match resolved.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return Ok(None),
+    };
+    let sysfs = fs::canonicalize(Path::new("/sys/class/block").join(&name))?;
+    let number = match fs::read_to_string(sysfs.join("partition")) {
+        Ok(number) => number.trim().to_string(),
+        Err(_) => return Ok(None),
+    };
+    let disk = match sysfs.parent().and_then(|p| p.file_name()) {
+        Some(disk) => format!("/dev/{}", disk.to_string_lossy()),
+        None => return Ok(None),
+    };
+    Ok(Some((disk, number)))
+}
+
+/// Grow a partition to the end of the free space after it
+fn growpart(disk: &str, number: &str) -> LvmResult<StepResult> {
+    debug!("running growpart {} {}", disk, number);
+    let output = Command::new("growpart").args([disk, number]).output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // growpart exits with 1 and says NOCHANGE when there's no room
+    if stdout.starts_with("NOCHANGE") {
+        return Ok(StepResult::Skipped(stdout.trim().to_string()));
+    }
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(LvmError::new((
+            Errno(libc::EIO),
+            format!("growpart {} {} failed: {}", disk, number, stderr.trim()),
+        )));
+    }
+    Ok(StepResult::Done)
+}
+
+/// Read one row of a report, failing with ENOENT if there is none
+fn report_row(lvm: &Lvm, command: &str, fields: &[&str], target: &str) -> LvmResult<Vec<String>> {
+    cli::report(lvm, command, fields, target)?
+        .into_iter()
+        .next()
+        .filter(|row| row.len() == fields.len())
+        .ok_or_else(|| LvmError::new((Errno(libc::ENOENT), format!("{} not found", target))))
+}
+
+impl Lvm {
+    fn lv_size(&self, lv: &str) -> LvmResult<u64> {
+        let row = report_row(self, "lvs", &["lv_size"], lv)?;
+        row[0].parse().map_err(|_| {
+            LvmError::new((
+                Errno(libc::EINVAL),
+                format!("Invalid size of {}: {}", lv, row[0]),
+            ))
+        })
+    }
+
+    /// Grow the filesystem on device, mounted at mount_point if it is
+    fn grow_filesystem(&self, device: &str, mount_point: Option<&Path>) -> LvmResult<StepResult> {
+        let fs_type = match Command::new("blkid")
+            .args(["-p", "-o", "value", "-s", "TYPE", device])
+            .output()
+        {
+            Ok(output) if output.status.success() => {
+                String::from_utf8_lossy(&output.stdout).trim().to_string()
+            }
+            _ => return Ok(StepResult::Skipped("no filesystem found".to_string())),
+        };
+        let mount_point = mount_point.map(|m| m.to_string_lossy().into_owned());
+        let (program, args): (&str, Vec<&str>) = match (fs_type.as_str(), mount_point.as_deref()) {
+            ("ext2", _) | ("ext3", _) | ("ext4", _) => ("resize2fs", vec![device]),
+            ("xfs", Some(mount_point)) => ("xfs_growfs", vec![mount_point]),
+            ("btrfs", Some(mount_point)) => {
+                ("btrfs", vec!["filesystem", "resize", "max", mount_point])
+            }
+            ("xfs", None) | ("btrfs", None) => {
+                return Ok(StepResult::Skipped(format!(
+                    "{} can only be grown while mounted",
+                    fs_type
+                )))
+            }
+            (other, _) => {
+                return Ok(StepResult::Skipped(format!(
+                    "don't know how to grow {}",
+                    other
+                )))
+            }
+        };
+        let params = [
+            ("type", fs_type.clone()),
+            ("args", format!("{} {}", program, args.join(" "))),
+        ];
+        self.audited("grow_filesystem", device, &params, || {
+            cli::run_program(program, &args)
+        })?;
+        Ok(StepResult::Done)
+    }
+
+    /// Grow an LV, given as vgname/lvname or its /dev path, and its
+    /// filesystem into all the space available, growing partitions and PVs
+    /// of its VG first.  Stops at the first step that fails, see the
+    /// report for what was done.  No VG handle may be open.  In test mode
+    /// the partitions and the filesystem are left alone and their steps
+    /// reported as skipped.
+    pub fn grow_lv_to_fill(&self, lv_path: &str) -> LvmResult<GrowReport> {
+        let lv = lv_path.trim_start_matches("/dev/");
+        let (vg_name, lv_name) = match lv.split_once('/') {
+            Some(names) => names,
+            None => {
+                return Err(LvmError::new((
+                    Errno(libc::EINVAL),
+                    format!("{} is not vgname/lvname or /dev/vgname/lvname", lv_path),
+                )))
+            }
+        };
+        let lv = format!("{}/{}", vg_name, lv_name);
+        let mut report = GrowReport {
+            old_size: self.lv_size(&lv)?,
+            ..GrowReport::default()
+        };
+        report.new_size = report.old_size;
+
+        let pvs: Vec<String> = cli::report(self, "pvs", &["pv_name", "vg_name"], "")?
+            .into_iter()
+            .filter(|row| row.len() == 2 && row[1] == vg_name)
+            .map(|row| row[0].clone())
+            .collect();
+        for pv in &pvs {
+            if let Some((disk, number)) = partition_of(Path::new(pv))? {
+                let step = format!("growpart {} {}", disk, number);
+                let result = if self.is_test_mode() {
+                    Ok(StepResult::Skipped("test mode".to_string()))
+                } else {
+                    let params = [("args", step.clone())];
+                    self.audited("growpart", pv, &params, || growpart(&disk, &number))
+                };
+                if !report.record(step, result) {
+                    return Ok(report);
+                }
+            }
+            let result = self
+                .run_command(self.lock_retry.get(), &["pvresize", pv])
+                .map(|_| StepResult::Done);
+            if !report.record(format!("pvresize {}", pv), result) {
+                return Ok(report);
+            }
+        }
+
+        let free = report_row(self, "vgs", &["vg_free_count"], vg_name)?;
+        let step = format!("lvextend -l +100%FREE {}", lv);
+        let result = if free[0] == "0" {
+            Ok(StepResult::Skipped(format!(
+                "{} has no free space",
+                vg_name
+            )))
+        } else {
+            self.run_command(self.lock_retry.get(), &["lvextend", "-l", "+100%FREE", &lv])
+                .map(|_| StepResult::Done)
+        };
+        if !report.record(step, result) {
+            return Ok(report);
+        }
+        report.new_size = self.lv_size(&lv)?;
+
+        let numbers = report_row(self, "lvs", &["lv_kernel_major", "lv_kernel_minor"], &lv)?;
+        let mount_point = match (numbers[0].parse(), numbers[1].parse()) {
+            (Ok(major), Ok(minor)) => mount::mount_points(major, minor)?.into_iter().next(),
+            _ => None,
+        };
+        let device = format!("/dev/{}", lv);
+        let result = if self.is_test_mode() {
+            Ok(StepResult::Skipped("test mode".to_string()))
+        } else {
+            self.grow_filesystem(&device, mount_point.as_deref())
+        };
+        report.record(format!("grow filesystem on {}", device), result);
+        Ok(report)
+    }
+}
@@ -0,0 +1,108 @@
+//! One health status for a PV combining lvm's view of it, missing or
+//! duplicated, with the SMART status of the disk underneath as reported by
+//! smartctl.  PVs on partitions are checked through their whole disk.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{LvmResult, PhysicalVolume};
+
+/// The health of a PV, the worst problem found
+#[derive(Clone, Debug, PartialEq)]
+pub enum PvHealth {
+    Healthy,
+    /// lvm couldn't find the PV's device
+    Missing,
+    /// The PV was found on more than one device, ie: multipath without
+    /// multipathd, and lvm is using one of them
+    Duplicate,
+    /// SMART predicts the disk will fail, with smartctl's verdict
+    Failing(String),
+    /// SMART attributes are past their thresholds but the disk hasn't
+    /// failed its self assessment
+    Degraded(String),
+    /// lvm is happy with the PV but SMART status couldn't be read, ie:
+    /// smartctl isn't installed or the device is virtual
+    Unknown(String),
+}
+
+/// The whole disk a partition belongs to, or the device itself
+fn whole_disk(device: &Path) -> PathBuf {
+    let resolved = fs::canonicalize(device).unwrap_or_else(|_| device.to_path_buf());
+    let name = match resolved.file_name() {
+        Some(name) => name.to_os_string(),
+        None => return resolved,
+    };
+    let sysfs = Path::new("/sys/class/block").join(&name);
+    if !sysfs.join("partition").exists() {
+        return resolved;
+    }
+    // /sys/class/block/sda1 links into the parent disk's directory
+    match fs::canonicalize(&sysfs).ok().and_then(|p| {
+        p.parent()
+            .and_then(|p| p.file_name())
+            .map(|n| n.to_os_string())
+    }) {
+        Some(disk) => Path::new("/dev").join(disk),
+        None => resolved,
+    }
+}
+
+/// Run smartctl -H on device
+fn smart_health(device: &Path) -> PvHealth {
+    let disk = whole_disk(device);
+    debug!("running smartctl -H {}", disk.display());
+    let output = match Command::new("smartctl").arg("-H").arg(&disk).output() {
+        Ok(output) => output,
+        Err(e) => return PvHealth::Unknown(format!("Failed to run smartctl: {}", e)),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // ATA disks say "self-assessment test result: PASSED", SCSI and NVMe
+    // "SMART Health Status: OK"
+    let verdict = stdout
+        .lines()
+        .find(|l| l.contains("self-assessment test result") || l.contains("Health Status"))
+        .and_then(|l| l.split_once(':'))
+        .map(|(_, v)| v.trim().to_string());
+    // smartctl's exit status is a bit mask, bit 3 means the disk is failing
+    // and bits 4 and 5 that attributes are or were past their thresholds
+    let status = output.status.code().unwrap_or(-1);
+    if status < 0 || status & 0b111 != 0 || verdict.is_none() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stdout
+            .lines()
+            .chain(stderr.lines())
+            .rfind(|l| !l.trim().is_empty())
+            .unwrap_or("no output")
+            .to_string();
+        return PvHealth::Unknown(format!(
+            "Can't read SMART status of {}: {}",
+            disk.display(),
+            reason
+        ));
+    }
+    let verdict = verdict.unwrap_or_default();
+    if status & 0b1000 != 0 {
+        PvHealth::Failing(verdict)
+    } else if status & 0b11_0000 != 0 {
+        PvHealth::Degraded(verdict)
+    } else {
+        PvHealth::Healthy
+    }
+}
+
+impl<'a> PhysicalVolume<'a> {
+    /// Get the health of the PV.  A missing or duplicated PV is reported
+    /// without looking at SMART.  Reading SMART status needs root.
+    pub fn health(&self) -> LvmResult<PvHealth> {
+        let attr: Vec<char> = self.get_string_property("pv_attr")?.chars().collect();
+        if attr.get(2) == Some(&'m') {
+            return Ok(PvHealth::Missing);
+        }
+        if attr.first() == Some(&'d') {
+            return Ok(PvHealth::Duplicate);
+        }
+        Ok(smart_health(Path::new(&self.try_name()?)))
+    }
+}
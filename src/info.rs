@@ -0,0 +1,168 @@
+//! Owned copies of what describes a VG, LV or PV.  Unlike the handles they
+//! don't borrow the Lvm handle, so they can be kept in long lived structs
+//! or sent to other threads, and turned back into a handle when needed.
+
+use errno::Errno;
+
+use crate::{LogicalVolume, Lvm, LvmError, LvmResult, OpenMode, PhysicalVolume, VolumeGroup};
+
+/// A VG as it was when to_info was called
+#[derive(Clone, Debug, PartialEq)]
+pub struct VgInfo {
+    pub name: String,
+    pub uuid: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Unallocated space in bytes
+    pub free_size: u64,
+    /// Extent size in bytes
+    pub extent_size: u64,
+    pub extent_count: u64,
+    pub free_extents: u64,
+    pub pv_count: u64,
+    /// The metadata sequence number, it changes with every metadata change
+    pub seq_number: u64,
+    pub tags: Vec<String>,
+}
+
+/// An LV as it was when to_info was called
+#[derive(Clone, Debug, PartialEq)]
+pub struct LvInfo {
+    pub vg_name: String,
+    pub name: String,
+    /// Used to find the LV again, even if it was renamed
+    pub uuid: String,
+    /// Size in bytes
+    pub size: u64,
+    /// The lvs attribute string, ie: -wi-a-----
+    pub attributes: String,
+    /// The origin if this is a snapshot
+    pub origin: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// A PV as it was when to_info was called
+#[derive(Clone, Debug, PartialEq)]
+pub struct PvInfo {
+    /// The path of the device
+    pub name: String,
+    pub uuid: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Size in bytes of the underlying device
+    pub dev_size: u64,
+    /// Unallocated space in bytes
+    pub free: u64,
+    pub mda_count: u64,
+}
+
+impl<'a> VolumeGroup<'a> {
+    pub fn to_info(&self) -> LvmResult<VgInfo> {
+        Ok(VgInfo {
+            name: self.get_name()?,
+            uuid: self.try_uuid()?,
+            size: self.get_size(),
+            free_size: self.get_free_size(),
+            extent_size: self.get_extent_size(),
+            extent_count: self.get_extent_count(),
+            free_extents: self.get_free_extents(),
+            pv_count: self.get_pv_count(),
+            seq_number: self.get_seq_number(),
+            tags: self.get_tags()?,
+        })
+    }
+
+    /// Find the LV info describes in this VG, by uuid so renames are
+    /// followed
+    pub fn lv_by_info(&self, info: &LvInfo) -> LvmResult<LogicalVolume<'_, '_>> {
+        for lv in self.list_lvs()? {
+            if lv.try_uuid()? == info.uuid {
+                return Ok(lv);
+            }
+        }
+        Err(LvmError::new((
+            Errno(libc::ENOENT),
+            format!(
+                "LV {} ({}) no longer exists in {}",
+                info.name,
+                info.uuid,
+                self.get_name()?
+            ),
+        )))
+    }
+
+    /// Find the PV info describes in this VG
+    pub fn pv_by_info(&self, info: &PvInfo) -> LvmResult<PhysicalVolume<'_>> {
+        for pv in self.list_pvs()? {
+            if pv.try_uuid()? == info.uuid {
+                return Ok(pv);
+            }
+        }
+        Err(LvmError::new((
+            Errno(libc::ENOENT),
+            format!(
+                "PV {} ({}) is no longer in {}",
+                info.name,
+                info.uuid,
+                self.get_name()?
+            ),
+        )))
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    pub fn to_info(&self) -> LvmResult<LvInfo> {
+        Ok(LvInfo {
+            vg_name: self.vg.get_name()?,
+            name: self.try_name()?,
+            uuid: self.try_uuid()?,
+            size: self.try_size()?,
+            attributes: self.try_attributes()?,
+            origin: self.try_origin()?,
+            tags: self.get_tags()?,
+        })
+    }
+}
+
+impl<'a> PhysicalVolume<'a> {
+    pub fn to_info(&self) -> LvmResult<PvInfo> {
+        Ok(PvInfo {
+            name: self.try_name()?,
+            uuid: self.try_uuid()?,
+            size: self.get_size(),
+            dev_size: self.get_dev_size(),
+            free: self.get_free(),
+            mda_count: self.get_mda_count(),
+        })
+    }
+}
+
+impl Lvm {
+    /// Open the VG info describes, failing if it has been replaced by
+    /// another VG of the same name
+    pub fn vg_by_info(&self, info: &VgInfo, mode: &OpenMode) -> LvmResult<VolumeGroup<'_>> {
+        let vg = self.vg_open(&info.name, mode)?;
+        let uuid = vg.try_uuid()?;
+        if uuid != info.uuid {
+            return Err(LvmError::new((
+                Errno(libc::ENOENT),
+                format!(
+                    "VG {} ({}) no longer exists, {} is now {}",
+                    info.name, info.uuid, info.name, uuid
+                ),
+            )));
+        }
+        Ok(vg)
+    }
+
+    /// Open the VG of the LV info describes in mode and run f with the
+    /// LV.  The VG is closed again once f returns.
+    pub fn lv_by_info<T, F>(&self, info: &LvInfo, mode: &OpenMode, f: F) -> LvmResult<T>
+    where
+        F: FnOnce(&mut LogicalVolume<'_, '_>) -> LvmResult<T>,
+    {
+        let vg = self.vg_open(&info.vg_name, mode)?;
+        let mut lv = vg.lv_by_info(info)?;
+        f(&mut lv)
+    }
+}
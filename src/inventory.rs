@@ -0,0 +1,58 @@
+//! Listing every LV on the system in one call, as owned values that don't
+//! hold any liblvm handle open.
+
+use crate::{LogicalVolume, Lvm, LvmErrorKind, LvmResult, OpenMode};
+
+/// An LV as it was when it was listed
+#[derive(Clone, Debug, PartialEq)]
+pub struct LvReport {
+    pub vg_name: String,
+    pub name: String,
+    pub uuid: String,
+    /// The device path, ie: /dev/vgname/lvname
+    pub path: String,
+    /// Size in bytes
+    pub size: u64,
+    /// The lvs attribute string, ie: -wi-a-----
+    pub attributes: String,
+    /// The origin if this is a snapshot
+    pub origin: Option<String>,
+    pub tags: Vec<String>,
+    pub active: bool,
+}
+
+impl LvReport {
+    pub(crate) fn new(vg_name: &str, lv: &LogicalVolume<'_, '_>) -> LvmResult<LvReport> {
+        Ok(LvReport {
+            vg_name: vg_name.to_string(),
+            name: lv.try_name()?,
+            uuid: lv.try_uuid()?,
+            path: lv.path()?.to_string_lossy().into_owned(),
+            size: lv.try_size()?,
+            attributes: lv.try_attributes()?,
+            origin: lv.try_origin()?,
+            tags: lv.get_tags()?,
+            active: lv.try_is_active()?,
+        })
+    }
+}
+
+impl Lvm {
+    /// List the LVs of every VG.  Each VG is opened read only and closed
+    /// again once its LVs are listed.  VGs removed while listing are
+    /// skipped.
+    pub fn list_all_lvs(&self) -> LvmResult<Vec<LvReport>> {
+        let mut reports = vec![];
+        for vg_name in self.get_volume_group_names()? {
+            let vg = match self.vg_open(&vg_name, &OpenMode::Read) {
+                Ok(vg) => vg,
+                Err(e) if e.kind() == LvmErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            for lv in vg.list_lvs()? {
+                reports.push(LvReport::new(&vg_name, &lv)?);
+            }
+        }
+        Ok(reports)
+    }
+}
@@ -0,0 +1,91 @@
+//! Per-LV IO statistics, read from the kernel's diskstats for the LV's
+//! device-mapper device.
+
+use std::fs;
+
+use errno::Errno;
+
+use crate::{LogicalVolume, LvmError, LvmResult};
+
+/// Cumulative IO counters of an active LV since it was activated.  Sectors
+/// are 512 bytes, times are in milliseconds.
+#[derive(Debug, Default)]
+pub struct IoStats {
+    pub reads: u64,
+    pub reads_merged: u64,
+    pub sectors_read: u64,
+    pub read_ms: u64,
+    pub writes: u64,
+    pub writes_merged: u64,
+    pub sectors_written: u64,
+    pub write_ms: u64,
+    pub in_flight: u64,
+    pub io_ms: u64,
+    pub weighted_io_ms: u64,
+    /// Discard counters, zero on kernels older than 4.18
+    pub discards: u64,
+    pub discards_merged: u64,
+    pub sectors_discarded: u64,
+    pub discard_ms: u64,
+}
+
+impl IoStats {
+    fn parse(line: &str) -> Option<IoStats> {
+        let fields: Vec<u64> = line
+            .split_whitespace()
+            .map(|f| f.parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        if fields.len() < 11 {
+            return None;
+        }
+        let field = |i: usize| fields.get(i).cloned().unwrap_or(0);
+        Some(IoStats {
+            reads: field(0),
+            reads_merged: field(1),
+            sectors_read: field(2),
+            read_ms: field(3),
+            writes: field(4),
+            writes_merged: field(5),
+            sectors_written: field(6),
+            write_ms: field(7),
+            in_flight: field(8),
+            io_ms: field(9),
+            weighted_io_ms: field(10),
+            discards: field(11),
+            discards_merged: field(12),
+            sectors_discarded: field(13),
+            discard_ms: field(14),
+        })
+    }
+
+    /// Average latency of completed reads in milliseconds
+    pub fn read_latency_ms(&self) -> Option<f64> {
+        if self.reads == 0 {
+            return None;
+        }
+        Some(self.read_ms as f64 / self.reads as f64)
+    }
+
+    /// Average latency of completed writes in milliseconds
+    pub fn write_latency_ms(&self) -> Option<f64> {
+        if self.writes == 0 {
+            return None;
+        }
+        Some(self.write_ms as f64 / self.writes as f64)
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Get the IO counters of an active LV
+    pub fn io_stats(&self) -> LvmResult<IoStats> {
+        let (major, minor) = self.major_minor()?;
+        let path = format!("/sys/dev/block/{}:{}/stat", major, minor);
+        let line = fs::read_to_string(&path)?;
+        IoStats::parse(&line).ok_or_else(|| {
+            LvmError::new((
+                Errno(libc::EINVAL),
+                format!("Unable to parse {}: {}", path, line.trim()),
+            ))
+        })
+    }
+}
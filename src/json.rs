@@ -0,0 +1,521 @@
+//! Just enough JSON for the server protocol and machine readable reports,
+//! without pulling in a serialization framework.
+
+use std::fmt;
+
+use errno::Errno;
+
+use crate::{LvmError, LvmResult};
+
+/// Deepest nesting parse accepts, input comes from other processes
+const MAX_DEPTH: usize = 128;
+
+/// A JSON value.  Numbers keep their text so u64 sizes survive a round
+/// trip without going through f64.  Object members keep their order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Build an object from key value pairs
+    pub fn object(members: Vec<(&str, Value)>) -> Value {
+        Value::Object(
+            members
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    /// Look up a member of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Value {
+        Value::Number(n.to_string())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Value {
+        Value::Number(n.to_string())
+    }
+}
+
+impl From<u32> for Value {
+    fn from(n: u32) -> Value {
+        Value::Number(n.to_string())
+    }
+}
+
+impl From<i32> for Value {
+    fn from(n: i32) -> Value {
+        Value::Number(n.to_string())
+    }
+}
+
+impl From<f64> for Value {
+    /// JSON has no NaN or infinity, they become null
+    fn from(n: f64) -> Value {
+        if n.is_finite() {
+            Value::Number(n.to_string())
+        } else {
+            Value::Null
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(s)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Value {
+        Value::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Value {
+        match value {
+            Some(v) => v.into(),
+            None => Value::Null,
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Compact JSON, on a single line
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => f.write_str(n),
+            Value::String(s) => write_string(f, s),
+            Value::Array(values) => {
+                f.write_str("[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                f.write_str("]")
+            }
+            Value::Object(members) => {
+                f.write_str("{")?;
+                for (i, (k, v)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn invalid(msg: &str, pos: usize) -> LvmError {
+    LvmError::new((
+        Errno(libc::EINVAL),
+        format!("Invalid JSON at byte {}: {}", pos, msg),
+    ))
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, literal: &str) -> LvmResult<()> {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err(invalid(&format!("expected {}", literal), self.pos))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> LvmResult<Value> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("nested too deeply", self.pos));
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'n') => self.expect("null").map(|_| Value::Null),
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut values = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(values));
+                        }
+                        _ => return Err(invalid("expected , or ]", self.pos)),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some(b'"') {
+                        return Err(invalid("expected a member name", self.pos));
+                    }
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(":")?;
+                    members.push((key, self.value(depth + 1)?));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Value::Object(members));
+                        }
+                        _ => return Err(invalid("expected , or }", self.pos)),
+                    }
+                }
+            }
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            _ => Err(invalid("expected a value", self.pos)),
+        }
+    }
+
+    /// Skip b if it's next
+    fn eat(&mut self, b: u8) -> bool {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skip a run of digits and return how many there were
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    /// A number as JSON spells them, which is stricter than Rust's parse:
+    /// no leading zeros, and digits on both sides of a point
+    fn number(&mut self) -> LvmResult<Value> {
+        let start = self.pos;
+        self.eat(b'-');
+        if !self.eat(b'0') && self.digits() == 0 {
+            return Err(invalid("bad number", start));
+        }
+        if self.eat(b'.') && self.digits() == 0 {
+            return Err(invalid("bad number", start));
+        }
+        if self.eat(b'e') || self.eat(b'E') {
+            if !self.eat(b'+') {
+                self.eat(b'-');
+            }
+            if self.digits() == 0 {
+                return Err(invalid("bad number", start));
+            }
+        }
+        let text = String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned();
+        Ok(Value::Number(text))
+    }
+
+    fn hex4(&mut self) -> LvmResult<u32> {
+        let digits = self
+            .bytes
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| invalid("bad \\u escape", self.pos))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn string(&mut self) -> LvmResult<String> {
+        // Skip the opening quote
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(invalid("unterminated string", self.pos)),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return String::from_utf8(out).map_err(|_| invalid("bad utf-8", self.pos));
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escaped = self.peek();
+                    self.pos += 1;
+                    let c = match escaped {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut code = self.hex4()?;
+                            // A surrogate pair encodes one character
+                            if (0xd800..0xdc00).contains(&code) {
+                                self.expect("\\u")?;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(invalid("bad surrogate pair", self.pos));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            std::char::from_u32(code)
+                                .ok_or_else(|| invalid("bad \\u escape", self.pos))?
+                        }
+                        _ => return Err(invalid("bad escape", self.pos)),
+                    };
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                Some(b) => {
+                    out.push(b);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Parse a single JSON value
+pub fn parse(text: &str) -> LvmResult<Value> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != parser.bytes.len() {
+        return Err(invalid("trailing characters", parser.pos));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_round_trip() {
+        let value = Value::object(vec![
+            ("null", Value::Null),
+            ("bools", vec![true, false].into()),
+            ("size", u64::MAX.into()),
+            ("negative", (-12i64).into()),
+            ("ratio", 0.25.into()),
+            ("escapes", "quote\" back\\ line\n tab\t bell\u{7}".into()),
+            ("unicode", "caf\u{e9} \u{1f980}".into()),
+            (
+                "nested",
+                Value::Array(vec![Value::object(vec![]), Value::Array(vec![])]),
+            ),
+        ]);
+        let text = value.to_string();
+        assert!(!text.contains('\n'));
+        assert_eq!(parse(&text).unwrap(), value);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            parse(r#""\"\\\/\b\f\n\r\t\u0041""#).unwrap(),
+            Value::String("\"\\/\u{8}\u{c}\n\r\tA".to_string())
+        );
+        assert_eq!(
+            parse(r#""\ud83e\udd80""#).unwrap(),
+            Value::String("\u{1f980}".to_string())
+        );
+        // A high surrogate must be followed by a low one
+        assert!(parse(r#""\ud83e""#).is_err());
+        assert!(parse(r#""\ud83e\u0041""#).is_err());
+        assert!(parse(r#""\ud83ex""#).is_err());
+        // A low surrogate can't stand alone
+        assert!(parse(r#""\udd80""#).is_err());
+        assert!(parse(r#""\x""#).is_err());
+        assert!(parse(r#""\u12g4""#).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        for text in &[
+            "",
+            "nul",
+            "tru",
+            "[",
+            "[1,",
+            "{",
+            "{\"a\"",
+            "{\"a\":",
+            "{\"a\":1,",
+            "\"abc",
+            "\"\\",
+            "\"\\u12",
+            "\"\\ud83e\\u",
+        ] {
+            assert!(parse(text).is_err(), "{} parsed", text);
+        }
+    }
+
+    #[test]
+    fn nesting() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 2)).is_err());
+        // Far too deep must fail rather than overflow the stack
+        assert!(parse(&nested(100_000)).is_err());
+    }
+
+    #[test]
+    fn trailing_data() {
+        assert_eq!(parse(" {} \n").unwrap(), Value::object(vec![]));
+        for text in &["1 2", "{} x", "null,", "[]]", "\"a\"\"b\""] {
+            assert!(parse(text).is_err(), "{} parsed", text);
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        for text in &["0", "-0", "12", "-12", "1.5", "1e5", "1E+5", "2.5e-3"] {
+            assert_eq!(parse(text).unwrap(), Value::Number(text.to_string()));
+        }
+        assert_eq!(parse("1e5").unwrap().as_f64(), Some(100000.0));
+        assert_eq!(
+            parse("18446744073709551615").unwrap().as_u64(),
+            Some(u64::MAX)
+        );
+        for text in &[
+            "-", "01", "-01", "1.", ".5", "-.5", "1e", "1e+", "+1", "0x10",
+        ] {
+            assert!(parse(text).is_err(), "{} parsed", text);
+        }
+    }
+}
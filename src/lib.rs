@@ -30,55 +30,222 @@
 //!  the new one obtained with WRITE permission.
 
 use errno;
+#[cfg(not(feature = "tracing"))]
 #[macro_use]
 extern crate log;
+#[cfg(feature = "tracing")]
+#[macro_use]
+extern crate tracing;
 
 use uuid;
 
+pub mod activation;
+pub mod audit;
+pub mod autoactivation;
+mod blockdev;
+pub mod boot;
+pub mod borrowed;
+pub mod builder;
+pub mod cache;
+pub mod ceph;
+mod cli;
+pub mod container;
+pub mod convert;
+pub mod cow;
+pub mod create;
+#[cfg(feature = "crypt")]
+pub mod crypt;
+pub mod degraded;
+pub mod devices;
+mod dm;
+pub mod duplicates;
+pub mod extents;
+pub mod graph;
+pub mod grow;
+#[cfg(feature = "health")]
+pub mod health;
+pub mod info;
+pub mod integrity;
+pub mod inventory;
+pub mod iostats;
+pub mod json;
+pub mod lock;
+pub mod lvchange;
+pub mod lvhealth;
+pub mod meta;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod mkfs;
+pub mod mount;
+pub mod multipath;
+pub mod operation;
+pub mod preflight;
+pub mod privileges;
+pub mod property;
+pub mod provision;
+pub mod pvcreate;
+pub mod pvresize;
+pub mod queue;
+pub mod retry;
+pub mod select;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod session;
+pub mod size;
+pub mod sizing;
+pub mod snapshots;
+pub mod spares;
+pub mod status;
+pub mod swap;
+pub mod thin;
+pub mod thin_tools;
+pub mod thinpool;
+mod trace;
+pub mod vdo;
+pub mod version;
+pub mod vgcreate;
+pub mod vgopen;
+pub mod writecache;
+
+use std::cell::{Cell, RefCell};
+use std::convert::TryFrom;
 use std::error::Error as err;
 use std::ffi::{CStr, CString, NulError};
 use std::fmt;
 use std::io::Error as IOError;
-use std::path::Path;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::ptr;
 use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use errno::Errno;
 use lvm_sys::*;
 use uuid::Uuid;
 
+use crate::size::Size;
+
 pub type LvmResult<T> = Result<T, LvmError>;
 
-/// Custom error handling
+/// liblvm percentages are fixed point with this many units per percent
+const DM_PERCENT_1: i64 = 1_000_000;
+
+/// Extent sizes lvm accepts, 1KiB up to the largest power of two number of
+/// sectors its 32 bit extent size holds
+const MIN_EXTENT_SIZE: u64 = 1024;
+const MAX_EXTENT_SIZE: u64 = 512 << 31;
+
+/// Copy a string liblvm returned for what, failing on a null pointer or
+/// invalid UTF-8 instead of crashing or mangling it
+unsafe fn checked_string(ptr: *const std::os::raw::c_char, what: &str) -> LvmResult<String> {
+    if ptr.is_null() {
+        return Err(LvmError::new((
+            Errno(libc::ENOMEM),
+            format!("liblvm returned no {}", what),
+        )));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map(|s| s.to_string())
+        .map_err(|_| {
+            LvmError::new((
+                Errno(libc::EILSEQ),
+                format!("{} from liblvm is not valid UTF-8", what),
+            ))
+        })
+}
+
+/// Custom error handling.  More variants may be added, match on kind() to
+/// handle errors by what went wrong.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum LvmError {
     Error((Errno, String)),
     IoError(IOError),
     NulError(NulError),
     ParseError(uuid::Error),
+    /// An operation didn't finish within its OperationOptions timeout
+    Timeout(String),
+    /// An operation was abandoned through its CancellationToken
+    Cancelled(String),
+    /// An LV couldn't be removed because other LVs depend on it.  Holds
+    /// the error message and the dependent LVs.
+    HasDependents(String, Vec<String>),
+    /// A destructive operation was refused because the LV is mounted.
+    /// Holds the error message and the mount point.
+    InUse(String, PathBuf),
+    /// A PV couldn't be removed from its VG because LVs still have extents
+    /// on it.  Holds the error message and those LVs.
+    PvInUse(String, Vec<String>),
+    /// The process isn't root and lacks CAP_SYS_ADMIN.  Holds what to do
+    /// about it.
+    InsufficientPrivileges(String),
+}
+
+/// What kind of failure an LvmError is, across the variants and errnos
+/// that can signal it
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum LvmErrorKind {
+    /// The VG, LV, PV or device doesn't exist
+    NotFound,
+    AlreadyExists,
+    /// Not allowed, by permissions, privileges or the VG's open mode
+    PermissionDenied,
+    /// Mounted, open, depended on or otherwise busy
+    InUse,
+    /// Another process holds a lock needed, retrying later may work
+    Locked,
+    /// A bad name, size or argument
+    InvalidInput,
+    Timeout,
+    Cancelled,
+    /// Reading or writing a device or file failed
+    Io,
+    Other,
+}
+
+fn errno_kind(errno: i32) -> LvmErrorKind {
+    match errno {
+        libc::ENOENT | libc::ENODEV | libc::ENXIO => LvmErrorKind::NotFound,
+        libc::EEXIST => LvmErrorKind::AlreadyExists,
+        libc::EPERM | libc::EACCES => LvmErrorKind::PermissionDenied,
+        libc::EBUSY => LvmErrorKind::InUse,
+        libc::EAGAIN => LvmErrorKind::Locked,
+        libc::EINVAL | libc::ERANGE | libc::ENAMETOOLONG => LvmErrorKind::InvalidInput,
+        libc::ETIMEDOUT => LvmErrorKind::Timeout,
+        libc::ECANCELED => LvmErrorKind::Cancelled,
+        libc::EIO | libc::ENOSPC => LvmErrorKind::Io,
+        _ => LvmErrorKind::Other,
+    }
 }
 
 impl fmt::Display for LvmError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(self.description())
+        match self {
+            LvmError::Error((_, msg)) => f.write_str(msg),
+            LvmError::IoError(e) => write!(f, "{}", e),
+            LvmError::NulError(e) => write!(f, "{}", e),
+            LvmError::ParseError(e) => write!(f, "{}", e),
+            LvmError::Timeout(msg) => f.write_str(msg),
+            LvmError::Cancelled(msg) => f.write_str(msg),
+            LvmError::HasDependents(msg, _) => f.write_str(msg),
+            LvmError::InUse(msg, _) => f.write_str(msg),
+            LvmError::PvInUse(msg, _) => f.write_str(msg),
+            LvmError::InsufficientPrivileges(msg) => f.write_str(msg),
+        }
     }
 }
 
 impl err for LvmError {
-    fn description(&self) -> &str {
-        match *self {
-            LvmError::Error(ref e) => &e.1,
-            LvmError::IoError(ref e) => e.description(),
-            LvmError::NulError(ref e) => e.description(),
-            LvmError::ParseError(ref e) => e.description(),
-        }
-    }
-    fn cause(&self) -> Option<&dyn err> {
-        match *self {
-            LvmError::Error(_) => None,
-            LvmError::IoError(ref e) => e.cause(),
-            LvmError::NulError(ref e) => e.cause(),
-            LvmError::ParseError(ref e) => e.cause(),
+    fn source(&self) -> Option<&(dyn err + 'static)> {
+        match self {
+            LvmError::IoError(e) => Some(e),
+            LvmError::NulError(e) => Some(e),
+            LvmError::ParseError(e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -88,6 +255,48 @@ impl LvmError {
     pub fn new(err: (Errno, String)) -> LvmError {
         LvmError::Error((err.0, err.1))
     }
+
+    /// What kind of failure this is
+    pub fn kind(&self) -> LvmErrorKind {
+        match self {
+            LvmError::Error(_) if retry::is_lock_error(self) => LvmErrorKind::Locked,
+            LvmError::Error((errno, _)) => errno_kind(errno.0),
+            LvmError::IoError(e) => match e.raw_os_error() {
+                Some(errno) => errno_kind(errno),
+                None => match e.kind() {
+                    std::io::ErrorKind::NotFound => LvmErrorKind::NotFound,
+                    std::io::ErrorKind::PermissionDenied => LvmErrorKind::PermissionDenied,
+                    std::io::ErrorKind::AlreadyExists => LvmErrorKind::AlreadyExists,
+                    std::io::ErrorKind::InvalidInput => LvmErrorKind::InvalidInput,
+                    std::io::ErrorKind::TimedOut => LvmErrorKind::Timeout,
+                    _ => LvmErrorKind::Io,
+                },
+            },
+            LvmError::NulError(_) | LvmError::ParseError(_) => LvmErrorKind::InvalidInput,
+            LvmError::Timeout(_) => LvmErrorKind::Timeout,
+            LvmError::Cancelled(_) => LvmErrorKind::Cancelled,
+            LvmError::HasDependents(_, _) | LvmError::InUse(_, _) | LvmError::PvInUse(_, _) => {
+                LvmErrorKind::InUse
+            }
+            LvmError::InsufficientPrivileges(_) => LvmErrorKind::PermissionDenied,
+        }
+    }
+
+    /// The errno this failure amounts to, for reporting it to other
+    /// processes or in logs
+    pub fn errno(&self) -> i32 {
+        match self {
+            LvmError::Error((errno, _)) => errno.0,
+            LvmError::IoError(e) => e.raw_os_error().unwrap_or(libc::EIO),
+            LvmError::NulError(_) | LvmError::ParseError(_) => libc::EINVAL,
+            LvmError::Timeout(_) => libc::ETIMEDOUT,
+            LvmError::Cancelled(_) => libc::ECANCELED,
+            LvmError::InsufficientPrivileges(_) => libc::EPERM,
+            LvmError::HasDependents(_, _) | LvmError::InUse(_, _) | LvmError::PvInUse(_, _) => {
+                libc::EBUSY
+            }
+        }
+    }
 }
 
 impl From<IOError> for LvmError {
@@ -111,6 +320,19 @@ impl From<uuid::Error> for LvmError {
 #[derive(Debug)]
 pub struct Lvm {
     handle: lvm_t,
+    /// Calls given up on after a timeout or cancellation that may still be
+    /// using the handle
+    abandoned: RefCell<Vec<thread::JoinHandle<()>>>,
+    lock_retry: Cell<retry::RetryPolicy>,
+    test_mode: Cell<bool>,
+    /// Settings passed to config_override, also handed to the lvm tools
+    config_overrides: RefCell<Vec<String>>,
+    /// The system dir passed to new, also handed to the lvm tools
+    system_dir: Option<PathBuf>,
+    container_mode: Cell<bool>,
+    audit: RefCell<audit::AuditHook>,
+    /// liblvm's error before the latest call, see clear_error
+    error_baseline: RefCell<Option<(i32, String)>>,
 }
 
 impl Drop for Lvm {
@@ -118,13 +340,17 @@ impl Drop for Lvm {
         unsafe {
             if !self.handle.is_null() {
                 debug!("dropping lvm");
+                for worker in self.abandoned.get_mut().drain(..) {
+                    let _ = worker.join();
+                }
                 lvm_quit(self.handle);
+                self.handle = ptr::null_mut();
             }
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum OpenMode {
     Read,
     Write,
@@ -157,6 +383,148 @@ impl ToString for LvmThinPolicy {
     }
 }
 
+impl LvmThinPolicy {
+    fn as_arg(&self) -> &'static str {
+        match self {
+            LvmThinPolicy::Ignore => "ignore",
+            LvmThinPolicy::NoPassdown => "nopassdown",
+            LvmThinPolicy::Passdown => "passdown",
+        }
+    }
+}
+
+/// Extent allocation policies
+#[derive(Debug)]
+pub enum AllocPolicy {
+    Normal,
+    /// New extents must be adjacent to existing ones
+    Contiguous,
+    /// New extents go on the same PVs as existing ones
+    Cling,
+    /// Allocate anywhere, even on a PV already used by another mirror leg
+    Anywhere,
+    /// Use the policy of the VG.  Only valid for LVs
+    Inherit,
+    /// Like Cling, but once the PVs already used are full new extents go
+    /// on PVs sharing one of these tags with them, ie: rack1, or * for any
+    /// tag.  Keeps each mirror leg or raid image within its own set of
+    /// tagged PVs as LVs grow.  The tags are lvm.conf's
+    /// allocation/cling_tag_list, which is set on the Lvm handle for as long
+    /// as it lives, and aren't stored in the metadata: a policy read back
+    /// from an LV has none.
+    ClingByTags(Vec<String>),
+}
+
+impl AllocPolicy {
+    fn as_arg(&self) -> &'static str {
+        match self {
+            AllocPolicy::Normal => "normal",
+            AllocPolicy::Contiguous => "contiguous",
+            AllocPolicy::Cling => "cling",
+            AllocPolicy::Anywhere => "anywhere",
+            AllocPolicy::Inherit => "inherit",
+            AllocPolicy::ClingByTags(_) => "cling_by_tags",
+        }
+    }
+
+    /// Set up lvm to allocate with this policy.  Only ClingByTags needs
+    /// anything, its tags are set as cling_tag_list on the handle.  Tags
+    /// can't be changed once set as the earlier ones would still apply.
+    fn prepare(&self, lvm: &Lvm) -> LvmResult<()> {
+        let tags = match self {
+            AllocPolicy::ClingByTags(tags) => tags,
+            _ => return Ok(()),
+        };
+        if tags.is_empty() {
+            return Err(LvmError::new((
+                Errno(libc::EINVAL),
+                "cling_by_tags needs at least one tag".to_string(),
+            )));
+        }
+        let mut list = vec![];
+        for tag in tags {
+            let tag = tag.trim_start_matches('@');
+            if tag != "*" {
+                validate_tag(tag)?;
+            }
+            list.push(format!("\"@{}\"", tag));
+        }
+        let setting = format!("allocation/cling_tag_list=[{}]", list.join(","));
+        let existing = lvm
+            .config_overrides
+            .borrow()
+            .iter()
+            .find(|o| o.starts_with("allocation/cling_tag_list="))
+            .cloned();
+        match existing {
+            Some(ref existing) if *existing == setting => Ok(()),
+            Some(existing) => Err(LvmError::new((
+                Errno(libc::EINVAL),
+                format!(
+                    "This handle already allocates with {}, use another handle for other tags",
+                    existing
+                ),
+            ))),
+            None => {
+                lvm.config_override(&setting)?;
+                lvm.reload_config()
+            }
+        }
+    }
+}
+
+impl FromStr for AllocPolicy {
+    type Err = LvmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(AllocPolicy::Normal),
+            "contiguous" => Ok(AllocPolicy::Contiguous),
+            "cling" => Ok(AllocPolicy::Cling),
+            "anywhere" => Ok(AllocPolicy::Anywhere),
+            "inherit" => Ok(AllocPolicy::Inherit),
+            "cling_by_tags" => Ok(AllocPolicy::ClingByTags(vec![])),
+            _ => Err(LvmError::new((
+                Errno(libc::EINVAL),
+                format!("Unknown allocation policy: {}", s),
+            ))),
+        }
+    }
+}
+
+/// How to scrub an LV's data before removing it
+#[derive(Debug)]
+pub enum WipePolicy {
+    /// Remove the LV without touching its data
+    None,
+    /// Overwrite the LV with zeros
+    Zero,
+    /// Discard the LV's blocks.  Whether discarded blocks read back as zeros
+    /// depends on the underlying devices.
+    Discard,
+}
+
+/// Mirror log types
+#[derive(Debug)]
+pub enum MirrorLog {
+    /// Keep the log in memory. The mirror is resynced on every activation
+    Core,
+    /// Keep the log on a separate disk
+    Disk,
+    /// Keep a mirrored log on two separate disks
+    Mirrored,
+}
+
+impl MirrorLog {
+    fn as_arg(&self) -> &'static str {
+        match self {
+            MirrorLog::Core => "core",
+            MirrorLog::Disk => "disk",
+            MirrorLog::Mirrored => "mirrored",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Property {
     /// zero indicates use detected size of device
@@ -180,24 +548,40 @@ impl ToString for Property {
             Property::PvMetaDataCopies(_) => "pvmetadatacopies".into(),
             Property::PvMetaDatasize(_) => "pvmetadatasize".into(),
             Property::DataAlignment(_) => "data_alignment".into(),
-            Property::DataAlignmentOffset(_) => "".into(),
+            Property::DataAlignmentOffset(_) => "data_alignment_offset".into(),
             Property::Zero(_) => "zero".into(),
         }
     }
 }
 
+/// An open VG.  If reopening it after an lvm command fails the handle is
+/// left closed: calls returning LvmResult fail with EBADF and the getters
+/// that can't fail return 0, false or an empty string.
 #[derive(Debug)]
 pub struct VolumeGroup<'a> {
-    handle: vg_t,
+    /// Null once closed.  A Cell so LV and PV methods can reopen it, see
+    /// run_command.
+    handle: Cell<vg_t>,
     lvm: &'a Lvm,
+    mode: OpenMode,
+    /// Overrides the Lvm handle's lock retry policy
+    lock_retry: Option<retry::RetryPolicy>,
+    /// Bumped every time the handle is reopened, which frees every LV and
+    /// PV handle taken from it before
+    generation: Cell<u64>,
+    /// Strings borrowed from the VG that are still alive, see borrowed.rs.
+    /// The handle isn't reopened while there are any.
+    pins: Cell<usize>,
 }
 
 impl<'a> Drop for VolumeGroup<'a> {
     fn drop(&mut self) {
         unsafe {
-            if !self.handle.is_null() {
+            if !self.handle.get().is_null() {
                 debug!("dropping vg");
-                lvm_vg_close(self.handle);
+                // An abandoned call may still be using the handle
+                self.lvm.wait_for_abandoned();
+                lvm_vg_close(self.handle.replace(ptr::null_mut()));
             }
         }
     }
@@ -215,6 +599,9 @@ pub struct LvmPropertyValue {
 pub struct PhysicalVolume<'a> {
     handle: pv_t,
     lvm: &'a Lvm,
+    vg: &'a VolumeGroup<'a>,
+    /// The VG's generation when handle was taken from it
+    generation: u64,
 }
 
 pub struct PhysicalVolumeCreateParameters<'a> {
@@ -228,9 +615,96 @@ pub struct LogicalVolume<'b, 'a: 'b> {
     handle: lv_t,
     lvm: &'a Lvm,
     vg: &'b VolumeGroup<'b>,
+    /// The VG's generation when handle was taken from it
+    generation: u64,
+}
+
+/// A newly created LV along with what callers usually ask for next.  It
+/// derefs to the LV so it can be used as one.
+#[derive(Debug)]
+pub struct CreatedLv<'b, 'a: 'b> {
+    pub lv: LogicalVolume<'b, 'a>,
+    /// The /dev/<vg>/<lv> path
+    pub path: PathBuf,
+    pub uuid: String,
+    /// Size in bytes actually allocated, after rounding up to whole extents
+    pub size: u64,
+}
+
+impl<'b, 'a: 'b> CreatedLv<'b, 'a> {
+    fn new(lv: LogicalVolume<'b, 'a>) -> LvmResult<Self> {
+        Ok(CreatedLv {
+            path: lv.path()?,
+            uuid: lv.try_uuid()?,
+            size: lv.try_size()?,
+            lv,
+        })
+    }
+}
+
+impl<'b, 'a: 'b> Deref for CreatedLv<'b, 'a> {
+    type Target = LogicalVolume<'b, 'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.lv
+    }
+}
+
+impl<'b, 'a: 'b> DerefMut for CreatedLv<'b, 'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.lv
+    }
 }
 
 impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// The liblvm handle, once any call abandoned on a worker thread has
+    /// finished with it.  Fails with EBADF once an lvm command run through
+    /// another handle reopened the VG, freeing this one.
+    fn handle(&self) -> LvmResult<lv_t> {
+        self.vg.handle()?;
+        if self.generation != self.vg.generation.get() {
+            return Err(LvmError::new((
+                Errno(libc::EBADF),
+                "The LV handle is stale, an lvm command reopened its VG, look it up again"
+                    .to_string(),
+            )));
+        }
+        Ok(self.handle)
+    }
+
+    /// Call f with the handle, or return the default for a stale handle
+    fn with_handle<T: Default, F: FnOnce(lv_t) -> T>(&self, f: F) -> T {
+        match self.handle() {
+            Ok(handle) => f(handle),
+            Err(_) => T::default(),
+        }
+    }
+
+    /// Run an lvm command on the LV, given as vgname/lvname after args,
+    /// through VolumeGroup::run_command and find the LV again in the
+    /// reopened VG by its uuid
+    fn run_command(&mut self, args: &[&str]) -> LvmResult<String> {
+        let uuid = CString::new(self.try_uuid()?)?;
+        let lv_path = self.full_name()?;
+        let mut args = args.to_vec();
+        args.push(&lv_path);
+        let output = self.vg.run_command(&args);
+        // The VG was reopened even if the command failed
+        let found = self.vg.handle().and_then(|vg| unsafe {
+            let lv_t = lvm_lv_from_uuid(vg, uuid.as_ptr());
+            if lv_t.is_null() {
+                let err = self.lvm.get_error()?;
+                return Err(LvmError::new((err.0, err.1)));
+            }
+            self.handle = lv_t;
+            self.generation = self.vg.generation.get();
+            Ok(())
+        });
+        let output = output?;
+        found?;
+        Ok(output)
+    }
+
     fn check_retcode(&self, retcode: i32) -> LvmResult<()> {
         if retcode < 0 {
             let err = self.lvm.get_error()?;
@@ -239,148 +713,427 @@ impl<'a, 'b> LogicalVolume<'a, 'b> {
         Ok(())
     }
 
-    /// Activate a logical volume
-    pub fn activate(&self) -> LvmResult<()> {
+    fn get_string_property(&self, name: &str) -> LvmResult<String> {
+        let prop_name = CString::new(name)?;
         unsafe {
-            let retcode = lvm_lv_activate(self.handle);
-            self.check_retcode(retcode)?;
-            Ok(())
+            let value = lvm_lv_get_property(self.handle()?, prop_name.as_ptr());
+            if value.is_valid() == 0 {
+                let err = self.lvm.get_error()?;
+                return Err(LvmError::new((err.0, err.1)));
+            }
+            if value.is_string() == 0 || value.value.string.is_null() {
+                return Err(LvmError::new((
+                    Errno(libc::EINVAL),
+                    format!("Property {} is not a string", name),
+                )));
+            }
+            let s = CStr::from_ptr(value.value.string).to_string_lossy();
+            Ok(s.into_owned())
         }
     }
 
-    pub fn add_tag(&self, name: &str) -> LvmResult<()> {
-        let name = CString::new(name)?;
+    /// Get a percentage property.  liblvm reports these as fixed point
+    /// integers and uses -1 when the value doesn't apply to the LV.
+    fn get_percent_property(&self, name: &str) -> LvmResult<Option<f64>> {
+        let percent = self.get_integer_property(name)?;
+        if !(0..=100 * DM_PERCENT_1).contains(&percent) {
+            return Ok(None);
+        }
+        Ok(Some(percent as f64 / DM_PERCENT_1 as f64))
+    }
+
+    fn get_integer_property(&self, name: &str) -> LvmResult<i64> {
+        let prop_name = CString::new(name)?;
         unsafe {
-            let retcode = lvm_lv_add_tag(self.handle, name.as_ptr());
-            self.check_retcode(retcode)?;
-            self.vg.write()?;
-            Ok(())
+            let value = lvm_lv_get_property(self.handle()?, prop_name.as_ptr());
+            if value.is_valid() == 0 {
+                let err = self.lvm.get_error()?;
+                return Err(LvmError::new((err.0, err.1)));
+            }
+            if value.is_integer() == 0 {
+                return Err(LvmError::new((
+                    Errno(libc::EINVAL),
+                    format!("Property {} is not an integer", name),
+                )));
+            }
+            Ok(value.value.signed_integer)
+        }
+    }
+
+    /// The vgname/lvname form of the LV used by the lvm tools
+    fn full_name(&self) -> LvmResult<String> {
+        Ok(format!("{}/{}", self.vg.get_name()?, self.try_name()?))
+    }
+
+    /// Activate a logical volume
+    pub fn activate(&mut self) -> LvmResult<()> {
+        self.lvm
+            .audited("lvm_lv_activate", &self.try_name()?, &[], || unsafe {
+                self.check_retcode(lvm_lv_activate(self.handle()?))
+            })?;
+        if self.lvm.is_container_mode() {
+            self.sync_device_node()?;
+        }
+        Ok(())
+    }
+
+    /// Activate a logical volume and block until its device node shows up.
+    /// udev may create the node some time after activation returns so this
+    /// waits for udev to settle and then polls /dev/mapper until timeout.
+    /// Returns the resolved device path, ie: /dev/dm-3
+    pub fn activate_and_wait(&mut self, timeout: Duration) -> LvmResult<PathBuf> {
+        self.activate()?;
+        let dm_path = self.dm_path()?;
+        let deadline = Instant::now() + timeout;
+        // udevadm may be missing, ie: in containers, so polling is the fallback
+        let settle = Command::new("udevadm")
+            .arg("settle")
+            .arg(format!("--timeout={}", timeout.as_secs().max(1)))
+            .status();
+        if let Err(e) = settle {
+            debug!("udevadm settle failed: {}", e);
+        }
+        loop {
+            if dm_path.exists() {
+                return Ok(dm_path.canonicalize()?);
+            }
+            if Instant::now() >= deadline {
+                return Err(LvmError::new((
+                    Errno(libc::ETIMEDOUT),
+                    format!("Timed out waiting for {}", dm_path.display()),
+                )));
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    pub fn add_tag(&mut self, name: &str) -> LvmResult<()> {
+        self.change_tag("lvm_lv_add_tag", name, lvm_lv_add_tag)?;
+        self.vg.commit()
+    }
+
+    /// Add several tags and commit them with a single VG write.  All tags
+    /// are validated before any are added.
+    pub fn add_tags(&mut self, names: &[&str]) -> LvmResult<()> {
+        validate_tags(names)?;
+        for name in names {
+            self.change_tag("lvm_lv_add_tag", name, lvm_lv_add_tag)?;
         }
+        self.vg.commit()
+    }
+
+    /// Add or remove a tag through f, auditing the call
+    fn change_tag(
+        &self,
+        op: &'static str,
+        name: &str,
+        f: unsafe extern "C" fn(lv_t, *const libc::c_char) -> libc::c_int,
+    ) -> LvmResult<()> {
+        let params = [("tag", name.to_string())];
+        let name = CString::new(name)?;
+        self.lvm.audited(op, &self.try_name()?, &params, || unsafe {
+            self.check_retcode(f(self.handle()?, name.as_ptr()))
+        })
+    }
+
+    /// Get the extent allocation policy of a logical volume
+    pub fn alloc_policy(&self) -> LvmResult<AllocPolicy> {
+        self.get_string_property("lv_allocation_policy")?.parse()
+    }
+
+    /// Set the extent allocation policy of a logical volume.  Runs lvchange,
+    /// see set_read_only.
+    pub fn set_alloc_policy(&mut self, policy: &AllocPolicy) -> LvmResult<()> {
+        policy.prepare(self.lvm)?;
+        self.run_command(&["lvchange", "--alloc", policy.as_arg()])?;
+        Ok(())
+    }
+
+    /// Get the host a logical volume was created on
+    pub fn creation_host(&self) -> LvmResult<String> {
+        self.get_string_property("lv_host")
+    }
+
+    /// Get the time a logical volume was created
+    pub fn creation_time(&self) -> LvmResult<SystemTime> {
+        let lv_time = self.get_string_property("lv_time")?;
+        parse_lvm_time(&lv_time).ok_or_else(|| {
+            LvmError::new((
+                Errno(libc::EINVAL),
+                format!("Unable to parse lv_time: {}", lv_time),
+            ))
+        })
     }
 
     /// Deactivate a logical volume
-    pub fn deactivate(&self) -> LvmResult<()> {
-        unsafe {
-            let retcode = lvm_lv_deactivate(self.handle);
-            self.check_retcode(retcode)?;
-            Ok(())
+    pub fn deactivate(&mut self) -> LvmResult<()> {
+        self.lvm
+            .audited("lvm_lv_deactivate", &self.try_name()?, &[], || unsafe {
+                self.check_retcode(lvm_lv_deactivate(self.handle()?))
+            })?;
+        if self.lvm.is_container_mode() {
+            self.sync_device_node()?;
+        }
+        Ok(())
+    }
+
+    /// Discard every block of a logical volume, ie: to hand unused space
+    /// back to a thin pool before reusing the LV.  The LV must be active.
+    pub fn discard_all(&mut self) -> LvmResult<()> {
+        if self.lvm.is_test_mode() {
+            info!("test mode, not discarding {}", self.path()?.display());
+            return Ok(());
         }
+        blockdev::discard(&self.path()?, 0, self.try_size()?)
+    }
+
+    /// Get the device-mapper name of a logical volume.
+    /// Any '-' in the VG or LV name is escaped as '--' and the two are
+    /// joined by a single '-', ie: vg-name/lv becomes vg--name-lv
+    pub fn dm_name(&self) -> LvmResult<String> {
+        let vg_name = self.vg.get_name()?;
+        Ok(dm_name(&vg_name, &self.try_name()?))
+    }
+
+    /// Get the device-mapper path of a logical volume, ie: /dev/mapper/vg-lv
+    pub fn dm_path(&self) -> LvmResult<PathBuf> {
+        Ok(Path::new("/dev/mapper").join(self.dm_name()?))
     }
 
     /// Get the attributes of a logical volume
+    #[deprecated(since = "0.3.3", note = "use try_attributes, which reports failures")]
     pub fn get_attributes(&self) -> String {
-        unsafe {
-            let ptr = lvm_lv_get_attr(self.handle);
+        self.with_handle(|handle| unsafe {
+            let ptr = lvm_lv_get_attr(handle);
             let attrs_str = CStr::from_ptr(ptr).to_string_lossy();
             attrs_str.into_owned()
-        }
+        })
+    }
+
+    /// Get the attributes of a logical volume, ie: -wi-a-----
+    pub fn try_attributes(&self) -> LvmResult<String> {
+        unsafe { checked_string(lvm_lv_get_attr(self.handle()?), "LV attributes") }
     }
 
     /// Get the current name of a logical volume
+    #[deprecated(since = "0.3.3", note = "use try_name, which reports failures")]
     pub fn get_name(&self) -> String {
-        unsafe {
-            let name = lvm_lv_get_name(self.handle);
+        self.with_handle(|handle| unsafe {
+            let name = lvm_lv_get_name(handle);
             let name_str = CStr::from_ptr(name).to_string_lossy();
             name_str.into_owned()
+        })
+    }
+
+    /// Get the current name of a logical volume
+    pub fn try_name(&self) -> LvmResult<String> {
+        unsafe { checked_string(lvm_lv_get_name(self.handle()?), "LV name") }
+    }
+
+    /// Get the kernel major and minor device numbers of an active LV
+    pub fn major_minor(&self) -> LvmResult<(u32, u32)> {
+        let major = self.get_integer_property("lv_kernel_major")?;
+        let minor = self.get_integer_property("lv_kernel_minor")?;
+        if major < 0 || minor < 0 {
+            return Err(LvmError::new((
+                Errno(libc::ENXIO),
+                format!("{} is not active", self.try_name()?),
+            )));
+        }
+        Ok((major as u32, minor as u32))
+    }
+
+    /// Get the persistent minor number assigned to an LV, if any
+    pub fn persistent_minor(&self) -> LvmResult<Option<u32>> {
+        let minor = self.get_integer_property("lv_minor")?;
+        if minor < 0 {
+            return Ok(None);
         }
+        Ok(Some(minor as u32))
     }
 
-    /// Get the origin of a snapshot
+    /// Get how far a mirror has synced, or None if this isn't a mirror
+    pub fn mirror_sync_percent(&self) -> LvmResult<Option<f64>> {
+        self.get_percent_property("copy_percent")
+    }
+
+    /// Get the origin of a snapshot.  None for a stale handle too, see
+    /// try_origin.
     pub fn get_origin(&self) -> Option<String> {
+        self.try_origin().unwrap_or(None)
+    }
+
+    /// Get the origin of a snapshot, or None if this isn't one
+    pub fn try_origin(&self) -> LvmResult<Option<String>> {
         unsafe {
-            let ptr = lvm_lv_get_origin(self.handle);
+            let ptr = lvm_lv_get_origin(self.handle()?);
             if ptr.is_null() {
-                return None;
+                return Ok(None);
             }
             let origin = CStr::from_ptr(ptr).to_string_lossy();
-            Some(origin.into_owned())
+            Ok(Some(origin.into_owned()))
         }
     }
 
-    /// Get the current size in bytes of a logical volume
+    /// Get the device path of a logical volume, ie: /dev/vgname/lvname
+    pub fn path(&self) -> LvmResult<PathBuf> {
+        let vg_name = self.vg.get_name()?;
+        Ok(Path::new("/dev").join(vg_name).join(self.try_name()?))
+    }
+
+    /// Get the current size in bytes of a logical volume, 0 for a stale
+    /// handle, see try_size
     pub fn get_size(&self) -> u64 {
-        unsafe { lvm_lv_get_size(self.handle) }
+        self.try_size().unwrap_or(0)
     }
 
-    pub fn get_tags(&self) -> LvmResult<Vec<String>> {
-        let mut names: Vec<String> = vec![];
-        unsafe {
-            let tag_head = lvm_lv_get_tags(self.handle);
-            let mut tag = dm_list_first(tag_head);
-            loop {
-                if tag.is_null() {
-                    break;
-                }
-                let str_list = tag as *mut lvm_str_list;
-                let name = CStr::from_ptr((*str_list).str)
-                    .to_string_lossy()
-                    .into_owned();
-                names.push(name);
-                tag = dm_list_next(tag_head, tag);
-            }
-        }
+    /// Get the current size in bytes of a logical volume
+    pub fn try_size(&self) -> LvmResult<u64> {
+        Ok(unsafe { lvm_lv_get_size(self.handle()?) })
+    }
 
-        Ok(names)
+    pub fn get_tags(&self) -> LvmResult<Vec<String>> {
+        self.handle()?;
+        Ok(self
+            .tags_iter()
+            .map(|tag| tag.to_string_lossy().into_owned())
+            .collect())
     }
 
     /// Get the current name of a logical volume
+    #[deprecated(since = "0.3.3", note = "use try_uuid, which reports failures")]
     pub fn get_uuid(&self) -> String {
-        unsafe {
-            let uuid = lvm_lv_get_uuid(self.handle);
+        self.with_handle(|handle| unsafe {
+            let uuid = lvm_lv_get_uuid(handle);
             let name = CStr::from_ptr(uuid).to_string_lossy();
 
             name.into_owned()
-        }
+        })
+    }
+
+    /// Get the uuid of a logical volume
+    pub fn try_uuid(&self) -> LvmResult<String> {
+        unsafe { checked_string(lvm_lv_get_uuid(self.handle()?), "LV uuid") }
     }
 
+    /// Check if the LV is active, false for a stale handle, see
+    /// try_is_active
     pub fn is_active(&self) -> bool {
-        unsafe {
-            let active = lvm_lv_is_active(self.handle);
-            active == 1
-        }
+        self.try_is_active().unwrap_or(false)
+    }
+
+    /// Check if the LV is active
+    pub fn try_is_active(&self) -> LvmResult<bool> {
+        Ok(unsafe { lvm_lv_is_active(self.handle()?) } == 1)
+    }
+
+    /// Get whether or not a logical volume is read only.  This is the
+    /// permission bit of the LV attributes so it also reports LVs that are
+    /// only activated read only.
+    pub fn is_read_only(&self) -> LvmResult<bool> {
+        Ok(matches!(
+            self.try_attributes()?.chars().nth(1),
+            Some('r') | Some('R')
+        ))
+    }
+
+    /// Make the logical volume read only or read write, ie: to hand a
+    /// golden image out to VMs.  liblvm can't change the permission so this
+    /// runs lvchange with the VG's lock released, leaving other handles
+    /// taken from the VG stale.
+    pub fn set_read_only(&mut self, read_only: bool) -> LvmResult<()> {
+        self.run_command(&[
+            "lvchange",
+            "--permission",
+            if read_only { "r" } else { "rw" },
+        ])?;
+        Ok(())
     }
 
+    /// Check if the LV is suspended, false for a stale handle, see
+    /// try_is_suspended
     pub fn is_suspended(&self) -> bool {
-        unsafe {
-            let suspended = lvm_lv_is_suspended(self.handle);
-            suspended == 1
-        }
+        self.try_is_suspended().unwrap_or(false)
+    }
+
+    /// Check if the LV is suspended
+    pub fn try_is_suspended(&self) -> LvmResult<bool> {
+        Ok(unsafe { lvm_lv_is_suspended(self.handle()?) } == 1)
+    }
+
+    /// Remove a logical volume from a volume group.
+    /// The handle is consumed as liblvm frees the LV on success.
+    /// Mounted LVs are refused with LvmError::InUse.
+    pub fn remove(mut self) -> LvmResult<()> {
+        self.check_not_mounted()?;
+        let name = self.try_name()?;
+        let handle = self.handle()?;
+        self.handle = ptr::null_mut();
+        self.lvm
+            .audited("lvm_vg_remove_lv", &name, &[], || unsafe {
+                self.check_retcode(lvm_vg_remove_lv(handle))
+            })?;
+        Ok(())
     }
 
-    /// Remove a logical volume from a volume group
-    pub fn remove(&self) -> LvmResult<()> {
-        unsafe {
-            let retcode = lvm_vg_remove_lv(self.handle);
-            self.check_retcode(retcode)?;
-            Ok(())
+    /// Scrub a logical volume according to policy and then remove it.
+    /// The LV is activated if needed so its device can be written.
+    pub fn remove_wiped(mut self, policy: &WipePolicy) -> LvmResult<()> {
+        match policy {
+            WipePolicy::None => {}
+            _ if self.lvm.is_test_mode() => {
+                info!("test mode, not wiping {}", self.try_name()?);
+            }
+            WipePolicy::Zero | WipePolicy::Discard => {
+                if !self.try_is_active()? {
+                    self.activate()?;
+                }
+                let path = self.path()?;
+                match policy {
+                    WipePolicy::Zero => blockdev::zero(&path, 0, self.try_size()?)?,
+                    _ => blockdev::discard(&path, 0, self.try_size()?)?,
+                }
+                self.deactivate()?;
+            }
         }
+        self.remove()
     }
 
-    pub fn remove_tag(&self, name: &str) -> LvmResult<()> {
-        let name = CString::new(name)?;
-        unsafe {
-            let retcode = lvm_lv_remove_tag(self.handle, name.as_ptr());
-            self.check_retcode(retcode)?;
-            self.vg.write()?;
-            Ok(())
+    pub fn remove_tag(&mut self, name: &str) -> LvmResult<()> {
+        self.change_tag("lvm_lv_remove_tag", name, lvm_lv_remove_tag)?;
+        self.vg.commit()
+    }
+
+    /// Remove several tags and commit with a single VG write
+    pub fn remove_tags(&mut self, names: &[&str]) -> LvmResult<()> {
+        validate_tags(names)?;
+        for name in names {
+            self.change_tag("lvm_lv_remove_tag", name, lvm_lv_remove_tag)?;
         }
+        self.vg.commit()
     }
 
-    pub fn rename(&self, new_name: &str) -> LvmResult<()> {
+    pub fn rename(&mut self, new_name: &str) -> LvmResult<()> {
+        let params = [("new_name", new_name.to_string())];
         let new_name = CString::new(new_name)?;
-        unsafe {
-            let retcode = lvm_lv_rename(self.handle, new_name.as_ptr());
-            self.check_retcode(retcode)?;
-        }
+        self.lvm
+            .audited("lvm_lv_rename", &self.try_name()?, &params, || unsafe {
+                self.check_retcode(lvm_lv_rename(self.handle()?, new_name.as_ptr()))
+            })?;
         Ok(())
     }
 
-    /// Resize logical volume to new_size bytes
-    pub fn resize(&self, new_size: u64) -> LvmResult<()> {
-        unsafe {
-            let retcode = lvm_lv_resize(self.handle, new_size);
-            self.check_retcode(retcode)?;
+    /// Resize logical volume to new_size bytes.
+    /// Shrinking a mounted LV is refused with LvmError::InUse.
+    pub fn resize(&mut self, new_size: u64) -> LvmResult<()> {
+        if new_size < self.try_size()? {
+            self.check_not_mounted()?;
         }
+        let params = [("size", new_size.to_string())];
+        self.lvm
+            .audited("lvm_lv_resize", &self.try_name()?, &params, || unsafe {
+                self.check_retcode(lvm_lv_resize(self.handle()?, new_size))
+            })?;
         Ok(())
     }
 
@@ -388,29 +1141,53 @@ impl<'a, 'b> LogicalVolume<'a, 'b> {
     /// Max snapshot space to use. If you pass zero the same amount of space
     /// as the origin will be used
     pub fn snapshot(
-        &self,
+        &mut self,
         snap_name: &str,
         max_snap_size: u64,
-    ) -> LvmResult<LogicalVolume<'_, '_>> {
+    ) -> LvmResult<CreatedLv<'_, '_>> {
+        let params = [
+            ("name", snap_name.to_string()),
+            ("max_snap_size", max_snap_size.to_string()),
+        ];
         let snap_name = CString::new(snap_name)?;
-        unsafe {
-            let lv_t = lvm_lv_snapshot(self.handle, snap_name.as_ptr(), max_snap_size);
-            if lv_t.is_null() {
-                let err = self.lvm.get_error()?;
-                return Err(LvmError::new((err.0, err.1)));
-            }
-            Ok({
-                LogicalVolume {
-                    handle: lv_t,
-                    lvm: self.lvm,
-                    vg: self.vg,
+        let lv = self
+            .lvm
+            .audited("lvm_lv_snapshot", &self.try_name()?, &params, || unsafe {
+                let lv_t = lvm_lv_snapshot(self.handle()?, snap_name.as_ptr(), max_snap_size);
+                if lv_t.is_null() {
+                    let err = self.lvm.get_error()?;
+                    return Err(LvmError::new((err.0, err.1)));
                 }
-            })
-        }
+                Ok({
+                    LogicalVolume {
+                        handle: lv_t,
+                        lvm: self.lvm,
+                        vg: self.vg,
+                        generation: self.generation,
+                    }
+                })
+            })?;
+        CreatedLv::new(lv)
     }
 }
 
 impl Lvm {
+    /// The liblvm handle, once any call abandoned on a worker thread has
+    /// finished with it.  liblvm isn't thread safe so every call waits.
+    fn handle(&self) -> lvm_t {
+        self.wait_for_abandoned();
+        self.handle
+    }
+
+    /// Wait for calls abandoned after a timeout or cancellation to finish
+    pub(crate) fn wait_for_abandoned(&self) {
+        let workers: Vec<_> = self.abandoned.borrow_mut().drain(..).collect();
+        for worker in workers {
+            debug!("waiting for an abandoned call to finish");
+            let _ = worker.join();
+        }
+    }
+
     fn check_retcode(&self, retcode: i32) -> LvmResult<()> {
         if retcode < 0 {
             let err = self.get_error()?;
@@ -419,19 +1196,60 @@ impl Lvm {
         Ok(())
     }
 
-    fn get_error(&self) -> LvmResult<(Errno, String)> {
-        let error = unsafe { lvm_errno(self.handle) };
+    fn read_error(&self) -> (i32, String) {
+        let error = unsafe { lvm_errno(self.handle()) };
         let msg = unsafe {
-            CStr::from_ptr(lvm_errmsg(self.handle))
+            CStr::from_ptr(lvm_errmsg(self.handle()))
                 .to_string_lossy()
                 .into_owned()
         };
+        (error, msg)
+    }
+
+    /// Forget liblvm's last error.  liblvm never resets it, so without this
+    /// a call that fails without setting one reports an earlier call's
+    /// error.  Every call through the crate does this first.
+    pub fn clear_error(&self) {
+        errno::set_errno(Errno(0));
+        *self.error_baseline.borrow_mut() = Some(self.read_error());
+    }
+
+    fn get_error(&self) -> LvmResult<(Errno, String)> {
+        let (error, msg) = self.read_error();
+        let stale = self.error_baseline.borrow().as_ref() == Some(&(error, msg.clone()));
+        if stale {
+            let errno = if error == 0 { libc::EIO } else { error };
+            if msg.is_empty() {
+                return Ok((Errno(errno), "liblvm gave no reason".to_string()));
+            }
+            return Ok((
+                Errno(errno),
+                format!("liblvm gave no new reason, its last error was: {}", msg),
+            ));
+        }
 
         Ok((Errno(error), msg))
     }
 
-    /// use system_dir to set an alternative LVM system directory
+    fn from_handle(handle: lvm_t) -> Lvm {
+        Lvm {
+            handle,
+            abandoned: RefCell::new(vec![]),
+            lock_retry: Cell::new(retry::RetryPolicy::none()),
+            test_mode: Cell::new(false),
+            config_overrides: RefCell::new(vec![]),
+            system_dir: None,
+            container_mode: Cell::new(false),
+            audit: RefCell::new(audit::AuditHook::default()),
+            error_baseline: RefCell::new(None),
+        }
+    }
+
+    /// use system_dir to set an alternative LVM system directory.
+    /// Fails with InsufficientPrivileges if the process isn't root and
+    /// lacks CAP_SYS_ADMIN, see check_privileges.
     pub fn new(system_dir: Option<&str>) -> LvmResult<Self> {
+        Lvm::check_privileges()?;
         match system_dir {
             Some(s) => {
                 let d = CString::new(s)?;
@@ -444,7 +1262,9 @@ impl Lvm {
                             "Memory allocation problem".into(),
                         )));
                     }
-                    Ok(Lvm { handle })
+                    let mut lvm = Lvm::from_handle(handle);
+                    lvm.system_dir = Some(PathBuf::from(s));
+                    Ok(lvm)
                 }
             }
             None => {
@@ -457,16 +1277,21 @@ impl Lvm {
                             "Memory allocation problem".into(),
                         )));
                     }
-                    Ok(Lvm { handle })
+                    Ok(Lvm::from_handle(handle))
                 }
             }
         }
     }
 
+    /// Check if a VG called name exists, without opening or locking it
+    pub fn vg_exists(&self, name: &str) -> LvmResult<bool> {
+        Ok(self.get_volume_group_names()?.iter().any(|n| n == name))
+    }
+
     pub fn get_volume_group_names(&self) -> LvmResult<Vec<String>> {
         let mut names: Vec<String> = vec![];
         unsafe {
-            let vg_names = lvm_list_vg_names(self.handle);
+            let vg_names = lvm_list_vg_names(self.handle());
             if vg_names.is_null() {
                 let err = self.get_error()?;
                 return Err(LvmError::new((err.0, err.1)));
@@ -491,7 +1316,7 @@ impl Lvm {
     pub fn get_volume_group_uuids(&self) -> LvmResult<Vec<Uuid>> {
         let mut ids: Vec<Uuid> = vec![];
         unsafe {
-            let vg_uuids = lvm_list_vg_uuids(self.handle);
+            let vg_uuids = lvm_list_vg_uuids(self.handle());
             if vg_uuids.is_null() {
                 let err = self.get_error()?;
                 return Err(LvmError::new((err.0, err.1)));
@@ -511,12 +1336,43 @@ impl Lvm {
         Ok(ids)
     }
 
+    /// Recreate the PV header on device with a known UUID, like
+    /// pvcreate --uuid --restorefile, so a VG can be restored with
+    /// vgcfgrestore after the header was lost.  restore_file is the metadata
+    /// backup, ie: /etc/lvm/backup/<vg>, the PV's layout is taken from.
+    /// Without one the PV is created with the default layout.
+    pub fn pv_create_with_uuid(
+        &self,
+        device: &Path,
+        uuid: &str,
+        restore_file: Option<&Path>,
+    ) -> LvmResult<()> {
+        let device = device.to_string_lossy();
+        let restore_file = restore_file.map(|f| f.to_string_lossy().into_owned());
+        let mut args = vec!["pvcreate", "--yes", "--uuid", uuid];
+        match restore_file {
+            Some(ref file) => args.extend_from_slice(&["--restorefile", file]),
+            None => args.push("--norestorefile"),
+        }
+        args.push(&device);
+        self.run_command(self.lock_retry.get(), &args)?;
+        Ok(())
+    }
+
+    /// Initialize a device as a PV.  Paths of multipath devices and md RAID
+    /// members are refused, see devices::block_device.
     pub fn pv_create(&self, name: &str, size: u64) -> LvmResult<()> {
+        devices::check_pv_candidate(Path::new(name))?;
         let name = CString::new(name)?;
-        unsafe {
-            let retcode = lvm_pv_create(self.handle, name.as_ptr(), size);
-            self.check_retcode(retcode)?;
-        }
+        retry::retry(self.lock_retry.get(), || {
+            let params = [("size", size.to_string())];
+            self.audited(
+                "lvm_pv_create",
+                &name.to_string_lossy(),
+                &params,
+                || unsafe { self.check_retcode(lvm_pv_create(self.handle(), name.as_ptr(), size)) },
+            )
+        })?;
         Ok(())
     }
 
@@ -525,17 +1381,18 @@ impl Lvm {
     /// locks are held for the PV list
     pub fn pv_remove(&self, name: &str) -> LvmResult<()> {
         let name = CString::new(name)?;
-        unsafe {
-            let retcode = lvm_pv_remove(self.handle, name.as_ptr());
-            self.check_retcode(retcode)?;
-        }
+        retry::retry(self.lock_retry.get(), || {
+            self.audited("lvm_pv_remove", &name.to_string_lossy(), &[], || unsafe {
+                self.check_retcode(lvm_pv_remove(self.handle(), name.as_ptr()))
+            })
+        })?;
         Ok(())
     }
 
     pub fn pv_create_params(&self, pv_name: &str) -> LvmResult<PhysicalVolumeCreateParameters<'_>> {
         let name = CString::new(pv_name)?;
         unsafe {
-            let pv_params = lvm_pv_params_create(self.handle, name.as_ptr());
+            let pv_params = lvm_pv_params_create(self.handle(), name.as_ptr());
             if pv_params.is_null() {
                 let err = self.get_error()?;
                 return Err(LvmError::new((err.0, err.1)));
@@ -548,20 +1405,120 @@ impl Lvm {
         }
     }
 
-    /// Scan all devices on the system for VGs and LVM metadata
-    pub fn scan(&self) -> LvmResult<()> {
+    /// Check if test mode is on, see set_test_mode
+    pub fn is_test_mode(&self) -> bool {
+        self.test_mode.get()
+    }
+
+    /// Turn test mode on or off, the equivalent of `lvm --test`.  While it
+    /// is on operations are validated and run as normal but metadata is
+    /// never written and LVs aren't wiped, so creating, resizing and
+    /// removing can be previewed safely.  Operations run through the lvm
+    /// tools that return the object they created fail to find it.
+    pub fn set_test_mode(&self, enabled: bool) -> LvmResult<()> {
+        let settings = if enabled {
+            vec!["global/test=1".to_string()]
+        } else {
+            vec![]
+        };
+        self.replace_overrides(&["global/test"], &settings)?;
+        self.reload_config()?;
+        self.test_mode.set(enabled);
+        Ok(())
+    }
+
+    /// Override a setting of this handle's configuration, ie:
+    /// devices/filter=["a|/dev/sdb|", "r|.*|"].  Values use lvm.conf
+    /// syntax and replace an earlier override of the same setting.  Takes
+    /// effect on the next reload_config.  Overrides are also passed with
+    /// --config to the lvm commands run for this handle.
+    pub fn config_override(&self, setting: &str) -> LvmResult<()> {
+        self.replace_overrides(&[], &[setting.to_string()])
+    }
+
+    /// Drop the overrides of the settings in paths and of those in
+    /// settings, add settings and hand liblvm the overrides that are left
+    fn replace_overrides(&self, paths: &[&str], settings: &[String]) -> LvmResult<()> {
+        let mut overrides = self.config_overrides.borrow().clone();
+        overrides.retain(|o| {
+            let path = override_path(o);
+            !paths.contains(&path) && !settings.iter().any(|s| override_path(s) == path)
+        });
+        overrides.extend(settings.iter().cloned());
+        let c_settings = CString::new(overrides.join(" "))?;
         unsafe {
-            let retcode = lvm_scan(self.handle);
+            let retcode = lvm_config_override(self.handle(), c_settings.as_ptr());
             self.check_retcode(retcode)?;
         }
+        *self.config_overrides.borrow_mut() = overrides;
         Ok(())
     }
 
-    ///Return the volume group name given a device name
-    pub fn vg_name_from_device(&self, device: &str) -> LvmResult<Option<String>> {
+    /// Override several settings given as (path, value) pairs, ie:
+    /// ("devices/filter", r#"["a|/dev/sdb|", "r|.*|"]"#), and reload the
+    /// configuration so they take effect
+    pub fn with_config_overrides(self, overrides: &[(&str, &str)]) -> LvmResult<Self> {
+        for (path, value) in overrides {
+            self.config_override(&format!("{}={}", path, value))?;
+        }
+        self.reload_config()?;
+        Ok(self)
+    }
+
+    /// Reload the configuration from lvm.conf, keeping any overrides, ie:
+    /// after lvm.conf was edited
+    pub fn reload_config(&self) -> LvmResult<()> {
+        unsafe {
+            let retcode = lvm_config_reload(self.handle());
+            self.check_retcode(retcode)?;
+        }
+        Ok(())
+    }
+
+    /// Look up a boolean setting, ie: global/use_lvmetad, returning
+    /// default if it isn't set
+    pub fn config_find_bool(&self, path: &str, default: bool) -> LvmResult<bool> {
+        let path = CString::new(path)?;
+        let value = unsafe { lvm_config_find_bool(self.handle(), path.as_ptr(), default as i32) };
+        Ok(value != 0)
+    }
+
+    /// Scan all devices on the system for VGs and LVM metadata
+    pub fn scan(&self) -> LvmResult<()> {
+        retry::retry(self.lock_retry.get(), || {
+            self.ffi_call("lvm_scan", "", &[], || unsafe {
+                self.check_retcode(lvm_scan(self.handle()))
+            })
+        })?;
+        Ok(())
+    }
+
+    /// Rescan a single device for LVM metadata, see scan_devices
+    pub fn scan_device(&self, device: &Path) -> LvmResult<()> {
+        self.scan_devices(&[device])
+    }
+
+    /// Rescan just devices for LVM metadata, like pvscan --cache, which is
+    /// much quicker than scan on systems with many devices
+    pub fn scan_devices(&self, devices: &[&Path]) -> LvmResult<()> {
+        if devices.is_empty() {
+            return Ok(());
+        }
+        let names: Vec<String> = devices
+            .iter()
+            .map(|d| d.to_string_lossy().into_owned())
+            .collect();
+        let mut args = vec!["pvscan", "--cache"];
+        args.extend(names.iter().map(|n| n.as_str()));
+        self.run_command(self.lock_retry.get(), &args)?;
+        Ok(())
+    }
+
+    ///Return the volume group name given a device name
+    pub fn vg_name_from_device(&self, device: &str) -> LvmResult<Option<String>> {
         let device = CString::new(device)?;
         unsafe {
-            let id = lvm_vgname_from_device(self.handle, device.as_ptr());
+            let id = lvm_vgname_from_device(self.handle(), device.as_ptr());
             if id.is_null() {
                 return Ok(None);
             }
@@ -574,7 +1531,7 @@ impl Lvm {
     pub fn vg_name_from_pvid(&self, pvid: &Uuid) -> LvmResult<Option<String>> {
         let pvid = CString::new(pvid.as_bytes().to_vec())?;
         unsafe {
-            let id = lvm_vgname_from_pvid(self.handle, pvid.as_ptr());
+            let id = lvm_vgname_from_pvid(self.handle(), pvid.as_ptr());
             if id.is_null() {
                 return Ok(None);
             }
@@ -582,13 +1539,52 @@ impl Lvm {
             Ok(Some(name))
         }
     }
+    /// Check that a tag only uses the characters lvm allows,
+    /// A-Z a-z 0-9 _ + . - / = ! : & #, doesn't start with a '-' and isn't
+    /// too long
+    pub fn tag_validate(&self, name: &str) -> LvmResult<()> {
+        validate_tag(name)
+    }
+
+    /// Run an lvm command, in test mode if it's on and retrying according
+    /// to policy if it can't get a lock
+    fn run_command(&self, policy: retry::RetryPolicy, args: &[&str]) -> LvmResult<String> {
+        let full_args = self.command_args(args);
+        let full_args: Vec<&str> = full_args.iter().map(|a| a.as_str()).collect();
+        self.audited_command(args, || {
+            retry::retry(policy, || cli::run(self.system_dir.as_deref(), &full_args))
+        })
+    }
+
+    /// The full argument list run_command would run for args, with the
+    /// test mode flag and config overrides added
+    fn command_args(&self, args: &[&str]) -> Vec<String> {
+        let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        if self.is_test_mode() {
+            args.insert(1, "--test".to_string());
+        }
+        let overrides = self.config_overrides.borrow().join(" ");
+        if !overrides.is_empty() {
+            args.insert(1, "--config".to_string());
+            args.insert(2, overrides);
+        }
+        args
+    }
+
+    /// Merge the VG src into dest, moving all of src's PVs and LVs, like
+    /// vgmerge.  src must be inactive and neither VG may be open.
+    pub fn vg_merge(&self, dest: &str, src: &str) -> LvmResult<()> {
+        self.run_command(self.lock_retry.get(), &["vgmerge", dest, src])?;
+        Ok(())
+    }
+
     ///  This function checks that the name has no invalid characters,
     /// the length doesn't exceed maximum and that the VG name isn't already in use
     /// and that the name adheres to any other limitations.
     pub fn vg_name_validate(&self, name: &str) -> LvmResult<()> {
         let name = CString::new(name)?;
         unsafe {
-            let retcode = lvm_vg_name_validate(self.handle, name.as_ptr());
+            let retcode = lvm_vg_name_validate(self.handle(), name.as_ptr());
             self.check_retcode(retcode)?;
         }
         Ok(())
@@ -601,33 +1597,25 @@ impl Lvm {
     /// release the VG handle.
     pub fn vg_create(&self, name: &str) -> LvmResult<VolumeGroup<'_>> {
         let name = CString::new(name)?;
-        unsafe {
-            let vg_t = lvm_vg_create(self.handle, name.as_ptr());
+        self.ffi_call("lvm_vg_create", &name.to_string_lossy(), &[], || unsafe {
+            let vg_t = lvm_vg_create(self.handle(), name.as_ptr());
             if vg_t.is_null() {
                 let err = self.get_error()?;
                 return Err(LvmError::new((err.0, err.1)));
             }
             Ok(VolumeGroup {
-                handle: vg_t,
+                handle: Cell::new(vg_t),
                 lvm: &self,
+                mode: OpenMode::Write,
+                lock_retry: None,
+                generation: Cell::new(0),
+                pins: Cell::new(0),
             })
-        }
+        })
     }
 
     pub fn vg_open(&self, name: &str, mode: &OpenMode) -> LvmResult<VolumeGroup<'_>> {
-        let name = CString::new(name)?;
-        let mode = CString::new(mode.to_string())?;
-        unsafe {
-            let vg_handle = lvm_vg_open(self.handle, name.as_ptr(), mode.as_ptr(), 0);
-            if vg_handle.is_null() {
-                let err = self.get_error()?;
-                return Err(LvmError::new((err.0, err.1)));
-            }
-            Ok(VolumeGroup {
-                handle: vg_handle,
-                lvm: &self,
-            })
-        }
+        self.vg_open_with_options(name, &vgopen::VgOpenOptions::new(*mode))
     }
 }
 
@@ -660,6 +1648,54 @@ impl<'a> PhysicalVolumeCreateParameters<'a> {
 }
 
 impl<'a> PhysicalVolume<'a> {
+    /// The liblvm handle, once any call abandoned on a worker thread has
+    /// finished with it.  Fails with EBADF once an lvm command run through
+    /// another handle reopened the VG, freeing this one.
+    fn handle(&self) -> LvmResult<pv_t> {
+        self.vg.handle()?;
+        if self.generation != self.vg.generation.get() {
+            return Err(LvmError::new((
+                Errno(libc::EBADF),
+                "The PV handle is stale, an lvm command reopened its VG, look it up again"
+                    .to_string(),
+            )));
+        }
+        Ok(self.handle)
+    }
+
+    /// Call f with the handle, or return the default for a stale handle
+    fn with_handle<T: Default, F: FnOnce(pv_t) -> T>(&self, f: F) -> T {
+        match self.handle() {
+            Ok(handle) => f(handle),
+            Err(_) => T::default(),
+        }
+    }
+
+    /// Run an lvm command on the PV, given by its device after args,
+    /// through VolumeGroup::run_command and find the PV again in the
+    /// reopened VG by its uuid
+    fn run_command(&mut self, args: &[&str]) -> LvmResult<String> {
+        let uuid = CString::new(self.try_uuid()?)?;
+        let device = self.try_name()?;
+        let mut args = args.to_vec();
+        args.push(&device);
+        let output = self.vg.run_command(&args);
+        // The VG was reopened even if the command failed
+        let found = self.vg.handle().and_then(|vg| unsafe {
+            let pv_t = lvm_pv_from_uuid(vg, uuid.as_ptr());
+            if pv_t.is_null() {
+                let err = self.lvm.get_error()?;
+                return Err(LvmError::new((err.0, err.1)));
+            }
+            self.handle = pv_t;
+            self.generation = self.vg.generation.get();
+            Ok(())
+        });
+        let output = output?;
+        found?;
+        Ok(output)
+    }
+
     fn check_retcode(&self, retcode: i32) -> LvmResult<()> {
         if retcode < 0 {
             let err = self.lvm.get_error()?;
@@ -668,78 +1704,228 @@ impl<'a> PhysicalVolume<'a> {
         Ok(())
     }
 
+    fn get_integer_property(&self, name: &str) -> LvmResult<u64> {
+        let prop_name = CString::new(name)?;
+        unsafe {
+            let value = lvm_pv_get_property(self.handle()?, prop_name.as_ptr());
+            if value.is_valid() == 0 {
+                let err = self.lvm.get_error()?;
+                return Err(LvmError::new((err.0, err.1)));
+            }
+            if value.is_integer() == 0 {
+                return Err(LvmError::new((
+                    Errno(libc::EINVAL),
+                    format!("Property {} is not an integer", name),
+                )));
+            }
+            Ok(value.value.integer)
+        }
+    }
+
+    fn get_string_property(&self, name: &str) -> LvmResult<String> {
+        let prop_name = CString::new(name)?;
+        unsafe {
+            let value = lvm_pv_get_property(self.handle()?, prop_name.as_ptr());
+            if value.is_valid() == 0 {
+                let err = self.lvm.get_error()?;
+                return Err(LvmError::new((err.0, err.1)));
+            }
+            if value.is_string() == 0 || value.value.string.is_null() {
+                return Err(LvmError::new((
+                    Errno(libc::EINVAL),
+                    format!("Property {} is not a string", name),
+                )));
+            }
+            let s = CStr::from_ptr(value.value.string).to_string_lossy();
+            Ok(s.into_owned())
+        }
+    }
+
     /// Get the current size in bytes of a device underlying a
-    /// physical volume
+    /// physical volume, 0 for a stale handle
     pub fn get_dev_size(&self) -> u64 {
-        unsafe { lvm_pv_get_dev_size(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_pv_get_dev_size(handle) })
     }
 
-    /// Get the current unallocated space in bytes of a physical volume
+    /// Get the current unallocated space in bytes of a physical volume,
+    /// 0 for a stale handle
     pub fn get_free(&self) -> u64 {
-        unsafe { lvm_pv_get_free(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_pv_get_free(handle) })
     }
 
-    /// Get the current number of metadata areas in the physical volume
+    /// Get the current number of metadata areas in the physical volume,
+    /// 0 for a stale handle
     pub fn get_mda_count(&self) -> u64 {
-        unsafe { lvm_pv_get_mda_count(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_pv_get_mda_count(handle) })
     }
 
     /// Get the current name of a physical volume
+    #[deprecated(since = "0.3.3", note = "use try_name, which reports failures")]
     pub fn get_name(&self) -> String {
-        unsafe {
-            let name = lvm_pv_get_name(self.handle);
+        self.with_handle(|handle| unsafe {
+            let name = lvm_pv_get_name(handle);
             CStr::from_ptr(name).to_string_lossy().into_owned()
-        }
+        })
+    }
+
+    /// Get the current name of a physical volume, the path of its device
+    pub fn try_name(&self) -> LvmResult<String> {
+        unsafe { checked_string(lvm_pv_get_name(self.handle()?), "PV name") }
     }
 
-    /// Get the current size in bytes of a physical volume
+    /// Get the current size in bytes of a physical volume, 0 for a stale
+    /// handle
     pub fn get_size(&self) -> u64 {
-        unsafe { lvm_pv_get_size(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_pv_get_size(handle) })
     }
 
-    /*
-    pub fn get_property(&self, name: &str) -> LvmResult<PhysicalVolumeCreateParameters> {
-        let name = CString::new(name)?;
-        unsafe {
-            let val = lvm_pv_get_property(self.handle, name.as_ptr());
-            if val.is_valid() != 0 {
-                let err = self.lvm.get_error()?;
-                return Err(LvmError::new((err.0, err.1)));
-            }
-            Ok(PhysicalVolumeCreateParameters {})
+    /// Get the free space in bytes of the smallest metadata area
+    pub fn mda_free(&self) -> LvmResult<u64> {
+        self.get_integer_property("pv_mda_free")
+    }
+
+    /// Get the offset in bytes of the first physical extent from the start
+    /// of the device
+    pub fn pe_start(&self) -> LvmResult<u64> {
+        self.get_integer_property("pe_start")
+    }
+
+    /// Get the offset in bytes of the bootloader area, zero if there isn't
+    /// one
+    pub fn ba_start(&self) -> LvmResult<u64> {
+        self.get_integer_property("pv_ba_start")
+    }
+
+    /// Get the size in bytes of the bootloader area
+    pub fn ba_size(&self) -> LvmResult<u64> {
+        self.get_integer_property("pv_ba_size")
+    }
+
+    /// Check if the device backing a physical volume is missing
+    pub fn is_missing(&self) -> LvmResult<bool> {
+        let attr = self.get_string_property("pv_attr")?;
+        Ok(attr.chars().nth(2) == Some('m'))
+    }
+
+    pub fn get_tags(&self) -> LvmResult<Vec<String>> {
+        self.handle()?;
+        Ok(self
+            .tags_iter()
+            .map(|tag| tag.to_string_lossy().into_owned())
+            .collect())
+    }
+
+    /// Add a tag to a physical volume.  liblvm can't change PVs so this
+    /// runs pvchange, which writes the VG metadata itself, leaving other
+    /// handles taken from the VG stale.
+    pub fn add_tag(&mut self, tag: &str) -> LvmResult<()> {
+        self.add_tags(&[tag])
+    }
+
+    /// Add several tags with a single pvchange.  All tags are validated
+    /// before any are added.
+    pub fn add_tags(&mut self, tags: &[&str]) -> LvmResult<()> {
+        self.change_tags("--addtag", tags)
+    }
+
+    /// Remove a tag from a physical volume, see add_tag
+    pub fn remove_tag(&mut self, tag: &str) -> LvmResult<()> {
+        self.remove_tags(&[tag])
+    }
+
+    /// Remove several tags with a single pvchange
+    pub fn remove_tags(&mut self, tags: &[&str]) -> LvmResult<()> {
+        self.change_tags("--deltag", tags)
+    }
+
+    fn change_tags(&mut self, flag: &str, tags: &[&str]) -> LvmResult<()> {
+        validate_tags(tags)?;
+        let mut args = vec!["pvchange"];
+        for tag in tags {
+            args.extend_from_slice(&[flag, tag]);
         }
+        self.run_command(&args)?;
+        Ok(())
+    }
+
+    /// Check if new extents may be allocated on a physical volume
+    pub fn is_allocatable(&self) -> LvmResult<bool> {
+        let attr = self.get_string_property("pv_attr")?;
+        Ok(attr.starts_with('a'))
     }
-    */
 
+    /// Allow or prevent allocation of new extents on the physical volume,
+    /// like pvchange -x.  Stopping allocation is the first step of draining
+    /// a disk before moving its extents off with pvmove.  Extents already
+    /// on the PV are left alone.  Runs pvchange with the VG's lock
+    /// released, leaving other handles taken from the VG stale.
+    pub fn set_allocatable(&mut self, allocatable: bool) -> LvmResult<()> {
+        self.run_command(&[
+            "pvchange",
+            "--allocatable",
+            if allocatable { "y" } else { "n" },
+        ])?;
+        Ok(())
+    }
+
+    #[deprecated(since = "0.3.3", note = "use try_uuid, which reports failures")]
     pub fn get_uuid(&self) -> String {
-        unsafe {
-            let id = lvm_pv_get_uuid(self.handle);
+        self.with_handle(|handle| unsafe {
+            let id = lvm_pv_get_uuid(handle);
             let tmp = CStr::from_ptr(id).to_string_lossy();
             tmp.into_owned()
-        }
+        })
     }
 
-    pub fn resize(&self, new_size: u64) -> LvmResult<()> {
-        unsafe {
-            let retcode = lvm_pv_resize(self.handle, new_size);
-            self.check_retcode(retcode)?;
-        }
+    /// Get the uuid of a physical volume
+    pub fn try_uuid(&self) -> LvmResult<String> {
+        unsafe { checked_string(lvm_pv_get_uuid(self.handle()?), "PV uuid") }
+    }
+
+    /// Resize a PV to new_size bytes.  Shrinking isn't checked against
+    /// allocated extents, see resize_checked.
+    pub fn resize(&mut self, new_size: u64) -> LvmResult<()> {
+        let params = [("size", new_size.to_string())];
+        self.lvm
+            .audited("lvm_pv_resize", &self.try_name()?, &params, || unsafe {
+                self.check_retcode(lvm_pv_resize(self.handle()?, new_size))
+            })?;
         Ok(())
     }
 }
 
 impl<'a> VolumeGroup<'a> {
     /// Add a tag to a VG
-    pub fn add_tag(&self, tag: &str) -> LvmResult<()> {
-        let tag = CString::new(tag)?;
-        unsafe {
-            let retcode = lvm_vg_add_tag(self.handle, tag.as_ptr());
-            self.check_retcode(retcode)?;
-        }
-        self.write()?;
+    pub fn add_tag(&mut self, tag: &str) -> LvmResult<()> {
+        self.change_tag("lvm_vg_add_tag", tag, lvm_vg_add_tag)?;
+        self.commit()?;
         Ok(())
     }
 
+    /// Add several tags to a VG and commit them with a single write.  All
+    /// tags are validated before any are added.
+    pub fn add_tags(&mut self, tags: &[&str]) -> LvmResult<()> {
+        validate_tags(tags)?;
+        for tag in tags {
+            self.change_tag("lvm_vg_add_tag", tag, lvm_vg_add_tag)?;
+        }
+        self.commit()
+    }
+
+    /// Add or remove a tag through f, auditing the call
+    fn change_tag(
+        &self,
+        op: &'static str,
+        tag: &str,
+        f: unsafe extern "C" fn(vg_t, *const libc::c_char) -> libc::c_int,
+    ) -> LvmResult<()> {
+        let params = [("tag", tag.to_string())];
+        let tag = CString::new(tag)?;
+        self.lvm.audited(op, &self.get_name()?, &params, || unsafe {
+            self.check_retcode(f(self.handle()?, tag.as_ptr()))
+        })
+    }
+
     fn check_retcode(&self, retcode: i32) -> LvmResult<()> {
         if retcode < 0 {
             let err = self.lvm.get_error()?;
@@ -748,12 +1934,114 @@ impl<'a> VolumeGroup<'a> {
         Ok(())
     }
 
-    /// Close a VG
-    pub fn close(&self) -> LvmResult<()> {
+    /// The liblvm handle, failing with EBADF once a failed reopen in
+    /// run_command left the VG closed.  Every liblvm call on the VG goes
+    /// through here so none is handed a closed handle, or made while a call
+    /// abandoned on a worker thread is still using it.
+    fn handle(&self) -> LvmResult<vg_t> {
+        self.lvm.wait_for_abandoned();
+        let handle = self.handle.get();
+        if handle.is_null() {
+            return Err(LvmError::new((
+                Errno(libc::EBADF),
+                "The VG was closed because reopening it after an lvm command failed, \
+                 open it again"
+                    .to_string(),
+            )));
+        }
+        Ok(handle)
+    }
+
+    /// Run f with the liblvm handle, for getters that can't fail.  They
+    /// return 0, false or an empty string for a closed VG.
+    fn with_handle<T: Default, F: FnOnce(vg_t) -> T>(&self, f: F) -> T {
+        match self.handle() {
+            Ok(handle) => f(handle),
+            Err(_) => T::default(),
+        }
+    }
+
+    /// Close a VG.
+    /// The handle is consumed so it can't be used or closed again on drop.
+    pub fn close(self) -> LvmResult<()> {
+        let name = self.get_name()?;
+        let handle = self.handle.replace(ptr::null_mut());
+        self.lvm.ffi_call("lvm_vg_close", &name, &[], || unsafe {
+            self.check_retcode(lvm_vg_close(handle))
+        })?;
+        Ok(())
+    }
+
+    fn get_integer_property(&self, name: &str) -> LvmResult<u64> {
+        let prop_name = CString::new(name)?;
         unsafe {
-            let retcode = lvm_vg_close(self.handle);
-            self.check_retcode(retcode)?;
+            let value = lvm_vg_get_property(self.handle()?, prop_name.as_ptr());
+            if value.is_valid() == 0 {
+                let err = self.lvm.get_error()?;
+                return Err(LvmError::new((err.0, err.1)));
+            }
+            if value.is_integer() == 0 {
+                return Err(LvmError::new((
+                    Errno(libc::EINVAL),
+                    format!("Property {} is not an integer", name),
+                )));
+            }
+            Ok(value.value.integer)
         }
+    }
+
+    /// Commit in-memory metadata changes made through this VG or one of
+    /// its LVs to disk
+    fn commit(&self) -> LvmResult<()> {
+        let name = self.get_name()?;
+        retry::retry(self.lock_retry(), || {
+            self.lvm.audited("lvm_vg_write", &name, &[], || unsafe {
+                self.check_retcode(lvm_vg_write(self.handle()?))
+            })
+        })?;
+        Ok(())
+    }
+
+    /// Run an lvm command for an operation liblvm doesn't support.
+    /// liblvm holds the VG lock for as long as the handle is open so the
+    /// handle is closed while the command runs and then reopened to pick up
+    /// the new metadata. If the VG can't be reopened the error is returned
+    /// and every later call on the VG fails with EBADF.  Reopening frees the
+    /// LV and PV handles taken from the VG before, calls on those fail with
+    /// EBADF too, except for the one running the command which finds itself
+    /// again.  Fails with EBUSY while strings borrowed from the VG are
+    /// alive, see borrowed.rs.
+    fn run_command(&self, args: &[&str]) -> LvmResult<String> {
+        let name = self.get_name()?;
+        self.close_handle(&name)?;
+        let output = self.lvm.run_command(self.lock_retry(), args);
+        self.reopen(&name)?;
+        output
+    }
+
+    /// Close the liblvm handle, releasing the VG lock, while keeping self
+    /// around to reopen
+    fn close_handle(&self, name: &str) -> LvmResult<()> {
+        if self.pins.get() > 0 {
+            return Err(LvmError::new((
+                Errno(libc::EBUSY),
+                format!(
+                    "Can't reopen VG {} while strings borrowed from it are alive",
+                    name
+                ),
+            )));
+        }
+        let handle = self.handle.replace(ptr::null_mut());
+        self.generation.set(self.generation.get() + 1);
+        self.lvm.ffi_call("lvm_vg_close", name, &[], || unsafe {
+            self.check_retcode(lvm_vg_close(handle))
+        })
+    }
+
+    /// Open the VG called name again in the same mode after close_handle
+    fn reopen(&self, name: &str) -> LvmResult<()> {
+        let reopened = self.lvm.vg_open(name, &self.mode)?;
+        self.handle.set(reopened.handle.replace(ptr::null_mut()));
         Ok(())
     }
 
@@ -761,7 +2049,7 @@ impl<'a> VolumeGroup<'a> {
     pub fn list_lvs(&self) -> LvmResult<Vec<LogicalVolume<'_, '_>>> {
         let mut lvs: Vec<LogicalVolume<'_, '_>> = vec![];
         unsafe {
-            let lv_head = lvm_vg_list_lvs(self.handle);
+            let lv_head = lvm_vg_list_lvs(self.handle()?);
             let mut lv = dm_list_first(lv_head);
             loop {
                 if lv.is_null() {
@@ -772,6 +2060,7 @@ impl<'a> VolumeGroup<'a> {
                     handle: (*lv_list).lv,
                     lvm: self.lvm,
                     vg: self,
+                    generation: self.generation.get(),
                 });
                 lv = dm_list_next(lv_head, lv);
             }
@@ -784,7 +2073,7 @@ impl<'a> VolumeGroup<'a> {
     pub fn list_pvs(&self) -> LvmResult<Vec<PhysicalVolume<'_>>> {
         let mut pvs: Vec<PhysicalVolume<'_>> = vec![];
         unsafe {
-            let pv_head = lvm_vg_list_pvs(self.handle);
+            let pv_head = lvm_vg_list_pvs(self.handle()?);
             let mut pv = dm_list_first(pv_head);
             loop {
                 if pv.is_null() {
@@ -794,6 +2083,8 @@ impl<'a> VolumeGroup<'a> {
                 pvs.push(PhysicalVolume {
                     handle: (*pv_list).pv,
                     lvm: self.lvm,
+                    vg: self,
+                    generation: self.generation.get(),
                 });
                 pv = dm_list_next(pv_head, pv);
             }
@@ -803,20 +2094,81 @@ impl<'a> VolumeGroup<'a> {
     }
 
     /// Create a linear logical volume
-    pub fn create_lv_linear(&self, name: &str, size: u64) -> LvmResult<LogicalVolume<'_, '_>> {
+    pub fn create_lv_linear(&mut self, name: &str, size: u64) -> LvmResult<CreatedLv<'_, '_>> {
+        let vg = &*self;
         let name = CString::new(name)?;
-        unsafe {
-            let lv_t = lvm_vg_create_lv_linear(self.handle, name.as_ptr(), size);
-            if lv_t.is_null() {
-                let err = self.lvm.get_error()?;
-                return Err(LvmError::new((err.0, err.1)));
-            }
-            Ok(LogicalVolume {
-                handle: lv_t,
-                lvm: self.lvm,
-                vg: self,
-            })
-        }
+        let lv = vg.lvm.audited(
+            "lvm_vg_create_lv_linear",
+            &name.to_string_lossy(),
+            &[("size", size.to_string())],
+            || unsafe {
+                let lv_t = lvm_vg_create_lv_linear(vg.handle()?, name.as_ptr(), size);
+                if lv_t.is_null() {
+                    let err = vg.lvm.get_error()?;
+                    return Err(LvmError::new((err.0, err.1)));
+                }
+                Ok(LogicalVolume {
+                    handle: lv_t,
+                    lvm: vg.lvm,
+                    vg,
+                    generation: vg.generation.get(),
+                })
+            },
+        )?;
+        CreatedLv::new(lv)
+    }
+
+    /// Create a mirrored logical volume with mirror_count additional copies
+    pub fn create_lv_mirror(
+        &mut self,
+        name: &str,
+        size: u64,
+        mirror_count: u32,
+        log: &MirrorLog,
+    ) -> LvmResult<LogicalVolume<'_, '_>> {
+        let vg_name = self.get_name()?;
+        self.run_command(&[
+            "lvcreate",
+            "--type",
+            "mirror",
+            "--mirrors",
+            &mirror_count.to_string(),
+            "--mirrorlog",
+            log.as_arg(),
+            "--size",
+            &format!("{}b", size),
+            "--name",
+            name,
+            &vg_name,
+        ])?;
+        self.lv_from_name(name)
+    }
+
+    /// Add count mirror legs to a mirrored logical volume.  The new legs
+    /// sync in the background, see LogicalVolume::mirror_sync_percent
+    pub fn add_mirror_legs(&mut self, lv_name: &str, count: u32) -> LvmResult<()> {
+        let lv_path = format!("{}/{}", self.get_name()?, lv_name);
+        self.run_command(&[
+            "lvconvert",
+            "--background",
+            "--mirrors",
+            &format!("+{}", count),
+            &lv_path,
+        ])?;
+        Ok(())
+    }
+
+    /// Remove count mirror legs from a mirrored logical volume
+    pub fn remove_mirror_legs(&mut self, lv_name: &str, count: u32) -> LvmResult<()> {
+        let lv_path = format!("{}/{}", self.get_name()?, lv_name);
+        self.run_command(&[
+            "lvconvert",
+            "--yes",
+            "--mirrors",
+            &format!("-{}", count),
+            &lv_path,
+        ])?;
+        Ok(())
     }
 
     /// Create a thinpool parameter passing object for the specified VG
@@ -831,7 +2183,7 @@ impl<'a> VolumeGroup<'a> {
     ///
     /// Note: Passdown discard policy is the default.
     pub fn create_thin_pool(
-        &self,
+        &mut self,
         pool_name: &str,
         size: u64,
         chunk_size: u32,
@@ -844,32 +2196,51 @@ impl<'a> VolumeGroup<'a> {
             LvmThinPolicy::NoPassdown => lvm_thin_discards_t_LVM_THIN_DISCARDS_NO_PASSDOWN,
             LvmThinPolicy::Passdown => lvm_thin_discards_t_LVM_THIN_DISCARDS_PASSDOWN,
         };
-        unsafe {
-            let create_params = lvm_lv_params_create_thin_pool(
-                self.handle,
-                pool_name.as_ptr(),
-                size,
-                chunk_size,
-                metadata_size,
-                discard,
-            );
-            if create_params.is_null() {
-                let err = self.lvm.get_error()?;
-                return Err(LvmError::new((err.0, err.1)));
-            }
-        }
+        let params = [
+            ("size", size.to_string()),
+            ("chunk_size", chunk_size.to_string()),
+            ("metadata_size", metadata_size.to_string()),
+        ];
+        self.lvm.audited(
+            "lvm_lv_params_create_thin_pool",
+            &pool_name.to_string_lossy(),
+            &params,
+            || unsafe {
+                let create_params = lvm_lv_params_create_thin_pool(
+                    self.handle()?,
+                    pool_name.as_ptr(),
+                    size,
+                    chunk_size,
+                    metadata_size,
+                    discard,
+                );
+                if create_params.is_null() {
+                    let err = self.lvm.get_error()?;
+                    return Err(LvmError::new((err.0, err.1)));
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Extend a VG by adding a device.  Paths of multipath devices and md
+    /// RAID members are refused, see devices::block_device.
+    pub fn extend(&mut self, device: &Path) -> LvmResult<()> {
+        self.extend_uncommitted(device)?;
+        self.commit()?;
         Ok(())
     }
 
-    /// Extend a VG by adding a device
-    pub fn extend(&self, device: &Path) -> LvmResult<()> {
+    /// Add a device to the in memory VG without writing it
+    fn extend_uncommitted(&mut self, device: &Path) -> LvmResult<()> {
+        devices::check_pv_candidate(device)?;
         let dev = CString::new(device.to_string_lossy().as_bytes())?;
-        unsafe {
-            let retcode = lvm_vg_extend(self.handle, dev.as_ptr());
-            self.check_retcode(retcode)?;
-        }
-        self.write()?;
-        Ok(())
+        let vg_name = self.get_name()?;
+        let params = [("device", device.to_string_lossy().into_owned())];
+        self.lvm
+            .audited("lvm_vg_extend", &vg_name, &params, || unsafe {
+                self.check_retcode(lvm_vg_extend(self.handle()?, dev.as_ptr()))
+            })
     }
 
     /// Get the current metadata sequence number of a volume group.
@@ -877,120 +2248,133 @@ impl<'a> VolumeGroup<'a> {
     /// Applications may use the sequence number to determine if any LVM objects
     /// have changed from a prior query.
     pub fn get_seq_number(&self) -> u64 {
-        unsafe { lvm_vg_get_seqno(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_vg_get_seqno(handle) })
     }
 
     /// Get the current name of a volume group
     pub fn get_name(&self) -> LvmResult<String> {
-        unsafe {
-            let uid = lvm_vg_get_name(self.handle);
-            let tmp = CStr::from_ptr(uid).to_string_lossy();
-
-            Ok(tmp.into_owned())
-        }
+        unsafe { checked_string(lvm_vg_get_name(self.handle()?), "VG name") }
     }
 
     /// Get the current number of total extents of a volume group
     pub fn get_extent_count(&self) -> u64 {
-        unsafe { lvm_vg_get_extent_count(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_vg_get_extent_count(handle) })
     }
 
     /// Get the current extent size in bytes of a volume group
     pub fn get_extent_size(&self) -> u64 {
-        unsafe { lvm_vg_get_extent_size(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_vg_get_extent_size(handle) })
     }
 
     /// Get the current number of free extents of a volume group
     pub fn get_free_extents(&self) -> u64 {
-        unsafe { lvm_vg_get_free_extent_count(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_vg_get_free_extent_count(handle) })
     }
 
     /// Get the current unallocated space in bytes of a volume group
     pub fn get_free_size(&self) -> u64 {
-        unsafe { lvm_vg_get_free_size(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_vg_get_free_size(handle) })
     }
 
     /// Get the maximum number of logical volumes allowed in a volume group
     pub fn get_max_lv(&self) -> u64 {
-        unsafe { lvm_vg_get_max_lv(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_vg_get_max_lv(handle) })
     }
 
     /// Get the maximum number of physical volumes allowed in a volume group
     pub fn get_max_pv(&self) -> u64 {
-        unsafe { lvm_vg_get_max_pv(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_vg_get_max_pv(handle) })
+    }
+
+    /// Get the number of metadata areas on the PVs of a volume group
+    pub fn get_mda_count(&self) -> LvmResult<u64> {
+        self.get_integer_property("vg_mda_count")
+    }
+
+    /// Get the number of metadata areas lvm keeps metadata in
+    pub fn get_mda_used_count(&self) -> LvmResult<u64> {
+        self.get_integer_property("vg_mda_used_count")
+    }
+
+    /// Get the free space in bytes of the smallest metadata area
+    pub fn get_mda_free(&self) -> LvmResult<u64> {
+        self.get_integer_property("vg_mda_free")
+    }
+
+    /// Get the size in bytes of the smallest metadata area
+    pub fn get_mda_size(&self) -> LvmResult<u64> {
+        self.get_integer_property("vg_mda_size")
+    }
+
+    /// Get the target number of metadata copies, zero meaning unmanaged
+    pub fn get_mda_copies(&self) -> LvmResult<u64> {
+        self.get_integer_property("vg_mda_copies")
     }
 
     /// Get the current number of physical volumes of a volume group
     pub fn get_pv_count(&self) -> u64 {
-        unsafe { lvm_vg_get_pv_count(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_vg_get_pv_count(handle) })
     }
 
     /// Get the current size in bytes of a volume group
     pub fn get_size(&self) -> u64 {
-        unsafe { lvm_vg_get_size(self.handle) }
+        self.with_handle(|handle| unsafe { lvm_vg_get_size(handle) })
     }
 
     pub fn get_tags(&self) -> LvmResult<Vec<String>> {
-        let mut names: Vec<String> = vec![];
-        unsafe {
-            let tag_head = lvm_vg_get_tags(self.handle);
-            let mut tag = dm_list_first(tag_head);
-            loop {
-                if tag.is_null() {
-                    break;
-                }
-                let str_list = tag as *mut lvm_str_list;
-                let name = CStr::from_ptr((*str_list).str)
-                    .to_string_lossy()
-                    .into_owned();
-                names.push(name);
-                tag = dm_list_next(tag_head, tag);
-            }
-        }
-
-        Ok(names)
+        self.handle()?;
+        Ok(self
+            .tags_iter()
+            .map(|tag| tag.to_string_lossy().into_owned())
+            .collect())
     }
 
     /// Get the current uuid of a volume group
+    #[deprecated(since = "0.3.3", note = "use try_uuid, which reports failures")]
     pub fn get_uuid(&self) -> String {
-        unsafe {
-            let uid = lvm_vg_get_uuid(self.handle);
+        self.with_handle(|handle| unsafe {
+            let uid = lvm_vg_get_uuid(handle);
             let tmp = CStr::from_ptr(uid).to_string_lossy();
 
             tmp.into_owned()
-        }
+        })
+    }
+
+    /// Get the current uuid of a volume group
+    pub fn try_uuid(&self) -> LvmResult<String> {
+        unsafe { checked_string(lvm_vg_get_uuid(self.handle()?), "VG uuid") }
     }
 
     /// Get whether or not a volume group is clustered
     pub fn is_clustered(&self) -> bool {
-        unsafe {
-            let clustered = lvm_vg_is_clustered(self.handle);
+        self.with_handle(|handle| unsafe {
+            let clustered = lvm_vg_is_clustered(handle);
             clustered == 1
-        }
+        })
     }
 
     /// Get whether or not a volume group is exported
     pub fn is_exported(&self) -> bool {
-        unsafe {
-            let exported = lvm_vg_is_exported(self.handle);
+        self.with_handle(|handle| unsafe {
+            let exported = lvm_vg_is_exported(handle);
             exported == 1
-        }
+        })
     }
     /// Get whether or not a volume group is a partial volume group.
     /// When one or more physical volumes belonging to the volume group
     /// are missing from the system the volume group is a partial volume
     ///  group.
     pub fn is_partial(&self) -> bool {
-        unsafe {
-            let partial = lvm_vg_is_partial(self.handle);
+        self.with_handle(|handle| unsafe {
+            let partial = lvm_vg_is_partial(handle);
             partial == 1
-        }
+        })
     }
 
     pub fn lv_from_name(&self, name: &str) -> LvmResult<LogicalVolume<'_, '_>> {
         let name = CString::new(name)?;
         unsafe {
-            let lv_t = lvm_lv_from_name(self.handle, name.as_ptr());
+            let lv_t = lvm_lv_from_name(self.handle()?, name.as_ptr());
             if lv_t.is_null() {
                 let err = self.lvm.get_error()?;
                 return Err(LvmError::new((err.0, err.1)));
@@ -999,6 +2383,7 @@ impl<'a> VolumeGroup<'a> {
                 handle: lv_t,
                 lvm: self.lvm,
                 vg: self,
+                generation: self.generation.get(),
             })
         }
     }
@@ -1009,7 +2394,7 @@ impl<'a> VolumeGroup<'a> {
     pub fn name_validate(&self, name: &str) -> LvmResult<()> {
         let name = CString::new(name)?;
         unsafe {
-            let retcode = lvm_lv_name_validate(self.handle, name.as_ptr());
+            let retcode = lvm_lv_name_validate(self.handle()?, name.as_ptr());
             self.check_retcode(retcode)?;
         }
         Ok(())
@@ -1019,7 +2404,7 @@ impl<'a> VolumeGroup<'a> {
     pub fn pv_from_name(&self, name: &str) -> LvmResult<PhysicalVolume<'_>> {
         let name = CString::new(name)?;
         unsafe {
-            let pv_t = lvm_pv_from_name(self.handle, name.as_ptr());
+            let pv_t = lvm_pv_from_name(self.handle()?, name.as_ptr());
             if pv_t.is_null() {
                 let err = self.lvm.get_error()?;
                 return Err(LvmError::new((err.0, err.1)));
@@ -1027,6 +2412,8 @@ impl<'a> VolumeGroup<'a> {
             Ok(PhysicalVolume {
                 handle: pv_t,
                 lvm: self.lvm,
+                vg: self,
+                generation: self.generation.get(),
             })
         }
     }
@@ -1035,7 +2422,7 @@ impl<'a> VolumeGroup<'a> {
     pub fn pv_from_uuid(&self, id: &Uuid) -> LvmResult<PhysicalVolume<'_>> {
         let id = CString::new(id.as_bytes().to_vec())?;
         unsafe {
-            let pv_t = lvm_pv_from_uuid(self.handle, id.as_ptr());
+            let pv_t = lvm_pv_from_uuid(self.handle()?, id.as_ptr());
             if pv_t.is_null() {
                 let err = self.lvm.get_error()?;
                 return Err(LvmError::new((err.0, err.1)));
@@ -1043,56 +2430,370 @@ impl<'a> VolumeGroup<'a> {
             Ok(PhysicalVolume {
                 handle: pv_t,
                 lvm: self.lvm,
+                vg: self,
+                generation: self.generation.get(),
             })
         }
     }
 
-    /// Reduce a VG by removing an unused device.
-    pub fn reduce(&self, device: &str) -> LvmResult<()> {
-        let dev = CString::new(device)?;
-        unsafe {
-            let retcode = lvm_vg_reduce(self.handle, dev.as_ptr());
-            self.check_retcode(retcode)?;
+    /// Reduce a VG by removing an unused device.  Returns PvInUse, listing
+    /// the LVs, if any LV still has extents on it.
+    pub fn reduce(&mut self, device: &Path) -> LvmResult<()> {
+        let lvs = self.lvs_on_pv(device)?;
+        if !lvs.is_empty() {
+            return Err(LvmError::PvInUse(
+                format!(
+                    "Can't remove {} from the VG, it's used by {}",
+                    device.display(),
+                    lvs.join(", ")
+                ),
+                lvs,
+            ));
         }
+        let dev = CString::new(device.to_string_lossy().as_bytes())?;
+        let vg_name = self.get_name()?;
+        let params = [("device", device.to_string_lossy().into_owned())];
+        self.lvm
+            .audited("lvm_vg_reduce", &vg_name, &params, || unsafe {
+                self.check_retcode(lvm_vg_reduce(self.handle()?, dev.as_ptr()))
+            })?;
+        Ok(())
+    }
+
+    /// Reduce a VG by removing one of its PVs, see reduce
+    pub fn reduce_pv(&mut self, pv: &PhysicalVolume<'_>) -> LvmResult<()> {
+        self.reduce(Path::new(&pv.try_name()?))
+    }
+
+    /// Move pvs, and the LVs on them, into a new VG called new_vg_name,
+    /// like vgsplit.  LVs on the moved PVs must be inactive and can't span
+    /// PVs that stay behind.
+    pub fn split(&mut self, new_vg_name: &str, pvs: &[&Path]) -> LvmResult<()> {
+        let vg_name = self.get_name()?;
+        let pv_names: Vec<String> = pvs
+            .iter()
+            .map(|pv| pv.to_string_lossy().into_owned())
+            .collect();
+        let mut args = vec!["vgsplit", &vg_name, new_vg_name];
+        args.extend(pv_names.iter().map(|pv| pv.as_str()));
+        self.run_command(&args)?;
         Ok(())
     }
 
     /// Remove a VG from the system.
-    pub fn remove(&self) -> LvmResult<()> {
-        unsafe {
-            let retcode = lvm_vg_remove(self.handle);
-            self.check_retcode(retcode)?;
-        }
-        self.write()?;
+    /// The handle is consumed and closed once the removal is committed.
+    pub fn remove(self) -> LvmResult<()> {
+        let name = self.get_name()?;
+        self.lvm.audited("lvm_vg_remove", &name, &[], || unsafe {
+            self.check_retcode(lvm_vg_remove(self.handle()?))
+        })?;
+        self.commit()?;
         Ok(())
     }
 
     /// Remove a tag to a VG
-    pub fn remove_tag(&self, tag: &str) -> LvmResult<()> {
-        let tag = CString::new(tag)?;
-        unsafe {
-            let retcode = lvm_vg_remove_tag(self.handle, tag.as_ptr());
-            self.check_retcode(retcode)?;
+    pub fn remove_tag(&mut self, tag: &str) -> LvmResult<()> {
+        self.change_tag("lvm_vg_remove_tag", tag, lvm_vg_remove_tag)?;
+        self.commit()?;
+        Ok(())
+    }
+
+    /// Remove several tags from a VG and commit with a single write
+    pub fn remove_tags(&mut self, tags: &[&str]) -> LvmResult<()> {
+        validate_tags(tags)?;
+        for tag in tags {
+            self.change_tag("lvm_vg_remove_tag", tag, lvm_vg_remove_tag)?;
         }
-        self.write()?;
+        self.commit()
+    }
+
+    /// Set the extent allocation policy of a VG
+    pub fn set_alloc_policy(&mut self, policy: &AllocPolicy) -> LvmResult<()> {
+        let name = self.get_name()?;
+        policy.prepare(self.lvm)?;
+        self.run_command(&["vgchange", "--alloc", policy.as_arg(), &name])?;
         Ok(())
     }
 
-    pub fn set_extent_size(&self, size: u32) -> LvmResult<()> {
-        unsafe {
-            let retcode = lvm_vg_set_extent_size(self.handle, size);
-            self.check_retcode(retcode)?;
+    /// Set the maximum number of logical volumes allowed in a VG.
+    /// Zero removes the limit.
+    pub fn set_max_lv(&mut self, max_lv: u64) -> LvmResult<()> {
+        let name = self.get_name()?;
+        self.run_command(&["vgchange", "--logicalvolume", &max_lv.to_string(), &name])?;
+        Ok(())
+    }
+
+    /// Set how many metadata areas lvm should keep copies of the metadata
+    /// in, spread across PVs.  Zero makes it unmanaged.
+    pub fn set_mda_copies(&mut self, copies: u32) -> LvmResult<()> {
+        let name = self.get_name()?;
+        let copies = if copies == 0 {
+            "unmanaged".to_string()
+        } else {
+            copies.to_string()
+        };
+        self.run_command(&["vgchange", "--vgmetadatacopies", &copies, &name])?;
+        Ok(())
+    }
+
+    /// Set the maximum number of physical volumes allowed in a VG.
+    /// Zero removes the limit.
+    pub fn set_max_pv(&mut self, max_pv: u64) -> LvmResult<()> {
+        let name = self.get_name()?;
+        self.run_command(&[
+            "vgchange",
+            "--maxphysicalvolumes",
+            &max_pv.to_string(),
+            &name,
+        ])?;
+        Ok(())
+    }
+
+    /// Change the extent size of the VG.  The size must be a power of two
+    /// of at least 1KiB, and every LV's size must be a whole number of
+    /// the new extents.  Sizes of 4GiB and more are beyond liblvm and are
+    /// set with vgchange.
+    pub fn set_extent_size(&mut self, size: Size) -> LvmResult<()> {
+        let bytes = size.bytes();
+        if !bytes.is_power_of_two() || !(MIN_EXTENT_SIZE..=MAX_EXTENT_SIZE).contains(&bytes) {
+            return Err(LvmError::new((
+                Errno(libc::EINVAL),
+                format!(
+                    "Invalid extent size {}, it must be a power of two from {} to {}",
+                    size,
+                    Size::from_bytes(MIN_EXTENT_SIZE),
+                    Size::from_bytes(MAX_EXTENT_SIZE)
+                ),
+            )));
+        }
+        let name = self.get_name()?;
+        match u32::try_from(bytes) {
+            Ok(bytes) => {
+                let params = [("size", size.to_string())];
+                self.lvm
+                    .audited("lvm_vg_set_extent_size", &name, &params, || unsafe {
+                        self.check_retcode(lvm_vg_set_extent_size(self.handle()?, bytes))
+                    })?;
+                self.commit()
+            }
+            Err(_) => {
+                self.run_command(&["vgchange", "--physicalextentsize", &size.as_arg(), &name])?;
+                Ok(())
+            }
         }
-        self.write()?;
+    }
+
+    /// Make a logical volume read only or read write, see
+    /// LogicalVolume::set_read_only
+    pub fn set_lv_read_only(&mut self, lv_name: &str, read_only: bool) -> LvmResult<()> {
+        self.lv_from_name(lv_name)?.set_read_only(read_only)
+    }
+
+    /// Set the extent allocation policy of a logical volume, see
+    /// LogicalVolume::set_alloc_policy
+    pub fn set_lv_alloc_policy(&mut self, lv_name: &str, policy: &AllocPolicy) -> LvmResult<()> {
+        self.lv_from_name(lv_name)?.set_alloc_policy(policy)
+    }
+
+    /// Set or clear (with None) the persistent minor number of a logical
+    /// volume so it keeps the same device number across reboots.  An
+    /// active LV is briefly deactivated for the new number to take effect.
+    pub fn set_lv_persistent_minor(&mut self, lv_name: &str, minor: Option<u32>) -> LvmResult<()> {
+        let lv_path = format!("{}/{}", self.get_name()?, lv_name);
+        match minor {
+            Some(minor) => self.run_command(&[
+                "lvchange",
+                "--yes",
+                "--persistent",
+                "y",
+                "--minor",
+                &minor.to_string(),
+                &lv_path,
+            ])?,
+            None => self.run_command(&["lvchange", "--yes", "--persistent", "n", &lv_path])?,
+        };
         Ok(())
     }
 
     /// Write a VG to disk
-    pub fn write(&self) -> LvmResult<()> {
-        unsafe {
-            let retcode = lvm_vg_write(self.handle);
-            self.check_retcode(retcode)?;
+    pub fn write(&mut self) -> LvmResult<()> {
+        self.commit()
+    }
+}
+
+/// Longest tag lvm accepts
+const MAX_TAG_LEN: usize = 128;
+
+fn validate_tag(tag: &str) -> LvmResult<()> {
+    let valid = !tag.is_empty()
+        && tag.len() <= MAX_TAG_LEN
+        && !tag.starts_with('-')
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_+.-/=!:&#".contains(c));
+    if !valid {
+        return Err(LvmError::new((
+            Errno(libc::EINVAL),
+            format!("Invalid tag: {}", tag),
+        )));
+    }
+    Ok(())
+}
+
+/// Validate every tag up front so a bad one doesn't leave the rest
+/// half applied
+fn validate_tags(tags: &[&str]) -> LvmResult<Vec<CString>> {
+    tags.iter()
+        .map(|tag| {
+            validate_tag(tag)?;
+            Ok(CString::new(*tag)?)
+        })
+        .collect()
+}
+
+/// The setting a config override sets, ie: global/test for global/test=1
+fn override_path(setting: &str) -> &str {
+    setting.split('=').next().unwrap_or(setting).trim()
+}
+
+/// Mangle a VG and LV name into the name device-mapper knows the LV by
+fn dm_name(vg_name: &str, lv_name: &str) -> String {
+    format!(
+        "{}-{}",
+        vg_name.replace('-', "--"),
+        lv_name.replace('-', "--")
+    )
+}
+
+/// Days since the unix epoch for a proleptic gregorian calendar date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parse the "%Y-%m-%d %T %z" timestamps liblvm reports, ie:
+/// 2019-03-02 10:39:06 -0500
+fn parse_lvm_time(s: &str) -> Option<SystemTime> {
+    let mut parts = s.split_whitespace();
+    let date: Vec<i64> = parts
+        .next()?
+        .split('-')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    let time: Vec<i64> = parts
+        .next()?
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    if date.len() != 3 || time.len() != 3 {
+        return None;
+    }
+    // A missing zone means the time is already UTC
+    let offset = match parts.next() {
+        Some(zone) if zone.len() == 5 => {
+            let sign = match &zone[..1] {
+                "+" => 1,
+                "-" => -1,
+                _ => return None,
+            };
+            let hours: i64 = zone[1..3].parse().ok()?;
+            let minutes: i64 = zone[3..5].parse().ok()?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+        Some(_) => return None,
+        None => 0,
+    };
+    let secs = days_from_civil(date[0], date[1], date[2]) * 86_400
+        + time[0] * 3600
+        + time[1] * 60
+        + time[2]
+        - offset;
+    if secs < 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+}
+
+/// The inverse of days_from_civil, returns (year, month, day)
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn override_paths() {
+        assert_eq!(override_path("global/test=1"), "global/test");
+        assert_eq!(
+            override_path(r#"devices/filter=["a|/dev/sdb|", "r|.*|"]"#),
+            "devices/filter"
+        );
+        assert_eq!(override_path("global/test = 0"), "global/test");
+    }
+
+    #[test]
+    fn lvm_time() {
+        assert_eq!(
+            parse_lvm_time("2024-02-29 12:34:56 +0000"),
+            at(1_709_210_096)
+        );
+        assert_eq!(parse_lvm_time("2024-02-29 12:34:56"), at(1_709_210_096));
+        assert_eq!(
+            parse_lvm_time("2024-02-29 13:34:56 +0100"),
+            at(1_709_210_096)
+        );
+        assert_eq!(
+            parse_lvm_time("2024-02-29 07:04:56 -0530"),
+            at(1_709_210_096)
+        );
+        assert_eq!(parse_lvm_time("1970-01-01 00:00:00 +0000"), at(0));
+    }
+
+    #[test]
+    fn invalid_lvm_time() {
+        for s in &[
+            "",
+            "2024-02-29",
+            "2024-02 12:34:56",
+            "2024-02-29 12:34",
+            "2024-02-29 12:34:56 0100",
+            "2024-02-29 12:34:56 +01",
+            "2024-02-29 12:34:56 UTC",
+            "1970-01-01 00:00:00 +0100",
+        ] {
+            assert_eq!(parse_lvm_time(s), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn civil_days() {
+        for &(date, days) in &[
+            ((1970, 1, 1), 0),
+            ((2000, 3, 1), 11_017),
+            ((1969, 12, 31), -1),
+        ] {
+            assert_eq!(days_from_civil(date.0, date.1, date.2), days);
+            assert_eq!(civil_from_days(days), date);
         }
-        Ok(())
     }
 }
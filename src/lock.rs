@@ -0,0 +1,88 @@
+//! An advisory lock shared by every process using this crate on a host, to
+//! run a sequence of metadata changes without another daemon's changes
+//! interleaving.  It is a flock on a file in lvm's locking directory.
+//! lvm's own P_global lock isn't used because liblvm takes that itself on
+//! a separate file descriptor, which would deadlock against a holder in
+//! the same process, so the lvm tools aren't held off by it.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Error as IOError;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use errno::Errno;
+
+use crate::{Lvm, LvmError, LvmResult};
+
+const LOCK_FILE: &str = "lvm-rs_global";
+
+/// The global lock, released when dropped
+#[derive(Debug)]
+pub struct LockGuard {
+    file: File,
+    path: PathBuf,
+}
+
+impl LockGuard {
+    /// The lock file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        // Closing the file releases the lock too, this just reports errors
+        if unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN) } < 0 {
+            error!(
+                "Failed to unlock {}: {}",
+                self.path.display(),
+                IOError::last_os_error()
+            );
+        }
+    }
+}
+
+impl Lvm {
+    fn lock_global(&self, operation: libc::c_int) -> LvmResult<Option<LockGuard>> {
+        let dir = self.locking_dir();
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(LOCK_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+                debug!("Took the global lock {}", path.display());
+                return Ok(Some(LockGuard { file, path }));
+            }
+            let err = IOError::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                Some(libc::EWOULDBLOCK) => return Ok(None),
+                _ => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Take the global lock, waiting for whoever holds it.  Every Lvm, in
+    /// this process or another, contends for the same lock, so an Lvm
+    /// taking it twice deadlocks.
+    pub fn global_lock(&self) -> LvmResult<LockGuard> {
+        // Without LOCK_NB flock waits instead of failing with EWOULDBLOCK
+        self.lock_global(libc::LOCK_EX)?.ok_or_else(|| {
+            LvmError::new((
+                Errno(libc::EWOULDBLOCK),
+                "Taking the global lock failed".to_string(),
+            ))
+        })
+    }
+
+    /// Take the global lock if nobody holds it, None if somebody does
+    pub fn try_global_lock(&self) -> LvmResult<Option<LockGuard>> {
+        self.lock_global(libc::LOCK_EX | libc::LOCK_NB)
+    }
+}
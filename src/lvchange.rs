@@ -0,0 +1,76 @@
+//! Per LV flags only lvchange can set.  liblvm has no way to change an
+//! existing LV's settings, so the setters run lvchange with the VG's lock
+//! released, see LogicalVolume::set_read_only.
+
+use crate::{LogicalVolume, LvmResult};
+
+/// How much to read ahead on an LV's device
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReadAhead {
+    /// Let lvm pick based on the LV's stripe size
+    Auto,
+    None,
+    /// A number of 512 byte sectors, lvm rounds it to whole pages
+    Sectors(u32),
+}
+
+impl ReadAhead {
+    fn as_arg(&self) -> String {
+        match self {
+            ReadAhead::Auto => "auto".to_string(),
+            ReadAhead::None => "none".to_string(),
+            ReadAhead::Sectors(sectors) => sectors.to_string(),
+        }
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Check if the LV is skipped on activation
+    pub fn activation_skip(&self) -> LvmResult<bool> {
+        Ok(self.try_attributes()?.chars().nth(9) == Some('k'))
+    }
+
+    /// Set whether the LV is skipped when the VG or the LV is activated
+    /// without `--ignoreactivationskip`
+    pub fn set_activation_skip(&mut self, skip: bool) -> LvmResult<()> {
+        self.run_command(&[
+            "lvchange",
+            "--setactivationskip",
+            if skip { "y" } else { "n" },
+        ])?;
+        Ok(())
+    }
+
+    /// Check if a thin pool zeroes newly provisioned blocks
+    pub fn zero(&self) -> LvmResult<bool> {
+        Ok(self.try_attributes()?.chars().nth(7) == Some('z'))
+    }
+
+    /// Set whether a thin pool zeroes newly provisioned blocks.  Only thin
+    /// pools have the setting.
+    pub fn set_zero(&mut self, zero: bool) -> LvmResult<()> {
+        self.run_command(&["lvchange", "--zero", if zero { "y" } else { "n" }])?;
+        Ok(())
+    }
+
+    /// Get the read ahead setting of the LV
+    pub fn read_ahead(&self) -> LvmResult<ReadAhead> {
+        // Reported in bytes, auto is a sector count no device could have
+        let bytes = self.get_integer_property("lv_read_ahead")?;
+        let sectors = bytes / 512;
+        Ok(if bytes < 0 || sectors >= i64::from(u32::MAX) {
+            ReadAhead::Auto
+        } else if sectors == 0 {
+            ReadAhead::None
+        } else {
+            ReadAhead::Sectors(sectors as u32)
+        })
+    }
+
+    /// Set the read ahead of the LV.  An active LV's device picks it up
+    /// straight away.
+    pub fn set_read_ahead(&mut self, read_ahead: ReadAhead) -> LvmResult<()> {
+        self.run_command(&["lvchange", "--readahead", &read_ahead.as_arg()])?;
+        Ok(())
+    }
+}
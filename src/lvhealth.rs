@@ -0,0 +1,110 @@
+//! The health lvm reports for an LV, the lv_health_status field of lvs,
+//! and what a thin pool does once it runs out of data space.
+
+use std::fmt;
+
+use crate::{property, LogicalVolume, LvmResult, VolumeGroup};
+
+/// An LV's health as lvm sees it
+#[derive(Clone, Debug, PartialEq)]
+pub enum LvHealth {
+    Ok,
+    /// Some of the LV's PVs are missing
+    Partial,
+    /// A raid image had a transient failure and needs lvchange --refresh
+    RefreshNeeded,
+    /// A raid scrub found images that don't match
+    MismatchesExist,
+    /// The kernel failed the LV, ie: a thin pool or thin LV whose target
+    /// hit an error
+    Failed,
+    /// A thin pool's data space is used up
+    OutOfData,
+    /// A thin pool switched its metadata to read only after an error or
+    /// running out of metadata space
+    MetadataReadOnly,
+    /// lvm couldn't get the status from the kernel
+    Unknown,
+    /// A status this crate doesn't know yet
+    Other(String),
+}
+
+impl LvHealth {
+    fn parse(status: &str) -> LvHealth {
+        match status {
+            "" => LvHealth::Ok,
+            "partial" => LvHealth::Partial,
+            "refresh needed" => LvHealth::RefreshNeeded,
+            "mismatches exist" => LvHealth::MismatchesExist,
+            "failed" => LvHealth::Failed,
+            "out of data" => LvHealth::OutOfData,
+            "metadata read only" => LvHealth::MetadataReadOnly,
+            "unknown" => LvHealth::Unknown,
+            other => LvHealth::Other(other.to_string()),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        *self == LvHealth::Ok
+    }
+}
+
+impl fmt::Display for LvHealth {
+    /// The status as lvs prints it, ok for a healthy LV
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            LvHealth::Ok => "ok",
+            LvHealth::Partial => "partial",
+            LvHealth::RefreshNeeded => "refresh needed",
+            LvHealth::MismatchesExist => "mismatches exist",
+            LvHealth::Failed => "failed",
+            LvHealth::OutOfData => "out of data",
+            LvHealth::MetadataReadOnly => "metadata read only",
+            LvHealth::Unknown => "unknown",
+            LvHealth::Other(status) => status,
+        };
+        f.write_str(status)
+    }
+}
+
+/// What a thin pool does with writes needing space once it's full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WhenFull {
+    /// Fail them straight away
+    Error,
+    /// Queue them until space is added, or dm-thin's no_space_timeout
+    /// passes and they fail after all
+    Queue,
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Get the health of the LV.  Only says more than Ok for active LVs.
+    pub fn health(&self) -> LvmResult<LvHealth> {
+        let status = self.get_string_property(property::LV_HEALTH_STATUS)?;
+        Ok(LvHealth::parse(&status))
+    }
+
+    /// Get what a thin pool does when full, None for other LVs
+    pub fn when_full(&self) -> LvmResult<Option<WhenFull>> {
+        let when_full = self.get_string_property(property::LV_WHEN_FULL)?;
+        Ok(match when_full.as_str() {
+            "error" => Some(WhenFull::Error),
+            "queue" => Some(WhenFull::Queue),
+            _ => None,
+        })
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Set what a thin pool does when full, lvchange --errorwhenfull.  An
+    /// active pool picks it up straight away.
+    pub fn set_lv_when_full(&mut self, lv_name: &str, when_full: WhenFull) -> LvmResult<()> {
+        let lv_path = format!("{}/{}", self.get_name()?, lv_name);
+        let error = match when_full {
+            WhenFull::Error => "y",
+            WhenFull::Queue => "n",
+        };
+        self.run_command(&["lvchange", "--errorwhenfull", error, &lv_path])?;
+        Ok(())
+    }
+}
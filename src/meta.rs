@@ -0,0 +1,264 @@
+//! Key/value metadata stored in LV tags as key=value, the way ceph-volume
+//! keeps its ceph.osd_id=12 style tags.  Tags only allow a few characters
+//! so values are escaped: any byte outside [A-Za-z0-9_+.-/:=] is written as
+//! & followed by two hex digits, ie: "a b" is stored as a&20b.  Keys aren't
+//! escaped and may only use [A-Za-z0-9_+.-/:].  Lvm::find_lvs searches
+//! every VG for LVs by their metadata.
+
+use std::collections::BTreeMap;
+
+use errno::Errno;
+use lvm_sys::*;
+
+use crate::inventory::LvReport;
+use crate::{LogicalVolume, Lvm, LvmError, LvmResult, MAX_TAG_LEN};
+
+fn is_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_+.-/:".contains(c)
+}
+
+fn validate_key(key: &str) -> LvmResult<()> {
+    if key.is_empty() || key.starts_with('-') || !key.chars().all(is_key_char) {
+        return Err(LvmError::new((
+            Errno(libc::EINVAL),
+            format!("Invalid metadata key: {}", key),
+        )));
+    }
+    Ok(())
+}
+
+/// The tag storing value under key
+pub fn encode_meta(key: &str, value: &str) -> LvmResult<String> {
+    validate_key(key)?;
+    let mut tag = format!("{}=", key);
+    for b in value.bytes() {
+        if is_key_char(b as char) || b == b'=' {
+            tag.push(b as char);
+        } else {
+            tag.push_str(&format!("&{:02x}", b));
+        }
+    }
+    if tag.len() > MAX_TAG_LEN {
+        return Err(LvmError::new((
+            Errno(libc::EINVAL),
+            format!(
+                "The value of {} is {} bytes once encoded, tags are limited to {}",
+                key,
+                tag.len(),
+                MAX_TAG_LEN
+            ),
+        )));
+    }
+    Ok(tag)
+}
+
+/// The key and value a tag stores, None for tags that aren't key=value or
+/// whose value isn't validly escaped
+pub fn decode_meta(tag: &str) -> Option<(String, String)> {
+    let (key, encoded) = tag.split_once('=')?;
+    validate_key(key).ok()?;
+    let mut value = vec![];
+    let mut bytes = encoded.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'&' {
+            let hex = [bytes.next()?, bytes.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            value.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            value.push(b);
+        }
+    }
+    Some((key.to_string(), String::from_utf8(value).ok()?))
+}
+
+/// Every key and value stored in tags
+fn metadata_of(tags: &[String]) -> BTreeMap<String, String> {
+    tags.iter().filter_map(|tag| decode_meta(tag)).collect()
+}
+
+/// Conditions an LV's metadata must all meet, ie:
+/// MetaQuery::new().eq("ceph.osd_id", "12").has("ceph.osd_fsid")
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetaQuery {
+    /// Keys and the value each must have, None for any value
+    conditions: Vec<(String, Option<String>)>,
+}
+
+impl MetaQuery {
+    /// A query every LV matches
+    pub fn new() -> MetaQuery {
+        MetaQuery::default()
+    }
+
+    /// Require key to be set to value
+    pub fn eq(mut self, key: &str, value: &str) -> MetaQuery {
+        self.conditions
+            .push((key.to_string(), Some(value.to_string())));
+        self
+    }
+
+    /// Require key to be set to any value
+    pub fn has(mut self, key: &str) -> MetaQuery {
+        self.conditions.push((key.to_string(), None));
+        self
+    }
+
+    /// Check if metadata meets every condition
+    pub fn matches(&self, metadata: &BTreeMap<String, String>) -> bool {
+        self.conditions.iter().all(|(key, value)| match value {
+            Some(value) => metadata.get(key) == Some(value),
+            None => metadata.contains_key(key),
+        })
+    }
+}
+
+impl LvReport {
+    /// Every key and value stored in the LV's tags
+    pub fn metadata(&self) -> BTreeMap<String, String> {
+        metadata_of(&self.tags)
+    }
+}
+
+impl Lvm {
+    /// List the LVs of every VG whose metadata matches query, ie: to find
+    /// the LV backing an OSD.  VGs are opened read only one at a time, as
+    /// with list_all_lvs.
+    pub fn find_lvs(&self, query: &MetaQuery) -> LvmResult<Vec<LvReport>> {
+        Ok(self
+            .list_all_lvs()?
+            .into_iter()
+            .filter(|lv| query.matches(&lv.metadata()))
+            .collect())
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Every key and value stored in the LV's tags.  Tags that aren't
+    /// key=value are left out.
+    pub fn metadata(&self) -> LvmResult<BTreeMap<String, String>> {
+        Ok(metadata_of(&self.get_tags()?))
+    }
+
+    /// Get the value stored under key
+    pub fn get_meta(&self, key: &str) -> LvmResult<Option<String>> {
+        validate_key(key)?;
+        Ok(self.metadata()?.remove(key))
+    }
+
+    /// The LV's tags storing key
+    fn meta_tags(&self, key: &str) -> LvmResult<Vec<String>> {
+        Ok(self
+            .get_tags()?
+            .into_iter()
+            .filter(|tag| decode_meta(tag).map(|(k, _)| k).as_deref() == Some(key))
+            .collect())
+    }
+
+    /// Remove the tags remove and add the tags add, without writing the VG
+    fn swap_tags(&self, remove: &[&str], add: &[&str]) -> LvmResult<()> {
+        for tag in remove {
+            self.change_tag("lvm_lv_remove_tag", tag, lvm_lv_remove_tag)?;
+        }
+        for tag in add {
+            self.change_tag("lvm_lv_add_tag", tag, lvm_lv_add_tag)?;
+        }
+        Ok(())
+    }
+
+    /// Store value under key, replacing any value it had, with a single VG
+    /// write.  If that fails the LV keeps the old value.
+    pub fn set_meta(&mut self, key: &str, value: &str) -> LvmResult<()> {
+        let tag = encode_meta(key, value)?;
+        let old = self.meta_tags(key)?;
+        if old == [tag.as_str()] {
+            return Ok(());
+        }
+        let old: Vec<&str> = old.iter().map(|t| t.as_str()).collect();
+        let result = self.swap_tags(&old, &[&tag]).and_then(|_| self.vg.commit());
+        if result.is_err() {
+            // Undo the change in memory too so the next write doesn't
+            // store it after all
+            let new: &[&str] = if old.contains(&tag.as_str()) {
+                &[]
+            } else {
+                &[&tag]
+            };
+            if let Err(err) = self.swap_tags(new, &old) {
+                error!("Failed to restore the old value of {}: {}", key, err);
+            }
+        }
+        result
+    }
+
+    /// Remove key and its value.  Returns whether it was set.
+    pub fn remove_meta(&mut self, key: &str) -> LvmResult<bool> {
+        validate_key(key)?;
+        let old = self.meta_tags(key)?;
+        if old.is_empty() {
+            return Ok(false);
+        }
+        let old: Vec<&str> = old.iter().map(|t| t.as_str()).collect();
+        self.remove_tags(&old)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for value in &[
+            "12",
+            "",
+            "a b",
+            "x&y",
+            "k=v=w",
+            "caf\u{e9}",
+            "\u{1f980}/dev/sda",
+        ] {
+            let tag = encode_meta("ceph.osd_id", value).unwrap();
+            assert_eq!(
+                decode_meta(&tag),
+                Some(("ceph.osd_id".to_string(), value.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(encode_meta("k", "a b").unwrap(), "k=a&20b");
+        assert_eq!(encode_meta("k", "x&y").unwrap(), "k=x&26y");
+        assert_eq!(encode_meta("k", "a=b").unwrap(), "k=a=b");
+        assert_eq!(encode_meta("k", "\u{e9}").unwrap(), "k=&c3&a9");
+    }
+
+    #[test]
+    fn invalid_keys() {
+        assert!(encode_meta("", "v").is_err());
+        assert!(encode_meta("-k", "v").is_err());
+        assert!(encode_meta("a=b", "v").is_err());
+        assert!(encode_meta("a b", "v").is_err());
+    }
+
+    #[test]
+    fn length_limit() {
+        // "k=" takes two bytes of the limit
+        let fits = "v".repeat(MAX_TAG_LEN - 2);
+        assert_eq!(encode_meta("k", &fits).unwrap().len(), MAX_TAG_LEN);
+        assert!(encode_meta("k", &format!("{}v", fits)).is_err());
+        // Escaping counts against the limit
+        assert!(encode_meta("k", &" ".repeat(MAX_TAG_LEN / 3 + 1)).is_err());
+    }
+
+    #[test]
+    fn decode_rejects() {
+        assert_eq!(decode_meta("no_value"), None);
+        assert_eq!(decode_meta("-k=v"), None);
+        assert_eq!(decode_meta("k=a&2"), None);
+        assert_eq!(decode_meta("k=a&zz"), None);
+        // Escapes that aren't UTF-8
+        assert_eq!(decode_meta("k=&ff"), None);
+    }
+}
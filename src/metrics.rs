@@ -0,0 +1,208 @@
+//! Gauges describing every VG, LV and PV on the system, for node exporters
+//! that want to publish LVM usage without shelling out to the lvm tools.
+
+use std::fmt::Write;
+
+use crate::{Lvm, LvmResult, OpenMode};
+
+/// A single gauge sample
+#[derive(Debug)]
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub labels: Vec<(&'static str, String)>,
+    pub value: f64,
+}
+
+impl Metric {
+    fn new(
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        value: f64,
+    ) -> Metric {
+        Metric {
+            name,
+            help,
+            labels: labels.iter().map(|(k, v)| (*k, v.to_string())).collect(),
+            value,
+        }
+    }
+}
+
+/// Collect the current metrics of every VG on the system.  Each VG is
+/// opened read only so holding the lock is short lived.
+///
+/// Emits lvm_vg_size_bytes, lvm_vg_free_bytes, lvm_lv_size_bytes,
+/// lvm_thin_pool_data_percent, lvm_thin_pool_metadata_percent,
+/// lvm_snapshot_usage_percent and lvm_pv_missing.
+pub fn collect_metrics(lvm: &Lvm) -> LvmResult<Vec<Metric>> {
+    let mut metrics = vec![];
+    for vg_name in lvm.get_volume_group_names()? {
+        // A VG removed or locked since it was listed shouldn't fail the
+        // whole scrape
+        let vg = match lvm.vg_open(&vg_name, &OpenMode::Read) {
+            Ok(vg) => vg,
+            Err(e) => {
+                warn!("Skipping VG {}, opening it failed: {}", vg_name, e);
+                continue;
+            }
+        };
+        let vg_labels = [("vg", vg_name.as_str())];
+        metrics.push(Metric::new(
+            "lvm_vg_size_bytes",
+            "Size of the volume group",
+            &vg_labels,
+            vg.get_size() as f64,
+        ));
+        metrics.push(Metric::new(
+            "lvm_vg_free_bytes",
+            "Unallocated space in the volume group",
+            &vg_labels,
+            vg.get_free_size() as f64,
+        ));
+        for lv in vg.list_lvs()? {
+            let lv_name = lv.try_name()?;
+            let labels = [("vg", vg_name.as_str()), ("lv", lv_name.as_str())];
+            metrics.push(Metric::new(
+                "lvm_lv_size_bytes",
+                "Size of the logical volume",
+                &labels,
+                lv.try_size()? as f64,
+            ));
+            match lv.try_attributes()?.chars().next() {
+                Some('t') => {
+                    if let Some(percent) = lv.get_percent_property("data_percent")? {
+                        metrics.push(Metric::new(
+                            "lvm_thin_pool_data_percent",
+                            "Percentage of the thin pool's data space in use",
+                            &labels,
+                            percent,
+                        ));
+                    }
+                    if let Some(percent) = lv.get_percent_property("metadata_percent")? {
+                        metrics.push(Metric::new(
+                            "lvm_thin_pool_metadata_percent",
+                            "Percentage of the thin pool's metadata space in use",
+                            &labels,
+                            percent,
+                        ));
+                    }
+                }
+                Some('s') | Some('S') => {
+                    if let Some(percent) = lv.get_percent_property("snap_percent")? {
+                        metrics.push(Metric::new(
+                            "lvm_snapshot_usage_percent",
+                            "Percentage of the snapshot's COW space in use",
+                            &labels,
+                            percent,
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+        for pv in vg.list_pvs()? {
+            let pv_name = pv.try_name()?;
+            let labels = [("vg", vg_name.as_str()), ("pv", pv_name.as_str())];
+            let missing = if pv.is_missing()? { 1.0 } else { 0.0 };
+            metrics.push(Metric::new(
+                "lvm_pv_missing",
+                "Whether the device backing the physical volume is missing",
+                &labels,
+                missing,
+            ));
+        }
+    }
+    Ok(metrics)
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render metrics in the Prometheus text exposition format.  Samples are
+/// grouped by name under a single HELP and TYPE line, keeping the names in
+/// the order they first appear.
+pub fn render(metrics: &[Metric]) -> String {
+    let mut families: Vec<(&str, &str)> = vec![];
+    for metric in metrics {
+        if !families.iter().any(|(name, _)| *name == metric.name) {
+            families.push((metric.name, metric.help));
+        }
+    }
+    let mut out = String::new();
+    for (name, help) in families {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for metric in metrics.iter().filter(|m| m.name == name) {
+            render_sample(&mut out, metric);
+        }
+    }
+    out
+}
+
+fn render_sample(out: &mut String, metric: &Metric) {
+    let labels: Vec<String> = metric
+        .labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+        .collect();
+    let _ = writeln!(
+        out,
+        "{}{{{}}} {}",
+        metric.name,
+        labels.join(","),
+        metric.value
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_header_per_family() {
+        let mut metrics = vec![];
+        for vg in &["vg0", "vg1"] {
+            metrics.push(Metric::new(
+                "lvm_vg_size_bytes",
+                "Size of the volume group",
+                &[("vg", vg)],
+                1024.0,
+            ));
+            for lv in &["lv0", "lv1"] {
+                metrics.push(Metric::new(
+                    "lvm_lv_size_bytes",
+                    "Size of the logical volume",
+                    &[("vg", vg), ("lv", lv)],
+                    512.0,
+                ));
+            }
+        }
+        let out = render(&metrics);
+        for name in &["lvm_vg_size_bytes", "lvm_lv_size_bytes"] {
+            let header = format!("# TYPE {} gauge", name);
+            assert_eq!(out.lines().filter(|l| *l == header).count(), 1);
+        }
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "# HELP lvm_vg_size_bytes Size of the volume group",
+                "# TYPE lvm_vg_size_bytes gauge",
+                "lvm_vg_size_bytes{vg=\"vg0\"} 1024",
+                "lvm_vg_size_bytes{vg=\"vg1\"} 1024",
+                "# HELP lvm_lv_size_bytes Size of the logical volume",
+                "# TYPE lvm_lv_size_bytes gauge",
+                "lvm_lv_size_bytes{vg=\"vg0\",lv=\"lv0\"} 512",
+                "lvm_lv_size_bytes{vg=\"vg0\",lv=\"lv1\"} 512",
+                "lvm_lv_size_bytes{vg=\"vg1\",lv=\"lv0\"} 512",
+                "lvm_lv_size_bytes{vg=\"vg1\",lv=\"lv1\"} 512",
+            ]
+        );
+    }
+}
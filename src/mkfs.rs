@@ -0,0 +1,147 @@
+//! Making a filesystem on a new LV, and probing LVs for signatures first so
+//! a device holding data isn't formatted by mistake.
+
+use std::process::Command;
+
+use errno::Errno;
+
+use crate::{cli, LogicalVolume, LvmError, LvmResult};
+
+/// Filesystems mkfs knows how to make
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FsType {
+    Ext4,
+    Xfs,
+    Btrfs,
+    Vfat,
+}
+
+impl FsType {
+    fn program(self) -> &'static str {
+        match self {
+            FsType::Ext4 => "mkfs.ext4",
+            FsType::Xfs => "mkfs.xfs",
+            FsType::Btrfs => "mkfs.btrfs",
+            FsType::Vfat => "mkfs.vfat",
+        }
+    }
+
+    /// Flag making mkfs overwrite an existing signature without asking
+    fn force_flag(self) -> Option<&'static str> {
+        match self {
+            FsType::Ext4 => Some("-F"),
+            FsType::Xfs | FsType::Btrfs => Some("-f"),
+            FsType::Vfat => None,
+        }
+    }
+
+    fn label_flag(self) -> &'static str {
+        match self {
+            FsType::Vfat => "-n",
+            _ => "-L",
+        }
+    }
+}
+
+/// How to make the filesystem
+#[derive(Clone, Debug, Default)]
+pub struct MkfsOptions {
+    pub label: Option<String>,
+    /// Format even if the LV already holds a signature, destroying it
+    pub force: bool,
+    /// Passed to the mkfs program before the device, ie: ["-b", "4096"]
+    pub extra_args: Vec<String>,
+}
+
+/// A signature blkid found on a device
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    /// ie: ext4, xfs, LVM2_member, crypto_LUKS, or the partition table
+    /// type, ie: gpt, for a partitioned device
+    pub fs_type: String,
+    pub label: Option<String>,
+    pub uuid: Option<String>,
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Probe an active LV for a filesystem, partition table or other
+    /// signature the way blkid -p does, None if it looks empty
+    pub fn blkid(&self) -> LvmResult<Option<Signature>> {
+        let device = self.path()?;
+        debug!("running blkid -p -o export {}", device.display());
+        let output = Command::new("blkid")
+            .args(["-p", "-o", "export"])
+            .arg(&device)
+            .output()?;
+        // blkid exits with 2 when it finds nothing
+        if output.status.code() == Some(2) {
+            return Ok(None);
+        }
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(LvmError::new((
+                Errno(libc::EIO),
+                format!("blkid {} failed: {}", device.display(), stderr.trim()),
+            )));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let value = |key: &str| {
+            stdout
+                .lines()
+                .filter_map(|l| l.split_once('='))
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        };
+        Ok(value("TYPE")
+            .or_else(|| value("PTTYPE"))
+            .map(|fs_type| Signature {
+                fs_type,
+                label: value("LABEL"),
+                uuid: value("UUID"),
+            }))
+    }
+
+    /// Make a filesystem on an active LV.  LVs that are mounted are
+    /// refused, and so are LVs holding a signature unless options.force
+    /// is set.  In test mode the checks are made but mkfs isn't run.
+    pub fn mkfs(&mut self, fs_type: FsType, options: &MkfsOptions) -> LvmResult<()> {
+        self.check_not_mounted()?;
+        if !options.force {
+            if let Some(signature) = self.blkid()? {
+                return Err(LvmError::new((
+                    Errno(libc::EEXIST),
+                    format!(
+                        "{} already holds {}, use force to overwrite it",
+                        self.try_name()?,
+                        signature.fs_type
+                    ),
+                )));
+            }
+        }
+        let device = self.path()?.to_string_lossy().into_owned();
+        if self.vg.lvm.is_test_mode() {
+            info!(
+                "test mode, not making a {} filesystem on {}",
+                fs_type.program(),
+                device
+            );
+            return Ok(());
+        }
+        // The check above decides whether to overwrite, so mkfs is always
+        // told not to ask
+        let mut args: Vec<&str> = fs_type.force_flag().into_iter().collect();
+        if let Some(ref label) = options.label {
+            args.extend_from_slice(&[fs_type.label_flag(), label]);
+        }
+        args.extend(options.extra_args.iter().map(|a| a.as_str()));
+        args.push(&device);
+        let params = [
+            ("type", fs_type.program().to_string()),
+            ("args", format!("{} {}", fs_type.program(), args.join(" "))),
+        ];
+        self.vg.lvm.audited("mkfs", &device, &params, || {
+            cli::run_program(fs_type.program(), &args)
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,300 @@
+//! Whether an LV is in use by a mounted filesystem or another opener, and
+//! mounting and unmounting its filesystem.  Mounts are found by matching
+//! the LV's device number against /proc/self/mountinfo so bind mounts and
+//! any name the device was mounted by are all found.
+
+use std::ffi::CString;
+use std::fs;
+use std::io::Error as IOError;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use errno::Errno;
+
+use crate::{blockdev, dm, LogicalVolume, LvmError, LvmResult};
+
+/// How to mount an LV's filesystem
+#[derive(Clone, Debug, Default)]
+pub struct MountOptions {
+    /// Filesystem type, ie: ext4.  None asks blkid.
+    pub fs_type: Option<String>,
+    pub read_only: bool,
+    pub no_exec: bool,
+    pub no_suid: bool,
+    pub no_dev: bool,
+    pub no_atime: bool,
+    /// Filesystem specific options, ie: discard,errors=remount-ro
+    pub data: Option<String>,
+}
+
+impl MountOptions {
+    fn flags(&self) -> libc::c_ulong {
+        let mut flags = 0;
+        for (set, flag) in &[
+            (self.read_only, libc::MS_RDONLY),
+            (self.no_exec, libc::MS_NOEXEC),
+            (self.no_suid, libc::MS_NOSUID),
+            (self.no_dev, libc::MS_NODEV),
+            (self.no_atime, libc::MS_NOATIME),
+        ] {
+            if *set {
+                flags |= flag;
+            }
+        }
+        flags
+    }
+}
+
+fn path_cstring(path: &Path) -> LvmResult<CString> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+/// Unmount the filesystem mounted at target, like umount
+fn unmount_at(target: &Path) -> LvmResult<()> {
+    let c_target = path_cstring(target)?;
+    if unsafe { libc::umount2(c_target.as_ptr(), 0) } < 0 {
+        let err = IOError::last_os_error();
+        return Err(LvmError::new((
+            Errno(err.raw_os_error().unwrap_or(libc::EIO)),
+            format!("Failed to unmount {}: {}", target.display(), err),
+        )));
+    }
+    Ok(())
+}
+
+/// An LV's filesystem mounted by LogicalVolume::mount, unmounted again when
+/// dropped unless kept
+#[derive(Debug)]
+pub struct MountedLv {
+    target: PathBuf,
+    mounted: bool,
+}
+
+impl MountedLv {
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    /// Leave the filesystem mounted
+    pub fn keep(mut self) -> PathBuf {
+        self.mounted = false;
+        self.target.clone()
+    }
+
+    /// Unmount the filesystem, reporting a failure that drop would only
+    /// log
+    pub fn unmount(mut self) -> LvmResult<()> {
+        self.mounted = false;
+        unmount_at(&self.target)
+    }
+}
+
+impl Drop for MountedLv {
+    fn drop(&mut self) {
+        if !self.mounted {
+            return;
+        }
+        if let Err(e) = unmount_at(&self.target) {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Undo the octal escaping mountinfo uses for spaces, tabs, newlines and
+/// backslashes in paths
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            if let Some(c) = field
+                .get(i + 1..i + 4)
+                .and_then(|oct| u8::from_str_radix(oct, 8).ok())
+            {
+                out.push(c);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Mount points of the block device major:minor
+pub(crate) fn mount_points(major: u32, minor: u32) -> LvmResult<Vec<PathBuf>> {
+    let device = format!("{}:{}", major, minor);
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+    // id parent major:minor root mount_point options ...
+    Ok(mountinfo
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let dev = fields.nth(2)?;
+            let mount_point = fields.nth(1)?;
+            if dev == device {
+                Some(PathBuf::from(unescape(mount_point)))
+            } else {
+                None
+            }
+        })
+        .collect())
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Get how many times the LV's device is held open, ie: by a mounted
+    /// filesystem, a VM or a stacked device.  Inactive LVs aren't open.
+    pub fn open_count(&self) -> LvmResult<u32> {
+        if !self.try_is_active()? {
+            return Ok(0);
+        }
+        Ok(dm::open_count(&self.dm_name()?)?.max(0) as u32)
+    }
+
+    /// Get where the LV's filesystem is mounted, if it is.  This is the
+    /// first mount found if the LV is mounted in more than one place.
+    pub fn mounted_at(&self) -> LvmResult<Option<PathBuf>> {
+        if !self.try_is_active()? {
+            return Ok(None);
+        }
+        let (major, minor) = self.major_minor()?;
+        Ok(mount_points(major, minor)?.into_iter().next())
+    }
+
+    /// Hand unused space back to the thin pool, or the underlying device.
+    /// If the LV is mounted its filesystem is trimmed with FITRIM, like
+    /// fstrim.  If it isn't every block of the LV is discarded, destroying
+    /// its contents, so only trim unmounted LVs that hold nothing worth
+    /// keeping.  Returns the number of bytes trimmed.  The LV must be active.
+    pub fn trim(&mut self) -> LvmResult<u64> {
+        match self.mounted_at()? {
+            Some(mount_point) if self.lvm.is_test_mode() => {
+                info!("test mode, not trimming {}", mount_point.display());
+                Ok(0)
+            }
+            Some(mount_point) => blockdev::fitrim(&mount_point),
+            None => {
+                self.discard_all()?;
+                self.try_size()
+            }
+        }
+    }
+
+    /// Mount an active LV's filesystem at target, an existing directory,
+    /// with the mount syscall.  The returned guard unmounts it when
+    /// dropped, call keep on it to leave it mounted.
+    pub fn mount(&mut self, target: &Path, options: &MountOptions) -> LvmResult<MountedLv> {
+        let device = self.path()?;
+        let fs_type = match options.fs_type {
+            Some(ref fs_type) => fs_type.clone(),
+            None => match self.blkid()? {
+                Some(signature) => signature.fs_type,
+                None => {
+                    return Err(LvmError::new((
+                        Errno(libc::EINVAL),
+                        format!("{} holds no filesystem", self.try_name()?),
+                    )))
+                }
+            },
+        };
+        let c_device = path_cstring(&device)?;
+        let c_target = path_cstring(target)?;
+        let c_fs_type = CString::new(fs_type.as_str())?;
+        let c_data = match options.data {
+            Some(ref data) => Some(CString::new(data.as_str())?),
+            None => None,
+        };
+        let data_ptr = c_data
+            .as_ref()
+            .map(|d| d.as_ptr() as *const libc::c_void)
+            .unwrap_or(ptr::null());
+        let mut params = vec![
+            ("target", target.display().to_string()),
+            ("type", fs_type.clone()),
+        ];
+        if let Some(ref data) = options.data {
+            params.push(("data", data.clone()));
+        }
+        self.lvm
+            .audited("mount", &device.to_string_lossy(), &params, || {
+                let ret = unsafe {
+                    libc::mount(
+                        c_device.as_ptr(),
+                        c_target.as_ptr(),
+                        c_fs_type.as_ptr(),
+                        options.flags(),
+                        data_ptr,
+                    )
+                };
+                if ret < 0 {
+                    let err = IOError::last_os_error();
+                    return Err(LvmError::new((
+                        Errno(err.raw_os_error().unwrap_or(libc::EIO)),
+                        format!(
+                            "Failed to mount {} at {}: {}",
+                            device.display(),
+                            target.display(),
+                            err
+                        ),
+                    )));
+                }
+                Ok(())
+            })?;
+        Ok(MountedLv {
+            target: target.to_path_buf(),
+            mounted: true,
+        })
+    }
+
+    /// Unmount the LV's filesystem everywhere it's mounted
+    pub fn unmount(&mut self) -> LvmResult<()> {
+        if !self.try_is_active()? {
+            return Ok(());
+        }
+        let (major, minor) = self.major_minor()?;
+        let name = self.try_name()?;
+        // Unmount the most recent mounts first, later ones may be stacked
+        // on top of earlier ones
+        for mount_point in mount_points(major, minor)?.iter().rev() {
+            let params = [("target", mount_point.display().to_string())];
+            self.lvm
+                .audited("umount", &name, &params, || unmount_at(mount_point))?;
+        }
+        Ok(())
+    }
+
+    /// Fail with LvmError::InUse if the LV is mounted
+    pub(crate) fn check_not_mounted(&self) -> LvmResult<()> {
+        if let Some(mount_point) = self.mounted_at()? {
+            return Err(LvmError::InUse(
+                format!(
+                    "{} is mounted at {}",
+                    self.try_name()?,
+                    mount_point.display()
+                ),
+                mount_point,
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescapes_mountinfo_paths() {
+        assert_eq!(unescape("/mnt/plain"), "/mnt/plain");
+        assert_eq!(unescape("/mnt/a\\040b"), "/mnt/a b");
+        assert_eq!(unescape("/mnt/tab\\011nl\\012"), "/mnt/tab\tnl\n");
+        assert_eq!(unescape("/mnt/back\\134slash"), "/mnt/back\\slash");
+        // Backslashes not starting an octal escape are kept
+        assert_eq!(unescape("/mnt/a\\b"), "/mnt/a\\b");
+        assert_eq!(unescape("/mnt/end\\04"), "/mnt/end\\04");
+        assert_eq!(unescape("/mnt/caf\u{e9}"), "/mnt/caf\u{e9}");
+    }
+}
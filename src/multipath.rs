@@ -0,0 +1,90 @@
+//! Multipath topologies.  With dm-multipath every path to a SAN LUN shows
+//! up as its own sdX device next to the /dev/mapper device combining them.
+//! A PV written through one sdX path bypasses multipathing and lvm then
+//! sees the same PV on every path, so PV creation and VG extension refuse
+//! paths that are part of a multipath device.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use errno::Errno;
+
+use crate::{LvmError, LvmResult};
+
+/// The sysfs directory of the block device at path, ie: /sys/block/sda,
+/// None if path isn't a block device
+pub(crate) fn sysfs_dir(path: &Path) -> Option<PathBuf> {
+    let resolved = fs::canonicalize(path).ok()?;
+    let name = resolved.file_name()?;
+    fs::canonicalize(Path::new("/sys/class/block").join(name)).ok()
+}
+
+/// The multipath map name of the device mapper device at sysfs dir, None
+/// for other devices
+fn mpath_name(dir: &Path) -> Option<String> {
+    // multipathd gives its maps uuids of mpath-<wwid>
+    let uuid = fs::read_to_string(dir.join("dm/uuid")).ok()?;
+    if !uuid.starts_with("mpath-") {
+        return None;
+    }
+    fs::read_to_string(dir.join("dm/name"))
+        .ok()
+        .map(|name| name.trim().to_string())
+}
+
+/// Check if the device at path is a multipath device itself, ie:
+/// /dev/mapper/mpatha
+pub fn is_multipath(path: &Path) -> bool {
+    sysfs_dir(path).and_then(|dir| mpath_name(&dir)).is_some()
+}
+
+/// The multipath device the device at path is a path of, ie:
+/// /dev/mapper/mpatha for /dev/sdb, or None if it isn't one.  Partitions
+/// of paths resolve to the multipath device of their disk.
+pub fn resolve_to_mpath(path: &Path) -> LvmResult<Option<PathBuf>> {
+    let dir = match sysfs_dir(path) {
+        Some(dir) => dir,
+        None => {
+            return Err(LvmError::new((
+                Errno(libc::ENOENT),
+                format!("{} is not a block device", path.display()),
+            )))
+        }
+    };
+    // Partitions live in their disk's directory
+    let disk = if dir.join("partition").exists() {
+        dir.parent().map(|p| p.to_path_buf()).unwrap_or(dir)
+    } else {
+        dir
+    };
+    let holders = match fs::read_dir(disk.join("holders")) {
+        Ok(holders) => holders,
+        Err(_) => return Ok(None),
+    };
+    for holder in holders {
+        let holder = fs::canonicalize(holder?.path())?;
+        if let Some(name) = mpath_name(&holder) {
+            return Ok(Some(Path::new("/dev/mapper").join(name)));
+        }
+    }
+    Ok(None)
+}
+
+/// Refuse a device that is a path of a multipath device with EBUSY
+pub(crate) fn check_not_mpath_path(path: &Path) -> LvmResult<()> {
+    // Paths that aren't block devices are left for lvm to report
+    if sysfs_dir(path).is_none() {
+        return Ok(());
+    }
+    if let Some(mpath) = resolve_to_mpath(path)? {
+        return Err(LvmError::new((
+            Errno(libc::EBUSY),
+            format!(
+                "{} is a path of multipath device {}, use that instead",
+                path.display(),
+                mpath.display()
+            ),
+        )));
+    }
+    Ok(())
+}
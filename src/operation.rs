@@ -0,0 +1,245 @@
+//! Timeouts and cancellation for operations that can block waiting on VG
+//! or global locks.  The liblvm call runs on a worker thread which is
+//! abandoned if it doesn't finish in time.  liblvm isn't thread safe so
+//! while an abandoned call is still running further timed operations on
+//! the same Lvm fail with EBUSY, and untimed ones, closing a VG or
+//! dropping a VG or the Lvm wait for it to finish.  Use
+//! `Lvm::has_abandoned_calls` to check without waiting.
+
+use std::cell::Cell;
+use std::ffi::CString;
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use errno::Errno;
+use lvm_sys::lvm_vg_close;
+
+use crate::audit::AuditHook;
+use crate::{LogicalVolume, Lvm, LvmError, LvmResult, OpenMode, VolumeGroup};
+
+/// How often a waiting caller checks its deadline and cancellation token
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Lets another thread give up on a running operation
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Cancel every operation using this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Limits on how long a blocking operation may run
+#[derive(Clone, Debug, Default)]
+pub struct OperationOptions {
+    /// Give up with LvmError::Timeout after this long
+    pub timeout: Option<Duration>,
+    /// Give up with LvmError::Cancelled once this is cancelled
+    pub cancellation_token: Option<CancellationToken>,
+}
+
+/// Moves a liblvm handle to the worker thread
+struct SendPtr<T>(T);
+
+unsafe impl<T> Send for SendPtr<T> {}
+
+impl Lvm {
+    /// Check if a call abandoned after a timeout or cancellation is still
+    /// using the handle
+    pub fn has_abandoned_calls(&self) -> bool {
+        let mut abandoned = self.abandoned.borrow_mut();
+        abandoned.retain(|worker| !worker.is_finished());
+        !abandoned.is_empty()
+    }
+
+    /// Run f against this handle on a worker thread.  If the caller gives
+    /// up before f finishes, cleanup is handed whatever f eventually
+    /// returns.
+    fn run_with<T, F, C>(
+        &self,
+        op: &str,
+        options: &OperationOptions,
+        f: F,
+        cleanup: C,
+    ) -> LvmResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Lvm) -> LvmResult<T> + Send + 'static,
+        C: FnOnce(T) + Send + 'static,
+    {
+        if self.has_abandoned_calls() {
+            return Err(LvmError::new((
+                Errno(libc::EBUSY),
+                format!("Unable to {}, an abandoned call is still running", op),
+            )));
+        }
+        let handle = SendPtr(self.handle);
+        let lock_retry = self.lock_retry.get();
+        let test_mode = self.test_mode.get();
+        let config_overrides = self.config_overrides.borrow().clone();
+        let container_mode = self.container_mode.get();
+        let audit = self.audit.borrow().clone();
+        let (tx, rx) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            // Borrow the handle without taking ownership of it
+            let lvm = ManuallyDrop::new(Lvm::from_handle(handle.0));
+            lvm.lock_retry.set(lock_retry);
+            lvm.test_mode.set(test_mode);
+            *lvm.config_overrides.borrow_mut() = config_overrides;
+            lvm.container_mode.set(container_mode);
+            *lvm.audit.borrow_mut() = audit;
+            let result = f(&lvm);
+            // The stub is never dropped, release the auditor it shares
+            *lvm.audit.borrow_mut() = AuditHook::default();
+            if let Err(mpsc::SendError(Ok(value))) = tx.send(result) {
+                cleanup(value);
+            }
+        });
+
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            match rx.recv_timeout(POLL_INTERVAL) {
+                Ok(result) => {
+                    let _ = worker.join();
+                    return result;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    let _ = worker.join();
+                    return Err(LvmError::new((
+                        Errno(libc::EIO),
+                        format!("Worker running {} panicked", op),
+                    )));
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            if let Some(ref token) = options.cancellation_token {
+                if token.is_cancelled() {
+                    self.abandoned.borrow_mut().push(worker);
+                    return Err(LvmError::Cancelled(format!("{} was cancelled", op)));
+                }
+            }
+            if let Some(deadline) = deadline {
+                if Instant::now() >= deadline {
+                    self.abandoned.borrow_mut().push(worker);
+                    return Err(LvmError::Timeout(format!(
+                        "{} didn't finish within {:?}",
+                        op,
+                        options.timeout.unwrap_or_default()
+                    )));
+                }
+            }
+        }
+    }
+
+    /// Scan all devices on the system for VGs and LVM metadata, giving up
+    /// according to options
+    pub fn scan_with(&self, options: &OperationOptions) -> LvmResult<()> {
+        self.run_with("scan", options, |lvm| lvm.scan(), |_| {})
+    }
+
+    /// Open a VG, giving up according to options.  A VG the abandoned call
+    /// opens afterwards is closed again.
+    pub fn vg_open_with(
+        &self,
+        name: &str,
+        mode: &OpenMode,
+        options: &OperationOptions,
+    ) -> LvmResult<VolumeGroup<'_>> {
+        // Fail on bad names here rather than on the worker
+        CString::new(name)?;
+        let mode = *mode;
+        let vg_name = name.to_string();
+        let handle = self.run_with(
+            &format!("open {}", name),
+            options,
+            move |lvm| {
+                let vg = lvm.vg_open(&vg_name, &mode)?;
+                Ok(SendPtr(vg.handle.replace(ptr::null_mut())))
+            },
+            |handle| unsafe {
+                lvm_vg_close(handle.0);
+            },
+        )?;
+        Ok(VolumeGroup {
+            handle: Cell::new(handle.0),
+            lvm: self,
+            mode,
+            lock_retry: None,
+            generation: Cell::new(0),
+            pins: Cell::new(0),
+        })
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Write a VG to disk, giving up according to options
+    pub fn write_with(&mut self, options: &OperationOptions) -> LvmResult<()> {
+        let handle = SendPtr(self.handle()?);
+        let mode = self.mode;
+        let lock_retry = self.lock_retry;
+        self.lvm.run_with(
+            &format!("write {}", self.get_name()?),
+            options,
+            move |lvm| {
+                let vg = ManuallyDrop::new(VolumeGroup {
+                    handle: Cell::new(handle.0),
+                    lvm,
+                    mode,
+                    lock_retry,
+                    generation: Cell::new(0),
+                    pins: Cell::new(0),
+                });
+                vg.commit()
+            },
+            |_| {},
+        )
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Resize logical volume to new_size bytes, giving up according to
+    /// options
+    pub fn resize_with(&mut self, new_size: u64, options: &OperationOptions) -> LvmResult<()> {
+        let handles = SendPtr((self.handle()?, self.vg.handle()?));
+        let mode = self.vg.mode;
+        let lock_retry = self.vg.lock_retry;
+        self.lvm.run_with(
+            &format!("resize {}", self.try_name()?),
+            options,
+            move |lvm| {
+                let (lv_handle, vg_handle) = handles.0;
+                let vg = ManuallyDrop::new(VolumeGroup {
+                    handle: Cell::new(vg_handle),
+                    lvm,
+                    mode,
+                    lock_retry,
+                    generation: Cell::new(0),
+                    pins: Cell::new(0),
+                });
+                let mut lv = LogicalVolume {
+                    handle: lv_handle,
+                    lvm,
+                    vg: &vg,
+                    generation: 0,
+                };
+                lv.resize(new_size)
+            },
+            |_| {},
+        )
+    }
+}
@@ -0,0 +1,218 @@
+//! Check the environment before a provisioning run: that the process is
+//! privileged enough, device-mapper and the kernel targets are there, the
+//! daemons lvm.conf asks for are running and locking and udev will work.
+//! Each problem comes with what to do about it.
+
+use std::env;
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+
+use crate::version::TargetType;
+use crate::Lvm;
+
+const DM_CONTROL: &str = "/dev/mapper/control";
+const LVMETAD_SOCKET: &str = "/run/lvm/lvmetad.socket";
+const LVMLOCKD_SOCKET: &str = "/run/lvm/lvmlockd.socket";
+const UDEV_CONTROL: &str = "/run/udev/control";
+const DEFAULT_LOCKING_DIR: &str = "/run/lock/lvm";
+const DEFAULT_SYSTEM_DIR: &str = "/etc/lvm";
+
+/// How bad a finding is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Ok,
+    /// Some operations may fail or misbehave
+    Warning,
+    /// LVM operations will fail
+    Error,
+}
+
+/// The outcome of one check
+#[derive(Debug)]
+pub struct Finding {
+    /// Which check, ie: device-mapper
+    pub check: &'static str,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Everything preflight found
+#[derive(Debug, Default)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    fn add(&mut self, check: &'static str, severity: Severity, message: String) {
+        self.findings.push(Finding {
+            check,
+            severity,
+            message,
+        });
+    }
+
+    /// No check found an error, warnings are allowed
+    pub fn is_ok(&self) -> bool {
+        !self.findings.iter().any(|f| f.severity == Severity::Error)
+    }
+
+    /// Findings that are warnings or errors
+    pub fn problems(&self) -> Vec<&Finding> {
+        self.findings
+            .iter()
+            .filter(|f| f.severity != Severity::Ok)
+            .collect()
+    }
+}
+
+/// Check if the process could create files in dir, or create dir itself
+/// if it doesn't exist yet
+fn writable(dir: &Path) -> bool {
+    let existing = dir.ancestors().find(|d| d.exists()).unwrap_or(dir);
+    match CString::new(existing.to_string_lossy().as_bytes()) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+impl Lvm {
+    /// The system directory, the one passed to new or else LVM_SYSTEM_DIR's
+    pub(crate) fn system_dir(&self) -> PathBuf {
+        self.system_dir
+            .clone()
+            .or_else(|| env::var_os("LVM_SYSTEM_DIR").map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_SYSTEM_DIR))
+    }
+
+    /// The locking directory, from an override if one was set
+    pub(crate) fn locking_dir(&self) -> PathBuf {
+        self.config_overrides
+            .borrow()
+            .iter()
+            .rev()
+            .find_map(|setting| setting.strip_prefix("global/locking_dir="))
+            .map(|dir| PathBuf::from(dir.trim_matches('"')))
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_LOCKING_DIR))
+    }
+
+    /// Check the environment for anything that would make LVM operations
+    /// fail and report what was found
+    pub fn preflight(&self) -> Report {
+        let mut report = Report::default();
+
+        match Lvm::check_privileges() {
+            Ok(()) => report.add("privileges", Severity::Ok, "Running privileged".into()),
+            Err(e) => report.add("privileges", Severity::Error, e.to_string()),
+        }
+
+        if !Path::new(DM_CONTROL).exists() {
+            report.add(
+                "device-mapper",
+                Severity::Error,
+                format!(
+                    "{} is missing, load the dm_mod module or, in a container, \
+                     bind mount /dev/mapper from the host",
+                    DM_CONTROL
+                ),
+            );
+        } else {
+            match self.driver_version() {
+                Ok(version) => report.add(
+                    "device-mapper",
+                    Severity::Ok,
+                    format!("Driver version {}", version),
+                ),
+                Err(e) => report.add(
+                    "device-mapper",
+                    Severity::Error,
+                    format!("Unable to talk to device-mapper: {}", e),
+                ),
+            }
+        }
+
+        let targets = [
+            (TargetType::Snapshot, "dm_snapshot", "snapshots"),
+            (TargetType::Mirror, "dm_mirror", "mirrors and pvmove"),
+            (TargetType::Raid, "dm_raid", "RAID LVs"),
+            (TargetType::ThinPool, "dm_thin_pool", "thin provisioning"),
+            (TargetType::Cache, "dm_cache", "caching"),
+        ];
+        for (target, module, used_for) in targets.iter() {
+            if self.has_target(target) {
+                report.add(
+                    "kernel modules",
+                    Severity::Ok,
+                    format!("{} available", module),
+                );
+            } else {
+                report.add(
+                    "kernel modules",
+                    Severity::Warning,
+                    format!("{} isn't available, {} won't work", module, used_for),
+                );
+            }
+        }
+
+        let daemons = [
+            ("global/use_lvmetad", "lvmetad", LVMETAD_SOCKET),
+            ("global/use_lvmlockd", "lvmlockd", LVMLOCKD_SOCKET),
+        ];
+        for (setting, daemon, socket) in daemons.iter() {
+            match self.config_find_bool(setting, false) {
+                Ok(true) if !Path::new(socket).exists() => report.add(
+                    "daemons",
+                    Severity::Error,
+                    format!(
+                        "{} is enabled by {} but isn't running, start it or disable it",
+                        daemon, setting
+                    ),
+                ),
+                Ok(true) => report.add("daemons", Severity::Ok, format!("{} is running", daemon)),
+                Ok(false) => report.add("daemons", Severity::Ok, format!("{} isn't used", daemon)),
+                Err(e) => report.add(
+                    "daemons",
+                    Severity::Warning,
+                    format!("Unable to read {}: {}", setting, e),
+                ),
+            }
+        }
+
+        let locking_dir = self.locking_dir();
+        if writable(&locking_dir) {
+            report.add(
+                "locking",
+                Severity::Ok,
+                format!("{} is writable", locking_dir.display()),
+            );
+        } else {
+            report.add(
+                "locking",
+                Severity::Error,
+                format!(
+                    "Locking directory {} isn't writable, fix its permissions or set \
+                     another with LvmBuilder::locking_dir",
+                    locking_dir.display()
+                ),
+            );
+        }
+
+        let udev_sync = self
+            .config_find_bool("activation/udev_sync", true)
+            .unwrap_or(true);
+        if self.is_container_mode() || !udev_sync {
+            report.add("udev", Severity::Ok, "udev isn't used".into());
+        } else if Path::new(UDEV_CONTROL).exists() {
+            report.add("udev", Severity::Ok, "udev is running".into());
+        } else {
+            report.add(
+                "udev",
+                Severity::Warning,
+                "udev isn't running so activation may wait for it, use container mode \
+                 or disable activation/udev_sync"
+                    .into(),
+            );
+        }
+
+        report
+    }
+}
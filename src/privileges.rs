@@ -0,0 +1,39 @@
+//! Catch a process that can't manage LVM before its first call fails with
+//! an unhelpful "permission denied" opening a device or lock file.
+
+use std::fs;
+
+use crate::{Lvm, LvmError, LvmResult};
+
+/// Bit of CAP_SYS_ADMIN in the capability sets of /proc/self/status
+const CAP_SYS_ADMIN: u32 = 21;
+
+/// Check if the effective capability set of this process has CAP_SYS_ADMIN
+fn has_sys_admin() -> bool {
+    let status = match fs::read_to_string("/proc/self/status") {
+        Ok(status) => status,
+        Err(_) => return false,
+    };
+    status
+        .lines()
+        .find(|line| line.starts_with("CapEff:"))
+        .and_then(|line| u64::from_str_radix(line["CapEff:".len()..].trim(), 16).ok())
+        .map(|caps| caps & (1 << CAP_SYS_ADMIN) != 0)
+        .unwrap_or(false)
+}
+
+impl Lvm {
+    /// Check that this process can manage LVM: it has to be root or hold
+    /// CAP_SYS_ADMIN to open block devices, talk to device-mapper and take
+    /// the VG locks.
+    pub fn check_privileges() -> LvmResult<()> {
+        if unsafe { libc::geteuid() } == 0 || has_sys_admin() {
+            return Ok(());
+        }
+        Err(LvmError::InsufficientPrivileges(
+            "LVM operations need root or CAP_SYS_ADMIN, run as root, with sudo or \
+             grant the process CAP_SYS_ADMIN"
+                .to_string(),
+        ))
+    }
+}
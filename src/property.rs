@@ -0,0 +1,314 @@
+//! Reading any PV, VG or LV field through liblvm's generic property API.
+//! Many fields, ie: lv_layout, lv_role or pool_lv, have no getter of their
+//! own.  The names are the ones `lvs -o help`, `vgs -o help` and
+//! `pvs -o help` list, the constants and the LvField, VgField and PvField
+//! enums below cover the commonly used ones.
+
+use std::ffi::{CStr, CString};
+use std::fmt;
+
+use errno::Errno;
+use lvm_sys::*;
+
+use crate::{LogicalVolume, Lvm, LvmError, LvmResult, PhysicalVolume, VolumeGroup};
+
+pub const LV_NAME: &str = "lv_name";
+pub const LV_UUID: &str = "lv_uuid";
+pub const LV_ATTR: &str = "lv_attr";
+pub const LV_PATH: &str = "lv_path";
+pub const LV_SIZE: &str = "lv_size";
+/// Comma separated, ie: thin,pool or raid,raid1
+pub const LV_LAYOUT: &str = "lv_layout";
+/// Comma separated, ie: public or private,thin,pool,data
+pub const LV_ROLE: &str = "lv_role";
+pub const LV_TAGS: &str = "lv_tags";
+pub const LV_READ_AHEAD: &str = "lv_read_ahead";
+pub const LV_KERNEL_MAJOR: &str = "lv_kernel_major";
+pub const LV_KERNEL_MINOR: &str = "lv_kernel_minor";
+/// Empty when healthy, see LogicalVolume::health
+pub const LV_HEALTH_STATUS: &str = "lv_health_status";
+/// error or queue for thin pools, see LogicalVolume::when_full
+pub const LV_WHEN_FULL: &str = "lv_when_full";
+pub const SEG_COUNT: &str = "seg_count";
+pub const ORIGIN: &str = "origin";
+pub const ORIGIN_SIZE: &str = "origin_size";
+pub const POOL_LV: &str = "pool_lv";
+pub const DATA_LV: &str = "data_lv";
+pub const METADATA_LV: &str = "metadata_lv";
+pub const MOVE_PV: &str = "move_pv";
+pub const CONVERT_LV: &str = "convert_lv";
+pub const MIRROR_LOG: &str = "mirror_log";
+/// Fixed point, see DATA_PERCENT
+pub const SNAP_PERCENT: &str = "snap_percent";
+/// Fixed point, 1000000 units per percent, -1 when it doesn't apply
+pub const DATA_PERCENT: &str = "data_percent";
+/// Fixed point, see DATA_PERCENT
+pub const METADATA_PERCENT: &str = "metadata_percent";
+/// Fixed point, see DATA_PERCENT
+pub const COPY_PERCENT: &str = "copy_percent";
+
+pub const VG_NAME: &str = "vg_name";
+pub const VG_UUID: &str = "vg_uuid";
+pub const VG_FMT: &str = "vg_fmt";
+pub const VG_ATTR: &str = "vg_attr";
+pub const VG_SIZE: &str = "vg_size";
+pub const VG_FREE: &str = "vg_free";
+pub const VG_SYSID: &str = "vg_sysid";
+pub const VG_EXTENT_SIZE: &str = "vg_extent_size";
+pub const VG_EXTENT_COUNT: &str = "vg_extent_count";
+pub const VG_FREE_COUNT: &str = "vg_free_count";
+pub const MAX_LV: &str = "max_lv";
+pub const MAX_PV: &str = "max_pv";
+pub const PV_COUNT: &str = "pv_count";
+pub const LV_COUNT: &str = "lv_count";
+pub const SNAP_COUNT: &str = "snap_count";
+pub const VG_SEQNO: &str = "vg_seqno";
+pub const VG_TAGS: &str = "vg_tags";
+pub const VG_MDA_COUNT: &str = "vg_mda_count";
+pub const VG_MDA_FREE: &str = "vg_mda_free";
+pub const VG_MDA_SIZE: &str = "vg_mda_size";
+pub const VG_MDA_COPIES: &str = "vg_mda_copies";
+
+pub const PV_NAME: &str = "pv_name";
+pub const PV_UUID: &str = "pv_uuid";
+pub const PV_FMT: &str = "pv_fmt";
+pub const PV_ATTR: &str = "pv_attr";
+pub const PV_SIZE: &str = "pv_size";
+pub const PV_FREE: &str = "pv_free";
+pub const PV_USED: &str = "pv_used";
+pub const PV_TAGS: &str = "pv_tags";
+pub const DEV_SIZE: &str = "dev_size";
+pub const PE_START: &str = "pe_start";
+pub const PV_PE_COUNT: &str = "pv_pe_count";
+pub const PV_PE_ALLOC_COUNT: &str = "pv_pe_alloc_count";
+pub const PV_MDA_COUNT: &str = "pv_mda_count";
+pub const PV_MDA_FREE: &str = "pv_mda_free";
+pub const PV_MDA_SIZE: &str = "pv_mda_size";
+pub const PV_BA_START: &str = "pv_ba_start";
+pub const PV_BA_SIZE: &str = "pv_ba_size";
+
+macro_rules! fields {
+    ($(#[$doc:meta])* $field:ident { $($(#[$vdoc:meta])* $variant:ident => $name:ident,)* }) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+        pub enum $field {
+            $($(#[$vdoc])* $variant,)*
+        }
+
+        impl $field {
+            /// Every field, ie: to report all of them
+            pub const ALL: &'static [$field] = &[$($field::$variant,)*];
+
+            /// The property name liblvm and the lvm tools know the field by
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($field::$variant => $name,)*
+                }
+            }
+        }
+
+        impl fmt::Display for $field {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+    };
+}
+
+fields! {
+    /// Well known LV report fields, see LogicalVolume::get_field
+    LvField {
+        Name => LV_NAME,
+        Uuid => LV_UUID,
+        Attr => LV_ATTR,
+        Path => LV_PATH,
+        Size => LV_SIZE,
+        Layout => LV_LAYOUT,
+        Role => LV_ROLE,
+        Tags => LV_TAGS,
+        ReadAhead => LV_READ_AHEAD,
+        KernelMajor => LV_KERNEL_MAJOR,
+        KernelMinor => LV_KERNEL_MINOR,
+        HealthStatus => LV_HEALTH_STATUS,
+        WhenFull => LV_WHEN_FULL,
+        SegCount => SEG_COUNT,
+        Origin => ORIGIN,
+        OriginSize => ORIGIN_SIZE,
+        PoolLv => POOL_LV,
+        DataLv => DATA_LV,
+        MetadataLv => METADATA_LV,
+        MovePv => MOVE_PV,
+        ConvertLv => CONVERT_LV,
+        MirrorLog => MIRROR_LOG,
+        SnapPercent => SNAP_PERCENT,
+        DataPercent => DATA_PERCENT,
+        MetadataPercent => METADATA_PERCENT,
+        CopyPercent => COPY_PERCENT,
+    }
+}
+
+fields! {
+    /// Well known VG report fields, see VolumeGroup::get_field
+    VgField {
+        Name => VG_NAME,
+        Uuid => VG_UUID,
+        Fmt => VG_FMT,
+        Attr => VG_ATTR,
+        Size => VG_SIZE,
+        Free => VG_FREE,
+        SysId => VG_SYSID,
+        ExtentSize => VG_EXTENT_SIZE,
+        ExtentCount => VG_EXTENT_COUNT,
+        FreeCount => VG_FREE_COUNT,
+        MaxLv => MAX_LV,
+        MaxPv => MAX_PV,
+        PvCount => PV_COUNT,
+        LvCount => LV_COUNT,
+        SnapCount => SNAP_COUNT,
+        SeqNo => VG_SEQNO,
+        Tags => VG_TAGS,
+        MdaCount => VG_MDA_COUNT,
+        MdaFree => VG_MDA_FREE,
+        MdaSize => VG_MDA_SIZE,
+        MdaCopies => VG_MDA_COPIES,
+    }
+}
+
+fields! {
+    /// Well known PV report fields, see PhysicalVolume::get_field
+    PvField {
+        Name => PV_NAME,
+        Uuid => PV_UUID,
+        Fmt => PV_FMT,
+        Attr => PV_ATTR,
+        Size => PV_SIZE,
+        Free => PV_FREE,
+        Used => PV_USED,
+        Tags => PV_TAGS,
+        DevSize => DEV_SIZE,
+        PeStart => PE_START,
+        PeCount => PV_PE_COUNT,
+        PeAllocCount => PV_PE_ALLOC_COUNT,
+        MdaCount => PV_MDA_COUNT,
+        MdaFree => PV_MDA_FREE,
+        MdaSize => PV_MDA_SIZE,
+        BaStart => PV_BA_START,
+        BaSize => PV_BA_SIZE,
+    }
+}
+
+/// The value of a property as liblvm reports it
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropertyValue {
+    Integer(u64),
+    SignedInteger(i64),
+    String(String),
+}
+
+impl PropertyValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PropertyValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value of an integer property, None for strings and negative
+    /// signed integers
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            PropertyValue::Integer(n) => Some(*n),
+            PropertyValue::SignedInteger(n) if *n >= 0 => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    /// The value of an integer property, None for strings and unsigned
+    /// integers too large for an i64
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            PropertyValue::Integer(n) if *n <= i64::MAX as u64 => Some(*n as i64),
+            PropertyValue::SignedInteger(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+unsafe fn property_value(
+    lvm: &Lvm,
+    name: &str,
+    value: lvm_property_value,
+) -> LvmResult<PropertyValue> {
+    if value.is_valid() == 0 {
+        let err = lvm.get_error()?;
+        return Err(LvmError::new((err.0, err.1)));
+    }
+    if value.is_string() != 0 {
+        if value.value.string.is_null() {
+            return Ok(PropertyValue::String(String::new()));
+        }
+        let s = CStr::from_ptr(value.value.string).to_string_lossy();
+        return Ok(PropertyValue::String(s.into_owned()));
+    }
+    if value.is_integer() != 0 {
+        if value.is_signed() != 0 {
+            return Ok(PropertyValue::SignedInteger(value.value.signed_integer));
+        }
+        return Ok(PropertyValue::Integer(value.value.integer));
+    }
+    Err(LvmError::new((
+        Errno(libc::EINVAL),
+        format!("Property {} has an unknown type", name),
+    )))
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Get any property of a logical volume by name, see the constants in
+    /// this module.  Unknown names fail with the error liblvm reports.
+    pub fn get_property_value(&self, name: &str) -> LvmResult<PropertyValue> {
+        let prop_name = CString::new(name)?;
+        unsafe {
+            let value = lvm_lv_get_property(self.handle()?, prop_name.as_ptr());
+            property_value(self.lvm, name, value)
+        }
+    }
+
+    /// Get a well known property of a logical volume
+    pub fn get_field(&self, field: LvField) -> LvmResult<PropertyValue> {
+        self.get_property_value(field.name())
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Get any property of a volume group by name, see the constants in
+    /// this module.  Unknown names fail with the error liblvm reports.
+    pub fn get_property_value(&self, name: &str) -> LvmResult<PropertyValue> {
+        let prop_name = CString::new(name)?;
+        unsafe {
+            let value = lvm_vg_get_property(self.handle()?, prop_name.as_ptr());
+            property_value(self.lvm, name, value)
+        }
+    }
+
+    /// Get a well known property of a volume group
+    pub fn get_field(&self, field: VgField) -> LvmResult<PropertyValue> {
+        self.get_property_value(field.name())
+    }
+}
+
+impl<'a> PhysicalVolume<'a> {
+    /// Get any property of a physical volume by name, see the constants in
+    /// this module.  Unknown names fail with the error liblvm reports.
+    pub fn get_property_value(&self, name: &str) -> LvmResult<PropertyValue> {
+        let prop_name = CString::new(name)?;
+        unsafe {
+            let value = lvm_pv_get_property(self.handle()?, prop_name.as_ptr());
+            property_value(self.lvm, name, value)
+        }
+    }
+
+    /// Get a well known property of a physical volume
+    pub fn get_field(&self, field: PvField) -> LvmResult<PropertyValue> {
+        self.get_property_value(field.name())
+    }
+}
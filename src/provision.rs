@@ -0,0 +1,214 @@
+//! Idempotent provisioning.  Each ensure call creates what is missing,
+//! adjusts what exists to match the spec and reports what it changed, so
+//! running it again is a no-op.
+
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use lvm_sys::*;
+
+use crate::{Lvm, LvmError, LvmResult, OpenMode, VolumeGroup};
+
+/// Something an ensure call changed
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    CreatedPv(PathBuf),
+    CreatedVg(String),
+    /// A PV was added to a VG
+    ExtendedVg {
+        vg: String,
+        pv: PathBuf,
+    },
+    CreatedLv(String),
+    ResizedLv {
+        lv: String,
+        from: u64,
+        to: u64,
+    },
+    AddedTags {
+        lv: String,
+        tags: Vec<String>,
+    },
+    RemovedTags {
+        lv: String,
+        tags: Vec<String>,
+    },
+}
+
+/// The desired state of a linear logical volume
+#[derive(Debug)]
+pub struct LvSpec {
+    name: String,
+    size: u64,
+    tags: Option<Vec<String>>,
+    allow_shrink: bool,
+}
+
+impl LvSpec {
+    /// An LV of at least size bytes, rounded up to the VG's extent size
+    pub fn new(name: &str, size: u64) -> Self {
+        LvSpec {
+            name: name.to_string(),
+            size,
+            tags: None,
+            allow_shrink: false,
+        }
+    }
+
+    /// Make the LV's tags exactly these, removing any others.  Tags are
+    /// left alone if this isn't set.
+    pub fn tags(mut self, tags: &[&str]) -> Self {
+        self.tags = Some(tags.iter().map(|t| t.to_string()).collect());
+        self
+    }
+
+    /// Shrink an existing LV that's larger than the spec.  Off by default
+    /// as shrinking destroys whatever is stored past the new end.
+    pub fn allow_shrink(mut self, allow: bool) -> Self {
+        self.allow_shrink = allow;
+        self
+    }
+}
+
+/// Compare device paths the way the kernel sees them so /dev/vdb and a
+/// /dev/disk/by-id link to it are the same PV
+fn same_device(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+impl Lvm {
+    /// Names of every PV on the system, including those not in a VG
+    fn pv_names(&self) -> LvmResult<Vec<String>> {
+        let mut names = vec![];
+        unsafe {
+            let pvs = lvm_list_pvs(self.handle());
+            if pvs.is_null() {
+                let err = self.get_error()?;
+                return Err(LvmError::new((err.0, err.1)));
+            }
+            let mut pv = dm_list_first(pvs);
+            while !pv.is_null() {
+                let pv_list = pv as *mut lvm_pv_list;
+                let name = lvm_pv_get_name((*pv_list).pv);
+                names.push(CStr::from_ptr(name).to_string_lossy().into_owned());
+                pv = dm_list_next(pvs, pv);
+            }
+            lvm_list_pvs_free(pvs);
+        }
+        Ok(names)
+    }
+
+    /// Make sure device is a PV, creating one using the whole device if not
+    pub fn ensure_pv(&self, device: &Path) -> LvmResult<Vec<Change>> {
+        let exists = self
+            .pv_names()?
+            .iter()
+            .any(|name| same_device(Path::new(name), device));
+        if exists {
+            return Ok(vec![]);
+        }
+        self.pv_create(&device.to_string_lossy(), 0)?;
+        Ok(vec![Change::CreatedPv(device.to_path_buf())])
+    }
+
+    /// Make sure a VG called name exists and contains every device in pvs,
+    /// creating the PVs and VG or extending the VG as needed.  PVs already
+    /// in the VG but not in pvs are left in place.
+    pub fn ensure_vg(&self, name: &str, pvs: &[&Path]) -> LvmResult<Vec<Change>> {
+        let mut changes = vec![];
+        for pv in pvs {
+            changes.extend(self.ensure_pv(pv)?);
+        }
+
+        let mut vg = if self.get_volume_group_names()?.iter().any(|n| n == name) {
+            self.vg_open(name, &OpenMode::Write)?
+        } else {
+            changes.push(Change::CreatedVg(name.to_string()));
+            self.vg_create(name)?
+        };
+        let members = vg
+            .list_pvs()?
+            .iter()
+            .map(|pv| pv.try_name())
+            .collect::<LvmResult<Vec<String>>>()?;
+        for pv in pvs {
+            if members.iter().any(|m| same_device(Path::new(m), pv)) {
+                continue;
+            }
+            vg.extend(pv)?;
+            changes.push(Change::ExtendedVg {
+                vg: name.to_string(),
+                pv: pv.to_path_buf(),
+            });
+        }
+        vg.close()?;
+        Ok(changes)
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Make sure an LV matching spec exists in this VG.  A missing LV is
+    /// created, an existing one is grown (or shrunk if the spec allows it)
+    /// and has its tags brought in line with the spec.
+    pub fn ensure_lv(&mut self, spec: &LvSpec) -> LvmResult<Vec<Change>> {
+        let mut changes = vec![];
+        let size = self.round_size_to_extent(spec.size);
+
+        let exists = self
+            .list_lvs()?
+            .iter()
+            .any(|lv| lv.try_name().ok().as_ref() == Some(&spec.name));
+        let mut lv = if exists {
+            self.lv_from_name(&spec.name)?
+        } else {
+            changes.push(Change::CreatedLv(spec.name.clone()));
+            self.create_lv_linear(&spec.name, size)?.lv
+        };
+
+        let current = lv.try_size()?;
+        if current < size || (current > size && spec.allow_shrink) {
+            lv.resize(size)?;
+            changes.push(Change::ResizedLv {
+                lv: spec.name.clone(),
+                from: current,
+                to: size,
+            });
+        }
+
+        if let Some(ref wanted) = spec.tags {
+            let current: HashSet<String> = lv.get_tags()?.into_iter().collect();
+            let add: Vec<String> = wanted
+                .iter()
+                .filter(|t| !current.contains(*t))
+                .cloned()
+                .collect();
+            let remove: Vec<String> = current
+                .iter()
+                .filter(|t| !wanted.contains(*t))
+                .cloned()
+                .collect();
+            if !add.is_empty() {
+                let tags: Vec<&str> = add.iter().map(|t| t.as_str()).collect();
+                lv.add_tags(&tags)?;
+                changes.push(Change::AddedTags {
+                    lv: spec.name.clone(),
+                    tags: add,
+                });
+            }
+            if !remove.is_empty() {
+                let tags: Vec<&str> = remove.iter().map(|t| t.as_str()).collect();
+                lv.remove_tags(&tags)?;
+                changes.push(Change::RemovedTags {
+                    lv: spec.name.clone(),
+                    tags: remove,
+                });
+            }
+        }
+        Ok(changes)
+    }
+}
@@ -0,0 +1,252 @@
+//! PV creation with every option lvm_pv_create_adv supports, without
+//! driving the parameter object by hand.
+
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use errno::Errno;
+use lvm_sys::*;
+
+use crate::{cli, devices, retry, Lvm, LvmError, LvmResult};
+
+/// Options for a new PV.  Anything left as None uses lvm's default.
+#[derive(Clone, Debug, Default)]
+pub struct PvCreateOptions {
+    /// Size in bytes to use, zero or None for the whole device
+    pub size: Option<u64>,
+    /// Zero the first 2048 bytes of the device
+    pub zero: Option<bool>,
+    /// Align the start of the data area to a multiple of this many bytes
+    pub data_alignment: Option<u64>,
+    /// Shift the start of the data area by this many more bytes
+    pub data_alignment_offset: Option<u64>,
+    /// Number of metadata areas, 0, 1 or 2
+    pub metadata_copies: Option<u8>,
+    /// Approximate size in bytes to set aside for each metadata area
+    pub metadata_size: Option<u64>,
+}
+
+impl PvCreateOptions {
+    /// The liblvm parameter name and integer value of every option set
+    fn properties(&self) -> Vec<(&'static str, u64)> {
+        let mut properties = vec![];
+        if let Some(size) = self.size {
+            properties.push(("size", size));
+        }
+        if let Some(zero) = self.zero {
+            properties.push(("zero", zero as u64));
+        }
+        if let Some(alignment) = self.data_alignment {
+            properties.push(("data_alignment", alignment));
+        }
+        if let Some(offset) = self.data_alignment_offset {
+            properties.push(("data_alignment_offset", offset));
+        }
+        if let Some(copies) = self.metadata_copies {
+            properties.push(("pvmetadatacopies", u64::from(copies)));
+        }
+        if let Some(size) = self.metadata_size {
+            properties.push(("pvmetadatasize", size));
+        }
+        properties
+    }
+
+    /// The same options as pvcreate arguments
+    fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(size) = self.size {
+            args.push(format!("--setphysicalvolumesize={}b", size));
+        }
+        if let Some(zero) = self.zero {
+            args.push(format!("--zero={}", if zero { "y" } else { "n" }));
+        }
+        if let Some(alignment) = self.data_alignment {
+            args.push(format!("--dataalignment={}b", alignment));
+        }
+        if let Some(offset) = self.data_alignment_offset {
+            args.push(format!("--dataalignmentoffset={}b", offset));
+        }
+        if let Some(copies) = self.metadata_copies {
+            args.push(format!("--pvmetadatacopies={}", copies));
+        }
+        if let Some(size) = self.metadata_size {
+            args.push(format!("--metadatasize={}b", size));
+        }
+        args
+    }
+}
+
+impl Lvm {
+    /// Build an error for a failed step of PV creation with liblvm's error
+    /// and what was being done
+    fn pv_create_error(&self, device: &Path, step: &str) -> LvmError {
+        match self.get_error() {
+            Ok((errno, msg)) => LvmError::new((
+                errno,
+                format!("Creating PV {} failed {}: {}", device.display(), step, msg),
+            )),
+            Err(e) => e,
+        }
+    }
+
+    /// Initialize device as a PV with options.  Paths of multipath devices
+    /// and md RAID members are refused.
+    pub fn pv_create_adv(&self, device: &Path, options: &PvCreateOptions) -> LvmResult<()> {
+        devices::check_pv_candidate(device)?;
+        let name = CString::new(device.to_string_lossy().as_bytes())?;
+        let device_name = device.to_string_lossy();
+        let params: Vec<(&str, String)> = options
+            .properties()
+            .into_iter()
+            .map(|(name, value)| (name, value.to_string()))
+            .collect();
+        retry::retry(self.lock_retry.get(), || {
+            self.audited("lvm_pv_create_adv", &device_name, &params, || unsafe {
+                let params = lvm_pv_params_create(self.handle(), name.as_ptr());
+                if params.is_null() {
+                    return Err(self.pv_create_error(device, "creating parameters"));
+                }
+                for (property, value) in options.properties() {
+                    let prop_name = CString::new(property)?;
+                    let mut prop = lvm_pv_params_get_property(params, prop_name.as_ptr());
+                    if prop.is_valid() == 0 {
+                        return Err(self.pv_create_error(device, &format!("getting {}", property)));
+                    }
+                    prop.value.integer = value;
+                    if lvm_pv_params_set_property(params, prop_name.as_ptr(), &mut prop) < 0 {
+                        return Err(self.pv_create_error(
+                            device,
+                            &format!("setting {} to {}", property, value),
+                        ));
+                    }
+                }
+                if lvm_pv_create_adv(params) < 0 {
+                    return Err(self.pv_create_error(device, "writing the PV"));
+                }
+                Ok(())
+            })
+        })
+    }
+
+    /// Initialize every device as a PV with options, one after another,
+    /// and return the outcome for each.  A failure doesn't stop the rest.
+    pub fn pv_create_many(
+        &self,
+        devices: &[&Path],
+        options: &PvCreateOptions,
+    ) -> Vec<(PathBuf, LvmResult<()>)> {
+        devices
+            .iter()
+            .map(|device| (device.to_path_buf(), self.pv_create_adv(device, options)))
+            .collect()
+    }
+
+    /// Like pv_create_many but runs pvcreate for up to max_threads devices
+    /// at once.  liblvm can't be used from several threads so this goes
+    /// through the command line tools.  Outcomes are in the order of
+    /// devices.  Multipath paths and md RAID members are refused with EBUSY
+    /// up front, as pv_create_adv would, and the rest still go ahead.
+    pub fn pv_create_many_parallel(
+        &self,
+        devices: &[&Path],
+        options: &PvCreateOptions,
+        max_threads: usize,
+    ) -> Vec<(PathBuf, LvmResult<()>)> {
+        let policy = self.lock_retry.get();
+        let options = options.args();
+        let commands: Vec<Vec<String>> = devices
+            .iter()
+            .map(|device| {
+                let mut args = vec!["pvcreate".to_string()];
+                args.extend(options.iter().cloned());
+                args.push(device.to_string_lossy().into_owned());
+                args
+            })
+            .collect();
+        let mut refused = vec![];
+        let queue: Vec<(usize, Vec<String>)> = commands
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| match devices::check_pv_candidate(devices[*i]) {
+                Ok(()) => true,
+                Err(e) => {
+                    refused.push((*i, Err(e)));
+                    false
+                }
+            })
+            .map(|(i, args)| {
+                let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+                (i, self.command_args(&args))
+            })
+            .collect();
+        let queued = queue.len();
+        let queue = Arc::new(Mutex::new(queue));
+        let results = Arc::new(Mutex::new(vec![]));
+
+        let workers: Vec<_> = (0..max_threads.max(1).min(queued))
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                let results = Arc::clone(&results);
+                let system_dir = self.system_dir.clone();
+                thread::spawn(move || loop {
+                    let next = match queue.lock() {
+                        Ok(mut queue) => queue.pop(),
+                        Err(_) => None,
+                    };
+                    let (i, args) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+                    let time = SystemTime::now();
+                    let start = Instant::now();
+                    let result =
+                        retry::retry(policy, || cli::run(system_dir.as_deref(), &args)).map(|_| ());
+                    if let Ok(mut results) = results.lock() {
+                        results.push((i, result, time, start.elapsed()));
+                    }
+                })
+            })
+            .collect();
+        let mut panicked = false;
+        for worker in workers {
+            panicked |= worker.join().is_err();
+        }
+
+        let mut results = match results.lock() {
+            Ok(mut results) => std::mem::take(&mut *results),
+            Err(_) => vec![],
+        };
+        // Anything a panicked worker didn't report
+        if panicked || results.len() != queued {
+            for (i, device) in devices.iter().enumerate() {
+                if !results.iter().any(|(j, _, _, _)| *j == i)
+                    && !refused.iter().any(|(j, _)| *j == i)
+                {
+                    let err = LvmError::new((
+                        Errno(libc::EIO),
+                        format!("Creating PV {} failed: worker panicked", device.display()),
+                    ));
+                    results.push((i, Err(err), SystemTime::now(), Duration::default()));
+                }
+            }
+        }
+        let mut outcomes: Vec<(usize, LvmResult<()>)> = results
+            .into_iter()
+            .map(|(i, result, time, duration)| {
+                let args: Vec<&str> = commands[i].iter().map(|a| a.as_str()).collect();
+                self.record_command(&args, time, duration, &result);
+                (i, result)
+            })
+            .collect();
+        outcomes.append(&mut refused);
+        outcomes.sort_by_key(|(i, _)| *i);
+        outcomes
+            .into_iter()
+            .map(|(i, result)| (devices[i].to_path_buf(), result))
+            .collect()
+    }
+}
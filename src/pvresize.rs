@@ -0,0 +1,149 @@
+//! Shrinking a PV without cutting off allocated extents.  lvm_pv_resize
+//! will shrink a PV below extents that are in use, destroying whatever LVs
+//! were on them.  Growing a PV after the disk under it grew is
+//! resize_to_device.
+
+use std::fs;
+use std::path::Path;
+
+use errno::Errno;
+
+use crate::{cli, LvmError, LvmResult, PhysicalVolume};
+
+/// What resize_to_device changed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PvGrowth {
+    /// Size of the PV in bytes before and after
+    pub old_size: u64,
+    pub new_size: u64,
+    /// Extents the VG gained, 0 for PVs that aren't in a VG
+    pub new_extents: u64,
+}
+
+/// Size in bytes of the block device at path as the kernel sees it now,
+/// asking SCSI disks to rescan their capacity first
+fn device_size(path: &Path) -> LvmResult<u64> {
+    let resolved = fs::canonicalize(path)?;
+    let name = resolved.file_name().ok_or_else(|| {
+        LvmError::new((
+            Errno(libc::EINVAL),
+            format!("{} is not a block device", path.display()),
+        ))
+    })?;
+    let sysfs = fs::canonicalize(Path::new("/sys/class/block").join(name))?;
+    // Partitions live in their disk's directory, only disks can rescan
+    let disk = if sysfs.join("partition").exists() {
+        sysfs.parent().map(|p| p.to_path_buf())
+    } else {
+        Some(sysfs.clone())
+    };
+    if let Some(rescan) = disk.map(|d| d.join("device/rescan")) {
+        if rescan.exists() {
+            if let Err(e) = fs::write(&rescan, "1") {
+                warn!("Rescanning {} failed: {}", rescan.display(), e);
+            }
+        }
+    }
+    let sectors = fs::read_to_string(sysfs.join("size"))?;
+    // sysfs counts 512 byte sectors whatever the device's sector size
+    sectors.trim().parse::<u64>().map(|s| s * 512).map_err(|_| {
+        LvmError::new((
+            Errno(libc::EINVAL),
+            format!("Invalid size of {}: {}", path.display(), sectors.trim()),
+        ))
+    })
+}
+
+impl<'a> PhysicalVolume<'a> {
+    /// Smallest size in bytes the PV can be shrunk to while keeping every
+    /// allocated extent
+    pub fn min_size(&self) -> LvmResult<u64> {
+        let rows = cli::report_segments(
+            self.lvm,
+            "pvs",
+            &["vg_extent_size", "pvseg_start", "pvseg_size", "lv_name"],
+            &self.try_name()?,
+        )?;
+        let mut extent_size = 0;
+        let mut extents_used = 0;
+        for row in rows {
+            if row.len() < 4 {
+                continue;
+            }
+            let number = |field: &str| {
+                field.parse::<u64>().map_err(|_| {
+                    LvmError::new((Errno(libc::EINVAL), format!("Invalid pvs value: {}", field)))
+                })
+            };
+            // Orphan PVs have no extent size and no allocated extents
+            if !row[0].is_empty() {
+                extent_size = number(&row[0])?;
+            }
+            if !row[3].is_empty() {
+                extents_used = extents_used.max(number(&row[1])? + number(&row[2])?);
+            }
+        }
+        Ok(self.pe_start()? + extents_used * extent_size)
+    }
+
+    /// How many bytes the PV could be shrunk by without losing data
+    pub fn shrinkable_size(&self) -> LvmResult<u64> {
+        Ok(self.get_size().saturating_sub(self.min_size()?))
+    }
+
+    /// Resize the PV, refusing to shrink it below its highest allocated
+    /// extent
+    pub fn resize_checked(&mut self, new_size: u64) -> LvmResult<()> {
+        let min_size = self.min_size()?;
+        if new_size < min_size {
+            return Err(LvmError::new((
+                Errno(libc::EINVAL),
+                format!(
+                    "Can't shrink PV {} to {} bytes, extents are allocated up to {} bytes, it \
+                     can be shrunk by at most {} bytes",
+                    self.try_name()?,
+                    new_size,
+                    min_size,
+                    self.get_size().saturating_sub(min_size)
+                ),
+            )));
+        }
+        self.resize(new_size)
+    }
+
+    /// Shrink the PV to end right after its highest allocated extent.
+    /// Free extents before that are kept, use VolumeGroup::compact first
+    /// to move them to the end.
+    pub fn shrink_to_fit(&mut self) -> LvmResult<()> {
+        let min_size = self.min_size()?;
+        self.resize(min_size)
+    }
+
+    /// Grow the PV to fill its device, ie: after a virtual disk or the
+    /// partition holding the PV was grown.  The device's size is read from
+    /// the kernel rather than from what lvm saw at scan time.
+    pub fn resize_to_device(&mut self) -> LvmResult<PvGrowth> {
+        let name = self.try_name()?;
+        let old_size = self.get_size();
+        let old_extents = self.get_integer_property("pv_pe_count")?;
+        let new_size = device_size(Path::new(&name))?;
+        if new_size < old_size {
+            return Err(LvmError::new((
+                Errno(libc::EINVAL),
+                format!(
+                    "{} is {} bytes, smaller than its PV of {} bytes",
+                    name, new_size, old_size
+                ),
+            )));
+        }
+        if new_size > old_size {
+            self.resize(new_size)?;
+        }
+        let new_extents = self.get_integer_property("pv_pe_count")?;
+        Ok(PvGrowth {
+            old_size,
+            new_size: self.get_size(),
+            new_extents: new_extents.saturating_sub(old_extents),
+        })
+    }
+}
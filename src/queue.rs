@@ -0,0 +1,135 @@
+//! Block queue settings of the disks under an active LV, read and written
+//! through sysfs.  device-mapper passes IO straight down, so the scheduler
+//! and queue depth that matter are the underlying disks'.  The kernel
+//! forgets the settings on reboot and they apply to everything else on the
+//! same disks too.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use errno::Errno;
+
+use crate::{LogicalVolume, LvmError, LvmResult};
+
+/// Queue settings of a disk.  Settings left as None aren't changed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueueSettings {
+    /// IO scheduler, ie: mq-deadline, bfq or none
+    pub scheduler: Option<String>,
+    /// Number of requests the block layer queues per hardware queue
+    pub nr_requests: Option<u32>,
+    /// Whether the disk is treated as spinning, which changes how the
+    /// schedulers and filesystems lay out IO
+    pub rotational: Option<bool>,
+}
+
+/// The sysfs directory of the whole disk the block device called name is,
+/// or is a partition of
+fn disk_dir(name: &str) -> LvmResult<PathBuf> {
+    let dir = fs::canonicalize(Path::new("/sys/class/block").join(name))?;
+    if !dir.join("partition").exists() {
+        return Ok(dir);
+    }
+    dir.parent().map(|p| p.to_path_buf()).ok_or_else(|| {
+        LvmError::new((
+            Errno(libc::ENOENT),
+            format!("No disk found for partition {}", name),
+        ))
+    })
+}
+
+/// Walk down the slaves of a stacked device to the disks at the bottom
+fn leaf_disks(dir: &Path, disks: &mut Vec<PathBuf>) -> LvmResult<()> {
+    let slaves = dir.join("slaves");
+    let mut found = false;
+    if slaves.is_dir() {
+        for entry in fs::read_dir(&slaves)? {
+            let name = entry?.file_name();
+            leaf_disks(&disk_dir(&name.to_string_lossy())?, disks)?;
+            found = true;
+        }
+    }
+    if !found && !disks.contains(&dir.to_path_buf()) {
+        disks.push(dir.to_path_buf());
+    }
+    Ok(())
+}
+
+fn read_queue(disk: &Path, setting: &str) -> LvmResult<String> {
+    Ok(fs::read_to_string(disk.join("queue").join(setting))?
+        .trim()
+        .to_string())
+}
+
+fn write_queue(disk: &Path, setting: &str, value: &str) -> LvmResult<()> {
+    let path = disk.join("queue").join(setting);
+    info!("Setting {} to {}", path.display(), value);
+    fs::write(&path, value).map_err(|e| {
+        LvmError::new((
+            Errno(e.raw_os_error().unwrap_or(libc::EIO)),
+            format!("Failed to set {} to {}: {}", path.display(), value, e),
+        ))
+    })
+}
+
+fn disk_name(disk: &Path) -> String {
+    disk.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    fn disk_dirs(&self) -> LvmResult<Vec<PathBuf>> {
+        let (major, minor) = self.major_minor()?;
+        let dir = fs::canonicalize(format!("/sys/dev/block/{}:{}", major, minor))?;
+        let mut disks = vec![];
+        leaf_disks(&dir, &mut disks)?;
+        Ok(disks)
+    }
+
+    /// Get the names of the disks an active LV's data lives on, ie: sda
+    /// for an LV on /dev/sda1
+    pub fn underlying_disks(&self) -> LvmResult<Vec<String>> {
+        Ok(self.disk_dirs()?.iter().map(|d| disk_name(d)).collect())
+    }
+
+    /// Get the queue settings of each disk under an active LV
+    pub fn queue_settings(&self) -> LvmResult<Vec<(String, QueueSettings)>> {
+        let mut settings = vec![];
+        for disk in self.disk_dirs()? {
+            // The active scheduler is the one in brackets:
+            // mq-deadline kyber [bfq] none
+            let schedulers = read_queue(&disk, "scheduler")?;
+            let scheduler = schedulers
+                .split_whitespace()
+                .find(|s| s.starts_with('['))
+                .map(|s| s.trim_matches(|c| c == '[' || c == ']').to_string())
+                .or_else(|| Some(schedulers.clone()));
+            settings.push((
+                disk_name(&disk),
+                QueueSettings {
+                    scheduler,
+                    nr_requests: read_queue(&disk, "nr_requests")?.parse().ok(),
+                    rotational: Some(read_queue(&disk, "rotational")? == "1"),
+                },
+            ));
+        }
+        Ok(settings)
+    }
+
+    /// Apply queue settings to every disk under an active LV.  Needs root.
+    pub fn set_queue_settings(&self, settings: &QueueSettings) -> LvmResult<()> {
+        for disk in self.disk_dirs()? {
+            if let Some(ref scheduler) = settings.scheduler {
+                write_queue(&disk, "scheduler", scheduler)?;
+            }
+            if let Some(nr_requests) = settings.nr_requests {
+                write_queue(&disk, "nr_requests", &nr_requests.to_string())?;
+            }
+            if let Some(rotational) = settings.rotational {
+                write_queue(&disk, "rotational", if rotational { "1" } else { "0" })?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+//! Retrying operations that fail because another process holds a VG or
+//! the global lock.  Retries are off by default, set a policy for every VG
+//! with `Lvm::set_lock_retry` or for one VG with
+//! `VolumeGroup::set_lock_retry`.
+
+use std::thread;
+use std::time::Duration;
+
+use crate::{Lvm, LvmError, LvmResult, VolumeGroup};
+
+/// How often and how patiently to retry an operation that couldn't get a
+/// lock.  The delay doubles after every attempt up to max_delay.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, initial_delay: Duration, max_delay: Duration) -> Self {
+        RetryPolicy {
+            max_attempts,
+            initial_delay,
+            max_delay,
+        }
+    }
+
+    /// Never retry
+    pub fn none() -> Self {
+        RetryPolicy::new(1, Duration::from_millis(0), Duration::from_millis(0))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::none()
+    }
+}
+
+/// Messages liblvm and the lvm tools fail with when a lock is held
+const LOCK_ERRORS: &[&str] = &[
+    "can't get lock",
+    "cannot get lock",
+    "failed to get lock",
+    "failed to lock",
+    "resource temporarily unavailable",
+];
+
+/// Check if an error means a lock couldn't be taken, either EAGAIN from
+/// liblvm or a "Can't get lock" style message from it or the lvm tools
+pub(crate) fn is_lock_error(err: &LvmError) -> bool {
+    match err {
+        LvmError::Error((errno, msg)) => {
+            let msg = msg.to_lowercase();
+            errno.0 == libc::EAGAIN || LOCK_ERRORS.iter().any(|e| msg.contains(e))
+        }
+        LvmError::IoError(e) => e.raw_os_error() == Some(libc::EAGAIN),
+        _ => false,
+    }
+}
+
+/// Run f until it succeeds, fails with something other than a lock error
+/// or policy runs out of attempts
+pub(crate) fn retry<T, F>(policy: RetryPolicy, mut f: F) -> LvmResult<T>
+where
+    F: FnMut() -> LvmResult<T>,
+{
+    let mut delay = policy.initial_delay;
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Err(ref e) if attempt < policy.max_attempts && is_lock_error(e) => {
+                debug!(
+                    "lock attempt {} of {} failed, retrying in {:?}: {}",
+                    attempt, policy.max_attempts, delay, e
+                );
+                thread::sleep(delay);
+                delay = delay
+                    .checked_mul(2)
+                    .unwrap_or(policy.max_delay)
+                    .min(policy.max_delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+impl Lvm {
+    /// Retry operations that fail to get a lock according to policy.
+    /// VGs opened from this handle use it unless they set their own.
+    pub fn set_lock_retry(&self, policy: RetryPolicy) {
+        self.lock_retry.set(policy);
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Override the Lvm handle's lock retry policy for this VG, or go back
+    /// to using it with None
+    pub fn set_lock_retry(&mut self, policy: Option<RetryPolicy>) {
+        self.lock_retry = policy;
+    }
+
+    pub(crate) fn lock_retry(&self) -> RetryPolicy {
+        self.lock_retry.unwrap_or_else(|| self.lvm.lock_retry.get())
+    }
+}
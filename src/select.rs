@@ -0,0 +1,533 @@
+//! Find VGs and LVs by tag or with a small selection language modelled on
+//! the lvm tools' --select option, ie:
+//!
+//! ```text
+//! lv_size > 10G && tags = {backup}
+//! (vg_name = data || vg_name = "fast") && !tags = {retired}
+//! ```
+//!
+//! A comparison is `field op value` where op is one of `=`, `!=`, `<`,
+//! `<=`, `>` or `>=`.  Comparisons are combined with `&&`, `||`, `!` and
+//! parentheses.  Values are numbers, bare or quoted strings, or tag sets.
+//! Numbers may carry a size suffix of b, s (512 byte sectors), k, m, g, t,
+//! p or e, all powers of 1024, and are compared against byte sized fields.
+//! A tag set `{a,b}` is matched by `=` when the object carries every tag in
+//! the set.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use errno::Errno;
+
+use crate::size::Size;
+use crate::{LogicalVolume, Lvm, LvmError, LvmResult, OpenMode, VolumeGroup};
+
+/// The value of a field of a VG or LV
+#[derive(Debug)]
+pub enum FieldValue {
+    Number(u64),
+    Text(String),
+    List(Vec<String>),
+}
+
+/// Objects that can be matched against a Selection
+pub trait Selectable {
+    /// Look up a report field by name.  Returns None for unknown fields.
+    fn field(&self, name: &str) -> LvmResult<Option<FieldValue>>;
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+enum Value {
+    Word(String),
+    Quoted(String),
+    Set(Vec<String>),
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(String, Op, Value),
+}
+
+/// A parsed selection expression
+#[derive(Debug)]
+pub struct Selection {
+    expr: Expr,
+}
+
+fn invalid(msg: String) -> LvmError {
+    LvmError::new((Errno(libc::EINVAL), msg))
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || "_.:/-+@".contains(c)
+}
+
+fn tokenize(s: &str) -> LvmResult<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars: Peekable<Chars<'_>> = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        chars.next();
+        let token = match c {
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '{' | '[' => Token::LBrace,
+            '}' | ']' => Token::RBrace,
+            ',' => Token::Comma,
+            '&' | '|' => {
+                if chars.next() != Some(c) {
+                    return Err(invalid(format!("Expected {}{} in selection", c, c)));
+                }
+                if c == '&' {
+                    Token::And
+                } else {
+                    Token::Or
+                }
+            }
+            '=' => Token::Op(Op::Eq),
+            '!' => {
+                if chars.peek() == Some(&'=') {
+                    chars.next();
+                    Token::Op(Op::Ne)
+                } else {
+                    Token::Not
+                }
+            }
+            '<' | '>' => {
+                let or_equal = chars.peek() == Some(&'=');
+                if or_equal {
+                    chars.next();
+                }
+                Token::Op(match (c, or_equal) {
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    ('>', false) => Op::Gt,
+                    _ => Op::Ge,
+                })
+            }
+            '"' | '\'' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(ch) => quoted.push(ch),
+                        None => return Err(invalid("Unterminated string in selection".into())),
+                    }
+                }
+                Token::Quoted(quoted)
+            }
+            c if is_word_char(c) => {
+                let mut word = c.to_string();
+                while let Some(&ch) = chars.peek() {
+                    if !is_word_char(ch) {
+                        break;
+                    }
+                    word.push(ch);
+                    chars.next();
+                }
+                Token::Word(word)
+            }
+            c => return Err(invalid(format!("Unexpected '{}' in selection", c))),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    fn peek(&mut self) -> Option<&Token> {
+        self.tokens.peek()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.tokens.next()
+    }
+
+    fn or(&mut self) -> LvmResult<Expr> {
+        let mut lhs = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> LvmResult<Expr> {
+        let mut lhs = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            lhs = Expr::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> LvmResult<Expr> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::LParen) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err(invalid("Expected ) in selection".into())),
+                }
+            }
+            Some(Token::Word(field)) => {
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    _ => return Err(invalid(format!("Expected operator after {}", field))),
+                };
+                Ok(Expr::Compare(field, op, self.value()?))
+            }
+            t => Err(invalid(format!("Unexpected {:?} in selection", t))),
+        }
+    }
+
+    fn value(&mut self) -> LvmResult<Value> {
+        match self.next() {
+            Some(Token::Word(w)) => Ok(Value::Word(w)),
+            Some(Token::Quoted(q)) => Ok(Value::Quoted(q)),
+            Some(Token::LBrace) => {
+                let mut items = vec![];
+                loop {
+                    match self.next() {
+                        Some(Token::Word(w)) | Some(Token::Quoted(w)) => items.push(w),
+                        Some(Token::RBrace) if items.is_empty() => break,
+                        t => return Err(invalid(format!("Unexpected {:?} in tag set", t))),
+                    }
+                    match self.next() {
+                        Some(Token::Comma) => continue,
+                        Some(Token::RBrace) => break,
+                        t => return Err(invalid(format!("Unexpected {:?} in tag set", t))),
+                    }
+                }
+                Ok(Value::Set(items))
+            }
+            t => Err(invalid(format!("Expected a value, found {:?}", t))),
+        }
+    }
+}
+
+/// Parse a number with an optional size suffix into bytes
+fn parse_size(s: &str) -> Option<u64> {
+    s.parse::<Size>().ok().map(Size::bytes)
+}
+
+fn compare<T: PartialOrd>(lhs: &T, op: Op, rhs: &T) -> bool {
+    match op {
+        Op::Eq => lhs == rhs,
+        Op::Ne => lhs != rhs,
+        Op::Lt => lhs < rhs,
+        Op::Le => lhs <= rhs,
+        Op::Gt => lhs > rhs,
+        Op::Ge => lhs >= rhs,
+    }
+}
+
+impl Expr {
+    fn eval<T: Selectable + ?Sized>(&self, item: &T) -> LvmResult<bool> {
+        match self {
+            Expr::And(lhs, rhs) => Ok(lhs.eval(item)? && rhs.eval(item)?),
+            Expr::Or(lhs, rhs) => Ok(lhs.eval(item)? || rhs.eval(item)?),
+            Expr::Not(expr) => Ok(!expr.eval(item)?),
+            Expr::Compare(field, op, value) => {
+                let field_value = item
+                    .field(field)?
+                    .ok_or_else(|| invalid(format!("Unknown selection field: {}", field)))?;
+                match (field_value, value) {
+                    (FieldValue::Number(n), Value::Word(w)) => {
+                        let rhs = parse_size(w)
+                            .ok_or_else(|| invalid(format!("{} is not a number", w)))?;
+                        Ok(compare(&n, *op, &rhs))
+                    }
+                    (FieldValue::Text(t), Value::Word(w))
+                    | (FieldValue::Text(t), Value::Quoted(w))
+                        if *op == Op::Eq || *op == Op::Ne =>
+                    {
+                        Ok(compare(&t.as_str(), *op, &w.as_str()))
+                    }
+                    (FieldValue::List(list), Value::Set(set)) if *op == Op::Eq || *op == Op::Ne => {
+                        let contains_all = set.iter().all(|s| list.contains(s));
+                        Ok(contains_all == (*op == Op::Eq))
+                    }
+                    (FieldValue::List(list), Value::Word(w))
+                    | (FieldValue::List(list), Value::Quoted(w))
+                        if *op == Op::Eq || *op == Op::Ne =>
+                    {
+                        Ok(list.contains(w) == (*op == Op::Eq))
+                    }
+                    (_, value) => Err(invalid(format!(
+                        "Can't compare {} {:?} {:?}",
+                        field, op, value
+                    ))),
+                }
+            }
+        }
+    }
+}
+
+impl Selection {
+    /// Parse a selection expression
+    pub fn parse(s: &str) -> LvmResult<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?.into_iter().peekable(),
+        };
+        let expr = parser.or()?;
+        if let Some(t) = parser.peek() {
+            return Err(invalid(format!("Unexpected {:?} in selection", t)));
+        }
+        Ok(Selection { expr })
+    }
+
+    /// Check whether an object matches this selection
+    pub fn matches<T: Selectable + ?Sized>(&self, item: &T) -> LvmResult<bool> {
+        self.expr.eval(item)
+    }
+}
+
+impl<'a, 'b> Selectable for LogicalVolume<'a, 'b> {
+    fn field(&self, name: &str) -> LvmResult<Option<FieldValue>> {
+        let value = match name {
+            "lv_name" | "name" => FieldValue::Text(self.try_name()?),
+            "lv_uuid" | "uuid" => FieldValue::Text(self.try_uuid()?),
+            "lv_attr" | "attr" => FieldValue::Text(self.try_attributes()?),
+            "lv_size" | "size" => FieldValue::Number(self.try_size()?),
+            "origin" => FieldValue::Text(self.try_origin()?.unwrap_or_default()),
+            "lv_tags" | "tags" => FieldValue::List(self.get_tags()?),
+            "lv_active" | "active" => {
+                FieldValue::Text(if self.try_is_active()? { "active" } else { "" }.to_string())
+            }
+            "vg_name" => FieldValue::Text(self.vg.get_name()?),
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+}
+
+impl<'a> Selectable for VolumeGroup<'a> {
+    fn field(&self, name: &str) -> LvmResult<Option<FieldValue>> {
+        let value = match name {
+            "vg_name" | "name" => FieldValue::Text(self.get_name()?),
+            "vg_uuid" | "uuid" => FieldValue::Text(self.try_uuid()?),
+            "vg_size" | "size" => FieldValue::Number(self.get_size()),
+            "vg_free" | "free" => FieldValue::Number(self.get_free_size()),
+            "vg_extent_size" => FieldValue::Number(self.get_extent_size()),
+            "vg_extent_count" => FieldValue::Number(self.get_extent_count()),
+            "vg_free_count" => FieldValue::Number(self.get_free_extents()),
+            "vg_seqno" => FieldValue::Number(self.get_seq_number()),
+            "pv_count" => FieldValue::Number(self.get_pv_count()),
+            "max_lv" => FieldValue::Number(self.get_max_lv()),
+            "max_pv" => FieldValue::Number(self.get_max_pv()),
+            "vg_tags" | "tags" => FieldValue::List(self.get_tags()?),
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+}
+
+impl Lvm {
+    /// Open each VG read only in turn and keep the names of those f
+    /// accepts.  VGs that can't be opened, ie: because they were removed or
+    /// are locked, are skipped with a warning rather than ending the search.
+    fn filter_vgs<F>(&self, mut f: F) -> LvmResult<Vec<String>>
+    where
+        F: FnMut(&VolumeGroup<'_>) -> LvmResult<bool>,
+    {
+        let mut names = vec![];
+        for name in self.get_volume_group_names()? {
+            let vg = match self.vg_open(&name, &OpenMode::Read) {
+                Ok(vg) => vg,
+                Err(e) => {
+                    warn!("Skipping VG {}, opening it failed: {}", name, e);
+                    continue;
+                }
+            };
+            if f(&vg)? {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    /// Return the names of VGs matching selection.  Each VG is opened read
+    /// only and closed again while it's checked.
+    pub fn select_vgs(&self, selection: &Selection) -> LvmResult<Vec<String>> {
+        self.filter_vgs(|vg| selection.matches(vg))
+    }
+
+    /// Return the names of VGs carrying tag
+    pub fn find_vgs_by_tag(&self, tag: &str) -> LvmResult<Vec<String>> {
+        self.filter_vgs(|vg| Ok(vg.get_tags()?.iter().any(|t| t == tag)))
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Return the LVs of this VG matching selection
+    pub fn select_lvs(&self, selection: &Selection) -> LvmResult<Vec<LogicalVolume<'_, '_>>> {
+        let mut lvs = vec![];
+        for lv in self.list_lvs()? {
+            if selection.matches(&lv)? {
+                lvs.push(lv);
+            }
+        }
+        Ok(lvs)
+    }
+
+    /// Return the LVs of this VG carrying tag
+    pub fn find_lvs_by_tag(&self, tag: &str) -> LvmResult<Vec<LogicalVolume<'_, '_>>> {
+        let mut lvs = vec![];
+        for lv in self.list_lvs()? {
+            if lv.get_tags()?.iter().any(|t| t == tag) {
+                lvs.push(lv);
+            }
+        }
+        Ok(lvs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Item {
+        name: &'static str,
+        size: u64,
+        tags: &'static [&'static str],
+    }
+
+    impl Selectable for Item {
+        fn field(&self, name: &str) -> LvmResult<Option<FieldValue>> {
+            Ok(match name {
+                "name" => Some(FieldValue::Text(self.name.to_string())),
+                "size" => Some(FieldValue::Number(self.size)),
+                "tags" => Some(FieldValue::List(
+                    self.tags.iter().map(|t| t.to_string()).collect(),
+                )),
+                _ => None,
+            })
+        }
+    }
+
+    const ITEM: Item = Item {
+        name: "data",
+        size: 20 << 30,
+        tags: &["backup", "ssd"],
+    };
+
+    fn matches(selection: &str) -> bool {
+        Selection::parse(selection).unwrap().matches(&ITEM).unwrap()
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            tokenize("size>=10G&&!tags={a,'b c'}").unwrap(),
+            vec![
+                Token::Word("size".into()),
+                Token::Op(Op::Ge),
+                Token::Word("10G".into()),
+                Token::And,
+                Token::Not,
+                Token::Word("tags".into()),
+                Token::Op(Op::Eq),
+                Token::LBrace,
+                Token::Word("a".into()),
+                Token::Comma,
+                Token::Quoted("b c".into()),
+                Token::RBrace,
+            ]
+        );
+        assert_eq!(
+            tokenize("name != \"x\" || (a < 1)").unwrap(),
+            vec![
+                Token::Word("name".into()),
+                Token::Op(Op::Ne),
+                Token::Quoted("x".into()),
+                Token::Or,
+                Token::LParen,
+                Token::Word("a".into()),
+                Token::Op(Op::Lt),
+                Token::Word("1".into()),
+                Token::RParen,
+            ]
+        );
+        assert!(tokenize("a & b").is_err());
+        assert!(tokenize("name = \"open").is_err());
+        assert!(tokenize("name = #").is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        for selection in &[
+            "",
+            "name",
+            "name =",
+            "(name = a",
+            "name = a)",
+            "tags = {a b}",
+            "= a",
+        ] {
+            assert!(Selection::parse(selection).is_err(), "{}", selection);
+        }
+    }
+
+    #[test]
+    fn eval() {
+        assert!(matches("name = data"));
+        assert!(matches("name = \"data\""));
+        assert!(!matches("name != data"));
+        assert!(matches("size > 10G && size <= 20g"));
+        assert!(!matches("size < 10G"));
+        assert!(matches("tags = {backup}"));
+        assert!(matches("tags = {ssd,backup}"));
+        assert!(!matches("tags = {backup,retired}"));
+        assert!(matches("!tags = {retired}"));
+        assert!(matches("tags = ssd"));
+        // && binds tighter than ||
+        assert!(matches("name = other && size < 1 || name = data"));
+        assert!(!matches("name = other && (size < 1 || name = data)"));
+    }
+
+    #[test]
+    fn eval_errors() {
+        for selection in &["missing = 1", "size = ten", "name < data", "tags < {a}"] {
+            let selection = Selection::parse(selection).unwrap();
+            assert!(selection.matches(&ITEM).is_err());
+        }
+    }
+}
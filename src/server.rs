@@ -0,0 +1,546 @@
+//! Serve the crate's operations to other processes over a unix socket so
+//! a fleet agent can manage LVM on a node it isn't linked into.  The
+//! protocol is one JSON object per line in each direction:
+//!
+//! ```text
+//! {"id":1,"method":"lv_create","params":{"vg":"vg0","name":"data","size":1073741824}}
+//! {"id":1,"result":{"name":"data","path":"/dev/vg0/data","uuid":"...","size":1073741824}}
+//! ```
+//!
+//! Failures come back as `{"id":1,"error":{"code":<errno>,"message":"..."}}`.
+//! Requests may carry an `"auth"` string for the Authenticator to check
+//! alongside the peer's credentials, the connection is closed after the
+//! first request refused.  liblvm isn't thread safe so connections are
+//! served one at a time on the calling thread, and each is closed after
+//! five minutes so one client can't hold the server forever.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::mem;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use errno::Errno;
+
+use crate::json::{self, Value};
+use crate::{LogicalVolume, Lvm, LvmError, LvmResult, OpenMode, VolumeGroup};
+
+/// Longest request line accepted
+const MAX_REQUEST_SIZE: u64 = 1024 * 1024;
+/// How long a connected client may stay silent before it's dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a connection may stay open however busy the client keeps it
+const CONNECTION_DEADLINE: Duration = Duration::from_secs(300);
+
+/// Who is on the other end of a connection, from SO_PEERCRED
+#[derive(Clone, Copy, Debug)]
+pub struct PeerCredentials {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// A decoded request
+#[derive(Debug)]
+pub struct Request {
+    pub id: Value,
+    pub method: String,
+    pub params: Value,
+    /// Credential the client sent, ie: a token
+    pub auth: Option<String>,
+}
+
+/// Decides whether a request may run
+pub trait Authenticator {
+    fn authorize(&self, peer: &PeerCredentials, request: &Request) -> bool;
+}
+
+/// Only allows requests from root, the default
+#[derive(Debug)]
+pub struct RootOnly;
+
+impl Authenticator for RootOnly {
+    fn authorize(&self, peer: &PeerCredentials, _request: &Request) -> bool {
+        peer.uid == 0
+    }
+}
+
+impl<F> Authenticator for F
+where
+    F: Fn(&PeerCredentials, &Request) -> bool,
+{
+    fn authorize(&self, peer: &PeerCredentials, request: &Request) -> bool {
+        self(peer, request)
+    }
+}
+
+fn error_value(code: i32, message: &str) -> Value {
+    Value::object(vec![("code", code.into()), ("message", message.into())])
+}
+
+fn param_str<'p>(params: &'p Value, name: &str) -> LvmResult<&'p str> {
+    params.get(name).and_then(Value::as_str).ok_or_else(|| {
+        LvmError::new((
+            Errno(libc::EINVAL),
+            format!("Missing string parameter {}", name),
+        ))
+    })
+}
+
+fn param_u64(params: &Value, name: &str) -> LvmResult<u64> {
+    params.get(name).and_then(Value::as_u64).ok_or_else(|| {
+        LvmError::new((
+            Errno(libc::EINVAL),
+            format!("Missing integer parameter {}", name),
+        ))
+    })
+}
+
+fn lv_value(lv: &LogicalVolume<'_, '_>) -> LvmResult<Value> {
+    Ok(Value::object(vec![
+        ("name", lv.try_name()?.into()),
+        ("path", lv.path()?.to_string_lossy().into_owned().into()),
+        ("uuid", lv.try_uuid()?.into()),
+        ("size", lv.try_size()?.into()),
+        ("active", lv.try_is_active()?.into()),
+    ]))
+}
+
+fn vg_value(vg: &VolumeGroup<'_>) -> LvmResult<Value> {
+    Ok(Value::object(vec![
+        ("name", vg.get_name()?.into()),
+        ("uuid", vg.try_uuid()?.into()),
+        ("size", vg.get_size().into()),
+        ("free", vg.get_free_size().into()),
+        ("extent_size", vg.get_extent_size().into()),
+        ("pv_count", vg.get_pv_count().into()),
+    ]))
+}
+
+fn peer_credentials(stream: &UnixStream) -> LvmResult<PeerCredentials> {
+    let mut cred: libc::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let retcode = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if retcode < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(PeerCredentials {
+        pid: cred.pid,
+        uid: cred.uid,
+        gid: cred.gid,
+    })
+}
+
+/// Reads from a connection, failing with TimedOut once the client has been
+/// silent for CLIENT_TIMEOUT or the connection's deadline has passed, even
+/// in the middle of a line
+struct DeadlineReader {
+    stream: UnixStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::from_secs(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Connection deadline passed",
+            ));
+        }
+        self.stream
+            .set_read_timeout(Some(remaining.min(CLIENT_TIMEOUT)))?;
+        self.stream.read(buf)
+    }
+}
+
+/// Serves requests on a unix socket using an Lvm handle
+pub struct Server<'a> {
+    lvm: &'a Lvm,
+    listener: UnixListener,
+    authenticator: Box<dyn Authenticator + 'a>,
+}
+
+impl<'a> Server<'a> {
+    /// Listen on path, replacing a stale socket left by an earlier server.
+    /// Only root may make requests until an authenticator is set.
+    pub fn bind(lvm: &'a Lvm, path: &Path) -> LvmResult<Self> {
+        if let Ok(meta) = fs::symlink_metadata(path) {
+            if meta.file_type().is_socket() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(Server {
+            lvm,
+            listener: UnixListener::bind(path)?,
+            authenticator: Box::new(RootOnly),
+        })
+    }
+
+    /// Decide which requests may run with authenticator
+    pub fn with_authenticator<A: Authenticator + 'a>(mut self, authenticator: A) -> Self {
+        self.authenticator = Box::new(authenticator);
+        self
+    }
+
+    /// Accept and serve connections until accepting fails.  A connection
+    /// that misbehaves is dropped without stopping the server.
+    pub fn serve(&self) -> LvmResult<()> {
+        loop {
+            let (stream, _) = self.listener.accept()?;
+            if let Err(e) = self.handle_connection(stream) {
+                warn!("lvm server connection failed: {}", e);
+            }
+        }
+    }
+
+    /// Serve the requests of a single connection until it closes, a
+    /// request is refused or CONNECTION_DEADLINE passes
+    pub fn handle_connection(&self, stream: UnixStream) -> LvmResult<()> {
+        let peer = peer_credentials(&stream)?;
+        // A client that stops reading mustn't block the server either
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(DeadlineReader {
+            stream,
+            deadline: Instant::now() + CONNECTION_DEADLINE,
+        });
+        loop {
+            let mut line = String::new();
+            let read = (&mut reader).take(MAX_REQUEST_SIZE).read_line(&mut line)?;
+            if read == 0 {
+                return Ok(());
+            }
+            if !line.ends_with('\n') && read as u64 == MAX_REQUEST_SIZE {
+                let response = Value::object(vec![
+                    ("id", Value::Null),
+                    ("error", error_value(libc::E2BIG, "Request too large")),
+                ]);
+                writeln!(writer, "{}", response)?;
+                return Ok(());
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (response, authorized) = self.respond(&peer, &line);
+            writeln!(writer, "{}", response)?;
+            if !authorized {
+                return Ok(());
+            }
+        }
+    }
+
+    /// The response to a request line and whether the request was allowed
+    fn respond(&self, peer: &PeerCredentials, line: &str) -> (Value, bool) {
+        let request = match json::parse(line) {
+            Ok(request) => request,
+            Err(e) => {
+                let response = Value::object(vec![
+                    ("id", Value::Null),
+                    ("error", error_value(e.errno(), &e.to_string())),
+                ]);
+                return (response, true);
+            }
+        };
+        let request = Request {
+            id: request.get("id").cloned().unwrap_or(Value::Null),
+            method: request
+                .get("method")
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .to_string(),
+            params: request.get("params").cloned().unwrap_or(Value::Null),
+            auth: request
+                .get("auth")
+                .and_then(Value::as_str)
+                .map(|s| s.to_string()),
+        };
+        let authorized = self.authenticator.authorize(peer, &request);
+        let result = if authorized {
+            debug!("lvm server: {} from uid {}", request.method, peer.uid);
+            self.dispatch(&request.method, &request.params)
+        } else {
+            warn!(
+                "lvm server: refused {} from uid {} pid {}",
+                request.method, peer.uid, peer.pid
+            );
+            Err(LvmError::new((
+                Errno(libc::EACCES),
+                format!("Not allowed to call {}", request.method),
+            )))
+        };
+        let response = match result {
+            Ok(result) => Value::object(vec![("id", request.id), ("result", result)]),
+            Err(e) => Value::object(vec![
+                ("id", request.id),
+                ("error", error_value(e.errno(), &e.to_string())),
+            ]),
+        };
+        (response, authorized)
+    }
+
+    fn dispatch(&self, method: &str, params: &Value) -> LvmResult<Value> {
+        let lvm = self.lvm;
+        match method {
+            "scan" => {
+                lvm.scan()?;
+                Ok(Value::Null)
+            }
+            "pv_create" => {
+                lvm.pv_create(param_str(params, "device")?, 0)?;
+                Ok(Value::Null)
+            }
+            "vg_names" => Ok(lvm.get_volume_group_names()?.into()),
+            "vg_info" => {
+                let vg = lvm.vg_open(param_str(params, "vg")?, &OpenMode::Read)?;
+                vg_value(&vg)
+            }
+            "lv_list" => {
+                let vg = lvm.vg_open(param_str(params, "vg")?, &OpenMode::Read)?;
+                let lvs = vg
+                    .list_lvs()?
+                    .iter()
+                    .map(lv_value)
+                    .collect::<LvmResult<Vec<Value>>>()?;
+                Ok(Value::Array(lvs))
+            }
+            "lv_create" => {
+                let mut vg = lvm.vg_open(param_str(params, "vg")?, &OpenMode::Write)?;
+                let lv =
+                    vg.create_lv_linear(param_str(params, "name")?, param_u64(params, "size")?)?;
+                lv_value(&lv)
+            }
+            "lv_snapshot" => {
+                let vg = lvm.vg_open(param_str(params, "vg")?, &OpenMode::Write)?;
+                let mut origin = vg.lv_from_name(param_str(params, "origin")?)?;
+                let size = params.get("size").and_then(Value::as_u64).unwrap_or(0);
+                let snap = origin.snapshot(param_str(params, "name")?, size)?;
+                lv_value(&snap)
+            }
+            "lv_resize" => {
+                let vg = lvm.vg_open(param_str(params, "vg")?, &OpenMode::Write)?;
+                let mut lv = vg.lv_from_name(param_str(params, "name")?)?;
+                lv.resize(param_u64(params, "size")?)?;
+                lv_value(&lv)
+            }
+            "lv_activate" | "lv_deactivate" => {
+                let vg = lvm.vg_open(param_str(params, "vg")?, &OpenMode::Write)?;
+                let mut lv = vg.lv_from_name(param_str(params, "name")?)?;
+                if method == "lv_activate" {
+                    lv.activate()?;
+                } else {
+                    lv.deactivate()?;
+                }
+                lv_value(&lv)
+            }
+            "lv_remove" => {
+                let vg = lvm.vg_open(param_str(params, "vg")?, &OpenMode::Write)?;
+                vg.lv_from_name(param_str(params, "name")?)?.remove()?;
+                Ok(Value::Null)
+            }
+            _ => Err(LvmError::new((
+                Errno(libc::ENOSYS),
+                format!("Unknown method {}", method),
+            ))),
+        }
+    }
+}
+
+/// Talks to a Server
+#[derive(Debug)]
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    auth: Option<String>,
+    next_id: u64,
+}
+
+impl Client {
+    pub fn connect(path: &Path) -> LvmResult<Self> {
+        let stream = UnixStream::connect(path)?;
+        Ok(Client {
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+            auth: None,
+            next_id: 1,
+        })
+    }
+
+    /// Send auth with every request
+    pub fn with_auth(mut self, auth: &str) -> Self {
+        self.auth = Some(auth.to_string());
+        self
+    }
+
+    /// Call method with params, an object or null, and return its result.
+    /// Errors from the server come back as LvmError::Error with the
+    /// server's errno.
+    pub fn call(&mut self, method: &str, params: Value) -> LvmResult<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut request = vec![
+            ("id", id.into()),
+            ("method", method.into()),
+            ("params", params),
+        ];
+        if let Some(auth) = &self.auth {
+            request.push(("auth", auth.as_str().into()));
+        }
+        writeln!(self.writer, "{}", Value::object(request))?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(LvmError::new((
+                Errno(libc::ECONNRESET),
+                "Server closed the connection".to_string(),
+            )));
+        }
+        let response = json::parse(&line)?;
+        if let Some(error) = response.get("error") {
+            let code = error
+                .get("code")
+                .and_then(Value::as_i64)
+                .unwrap_or(i64::from(libc::EIO));
+            let message = error.get("message").and_then(Value::as_str).unwrap_or("");
+            return Err(LvmError::new((Errno(code as i32), message.to_string())));
+        }
+        Ok(response.get("result").cloned().unwrap_or(Value::Null))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::Shutdown;
+    use std::path::PathBuf;
+    use std::ptr;
+    use std::thread;
+
+    const PEER: PeerCredentials = PeerCredentials {
+        pid: 1,
+        uid: 1000,
+        gid: 1000,
+    };
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("lvm-server-{}-{}.sock", name, std::process::id()))
+    }
+
+    /// Serve stream on this thread while client runs on another, and
+    /// return what client returns
+    fn exchange<A, F>(authenticator: A, name: &str, client: F) -> String
+    where
+        A: Authenticator,
+        F: FnOnce(&mut UnixStream) + Send + 'static,
+    {
+        // Nothing here reaches liblvm, so the Lvm needs no handle
+        let lvm = Lvm::from_handle(ptr::null_mut());
+        let path = socket_path(name);
+        let server = Server::bind(&lvm, &path)
+            .unwrap()
+            .with_authenticator(authenticator);
+        let (ours, mut theirs) = UnixStream::pair().unwrap();
+        let client = thread::spawn(move || {
+            client(&mut theirs);
+            let mut responses = String::new();
+            theirs.read_to_string(&mut responses).unwrap();
+            responses
+        });
+        server.handle_connection(ours).unwrap();
+        fs::remove_file(&path).unwrap();
+        client.join().unwrap()
+    }
+
+    fn error_code(response: &Value) -> Option<i64> {
+        response.get("error")?.get("code")?.as_i64()
+    }
+
+    #[test]
+    fn respond() {
+        let lvm = Lvm::from_handle(ptr::null_mut());
+        let path = socket_path("respond");
+        let server = Server::bind(&lvm, &path)
+            .unwrap()
+            .with_authenticator(|_: &PeerCredentials, _: &Request| true);
+        fs::remove_file(&path).unwrap();
+
+        let (response, authorized) = server.respond(&PEER, r#"{"id":7,"method":"nope"}"#);
+        assert!(authorized);
+        assert_eq!(response.get("id"), Some(&Value::from(7u64)));
+        assert_eq!(error_code(&response), Some(i64::from(libc::ENOSYS)));
+
+        let (response, authorized) = server.respond(&PEER, "{not json");
+        assert!(authorized);
+        assert_eq!(response.get("id"), Some(&Value::Null));
+        assert_eq!(error_code(&response), Some(i64::from(libc::EINVAL)));
+
+        let (response, _) = server.respond(&PEER, r#"{"id":1,"method":"lv_remove"}"#);
+        assert_eq!(error_code(&response), Some(i64::from(libc::EINVAL)));
+    }
+
+    #[test]
+    fn refused_request_closes_the_connection() {
+        let responses = exchange(
+            |peer: &PeerCredentials, request: &Request| {
+                peer.uid == 0 && request.auth.as_deref() == Some("secret")
+            },
+            "refused",
+            |client| {
+                writeln!(client, r#"{{"id":1,"method":"scan"}}"#).unwrap();
+                writeln!(client, r#"{{"id":2,"method":"scan","auth":"secret"}}"#).unwrap();
+            },
+        );
+        let lines: Vec<&str> = responses.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let response = json::parse(lines[0]).unwrap();
+        assert_eq!(response.get("id"), Some(&Value::from(1u64)));
+        assert_eq!(error_code(&response), Some(i64::from(libc::EACCES)));
+    }
+
+    #[test]
+    fn serves_until_the_client_hangs_up() {
+        let responses = exchange(
+            |_: &PeerCredentials, _: &Request| true,
+            "hangup",
+            |client| {
+                writeln!(client, r#"{{"id":1,"method":"nope"}}"#).unwrap();
+                writeln!(client).unwrap();
+                writeln!(client, r#"{{"id":2,"method":"nope"}}"#).unwrap();
+                client.shutdown(Shutdown::Write).unwrap();
+            },
+        );
+        let ids: Vec<Option<u64>> = responses
+            .lines()
+            .map(|line| json::parse(line).unwrap().get("id").and_then(Value::as_u64))
+            .collect();
+        assert_eq!(ids, vec![Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn oversize_request() {
+        let responses = exchange(
+            |_: &PeerCredentials, _: &Request| true,
+            "oversize",
+            |client| {
+                let line = vec![b'x'; MAX_REQUEST_SIZE as usize];
+                client.write_all(&line).unwrap();
+            },
+        );
+        let lines: Vec<&str> = responses.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let response = json::parse(lines[0]).unwrap();
+        assert_eq!(error_code(&response), Some(i64::from(libc::E2BIG)));
+    }
+}
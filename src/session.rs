@@ -0,0 +1,82 @@
+//! A long lived view of the system for daemons serving many requests.
+//! Opening a VG rereads its metadata from every PV, so a Session keeps VG
+//! handles open between requests and only reopens them once the metadata
+//! on disk has moved on.
+
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use crate::{Lvm, LvmResult, OpenMode, VolumeGroup};
+
+/// Cache of open VG handles over one Lvm handle.  Write handles hold the
+/// VG lock for as long as they're cached, blocking the lvm tools and
+/// other processes, so release them when a burst of changes is done.
+pub struct Session<'a> {
+    lvm: &'a Lvm,
+    vgs: RefCell<HashMap<String, VolumeGroup<'a>>>,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(lvm: &'a Lvm) -> Self {
+        Session {
+            lvm,
+            vgs: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The Lvm handle the session uses
+    pub fn lvm(&self) -> &'a Lvm {
+        self.lvm
+    }
+
+    /// Run f with the VG called name open in mode.  A cached handle is
+    /// reused if it's in the same mode, after being refreshed if the VG
+    /// was changed by someone else.  f must not call back into the session.
+    pub fn with_vg<T, F>(&self, name: &str, mode: OpenMode, f: F) -> LvmResult<T>
+    where
+        F: FnOnce(&mut VolumeGroup<'a>) -> LvmResult<T>,
+    {
+        let mut vgs = self.vgs.borrow_mut();
+        let cached = match vgs.get_mut(name) {
+            Some(vg) if vg.mode == mode => vg.refresh().is_ok(),
+            _ => false,
+        };
+        if !cached {
+            // Drop any handle in the other mode first so its lock is
+            // released before the VG is opened again
+            vgs.remove(name);
+        }
+        let vg = match vgs.entry(name.to_string()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(self.lvm.vg_open(name, &mode)?),
+        };
+        f(vg)
+    }
+
+    /// Close the cached handle of a VG, if there is one
+    pub fn release(&self, name: &str) {
+        self.vgs.borrow_mut().remove(name);
+    }
+
+    /// Close every cached VG handle
+    pub fn release_all(&self) {
+        self.vgs.borrow_mut().clear();
+    }
+
+    /// Names of the VGs with a cached handle
+    pub fn open_vgs(&self) -> Vec<String> {
+        self.vgs.borrow().keys().cloned().collect()
+    }
+
+    /// Check the session still works: that liblvm answers and that every
+    /// cached VG still exists.  Handles of VGs that are gone or can't be
+    /// refreshed are released, stale ones are refreshed.
+    pub fn ping(&self) -> LvmResult<()> {
+        let names = self.lvm.get_volume_group_names()?;
+        self.vgs
+            .borrow_mut()
+            .retain(|name, vg| names.contains(name) && vg.refresh().is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//! Sizes in bytes, parsed and printed with the lvm tools' binary units, ie:
+//! 4m or 1.5g.
+
+use std::fmt;
+use std::str::FromStr;
+
+use errno::Errno;
+
+use crate::LvmError;
+
+/// A size in bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Size(u64);
+
+/// Unit suffixes and the power of two they stand for
+const UNITS: [(char, u32); 6] = [
+    ('e', 60),
+    ('p', 50),
+    ('t', 40),
+    ('g', 30),
+    ('m', 20),
+    ('k', 10),
+];
+
+impl Size {
+    pub const fn from_bytes(bytes: u64) -> Size {
+        Size(bytes)
+    }
+
+    pub const fn from_kib(kib: u64) -> Size {
+        Size(kib.saturating_mul(1 << 10))
+    }
+
+    pub const fn from_mib(mib: u64) -> Size {
+        Size(mib.saturating_mul(1 << 20))
+    }
+
+    pub const fn from_gib(gib: u64) -> Size {
+        Size(gib.saturating_mul(1 << 30))
+    }
+
+    pub const fn from_tib(tib: u64) -> Size {
+        Size(tib.saturating_mul(1 << 40))
+    }
+
+    pub const fn bytes(self) -> u64 {
+        self.0
+    }
+
+    /// The size as an lvm tools argument, ie: 4194304b
+    pub(crate) fn as_arg(self) -> String {
+        format!("{}b", self.0)
+    }
+}
+
+impl From<u64> for Size {
+    fn from(bytes: u64) -> Size {
+        Size(bytes)
+    }
+}
+
+impl From<Size> for u64 {
+    fn from(size: Size) -> u64 {
+        size.0
+    }
+}
+
+impl fmt::Display for Size {
+    /// The largest unit the size is a whole number of, ie: 4m or 4100k
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (suffix, shift) in UNITS.iter() {
+            if self.0 != 0 && self.0.trailing_zeros() >= *shift {
+                return write!(f, "{}{}", self.0 >> shift, suffix);
+            }
+        }
+        write!(f, "{}b", self.0)
+    }
+}
+
+impl FromStr for Size {
+    type Err = LvmError;
+
+    /// Parse a number with an optional suffix, b for bytes, s for 512 byte
+    /// sectors or one of k, m, g, t, p and e for binary units.  Fractions
+    /// are rounded down to whole bytes.  Sizes past u64::MAX are refused.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || LvmError::new((Errno(libc::EINVAL), format!("Invalid size: {}", s)));
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let unit = unit.to_lowercase();
+        let multiplier = match unit.as_str() {
+            "" | "b" => 1,
+            "s" => 512,
+            _ => UNITS
+                .iter()
+                .find(|(suffix, _)| unit.len() == 1 && unit.starts_with(*suffix))
+                .map(|(_, shift)| 1 << shift)
+                .ok_or_else(invalid)?,
+        };
+        if number.contains('.') {
+            let n: f64 = number.parse().map_err(|_| invalid())?;
+            let bytes = n * multiplier as f64;
+            // u64::MAX as f64 rounds up to 2^64, the first value too large
+            if bytes >= u64::MAX as f64 {
+                return Err(invalid());
+            }
+            Ok(Size(bytes as u64))
+        } else {
+            let n: u64 = number.parse().map_err(|_| invalid())?;
+            n.checked_mul(multiplier).map(Size).ok_or_else(invalid)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Option<u64> {
+        s.parse::<Size>().ok().map(Size::bytes)
+    }
+
+    #[test]
+    fn parses_units() {
+        assert_eq!(parse("0"), Some(0));
+        assert_eq!(parse("0b"), Some(0));
+        assert_eq!(parse("4096"), Some(4096));
+        assert_eq!(parse("4096b"), Some(4096));
+        assert_eq!(parse("8s"), Some(4096));
+        assert_eq!(parse("4k"), Some(4 << 10));
+        assert_eq!(parse("4M"), Some(4 << 20));
+        assert_eq!(parse("2g"), Some(2 << 30));
+        assert_eq!(parse("1t"), Some(1 << 40));
+        assert_eq!(parse("1p"), Some(1 << 50));
+        assert_eq!(parse("15e"), Some(15 << 60));
+    }
+
+    #[test]
+    fn parses_fractions() {
+        assert_eq!(parse("1.5g"), Some(3 << 29));
+        assert_eq!(parse(".5k"), Some(512));
+        assert_eq!(parse("2.k"), Some(2048));
+        assert_eq!(parse("1.5s"), Some(768));
+        // Rounded down to whole bytes
+        assert_eq!(parse("0.3k"), Some(307));
+        assert_eq!(parse("1.5"), Some(1));
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        for s in &["", "k", "-1", "1x", "1kb", "1.2.3m", "4 m", "1.5gg"] {
+            assert_eq!(parse(s), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn rejects_overflow() {
+        assert_eq!(parse("18446744073709551615"), Some(u64::MAX));
+        assert_eq!(parse("18446744073709551616"), None);
+        assert_eq!(parse("16e"), None);
+        assert_eq!(parse("36028797018963968s"), None);
+        assert_eq!(parse("16.0e"), None);
+        assert_eq!(parse("15.5e"), Some(31 << 59));
+    }
+
+    #[test]
+    fn displays_the_largest_whole_unit() {
+        assert_eq!(Size::from_bytes(0).to_string(), "0b");
+        assert_eq!(Size::from_bytes(512).to_string(), "512b");
+        assert_eq!(Size::from_bytes(4096).to_string(), "4k");
+        assert_eq!(Size::from_kib(4100).to_string(), "4100k");
+        assert_eq!(Size::from_mib(1536).to_string(), "1536m");
+        assert_eq!(Size::from_gib(1024).to_string(), "1t");
+        assert_eq!(Size::from_bytes(1 << 60).to_string(), "1e");
+        assert_eq!(
+            Size::from_bytes(u64::MAX).to_string(),
+            "18446744073709551615b"
+        );
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for bytes in &[0, 1, 512, 4096, 3 << 29, 5 << 40, u64::MAX] {
+            let size = Size::from_bytes(*bytes);
+            assert_eq!(size.to_string().parse::<Size>().ok(), Some(size));
+        }
+    }
+}
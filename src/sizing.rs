@@ -0,0 +1,116 @@
+//! Predict what a creation call will allocate.  lvm rounds sizes up to
+//! whole extents and layouts other than linear take extra space for
+//! parity, mirror copies or metadata, so the largest LV a VG can hold
+//! depends on what kind of LV it is.
+
+use std::cmp::Reverse;
+
+use crate::{LvmResult, VolumeGroup};
+
+/// Thin pool chunk size lvm uses when none is given
+const DEFAULT_THIN_CHUNK_SIZE: u64 = 64 * 1024;
+/// Smallest and largest thin pool metadata LV lvm creates
+const MIN_THIN_METADATA_SIZE: u64 = 2 * 1024 * 1024;
+const MAX_THIN_METADATA_SIZE: u64 = 16 * 1024 * 1024 * 1024;
+
+/// Layout of an LV being planned
+#[derive(Debug)]
+pub enum LvType {
+    Linear,
+    /// Data striped across stripes PVs
+    Striped {
+        stripes: u32,
+    },
+    /// raid1 with mirrors additional copies
+    Raid1 {
+        mirrors: u32,
+    },
+    /// stripes data stripes plus one parity stripe
+    Raid5 {
+        stripes: u32,
+    },
+    /// stripes data stripes plus two parity stripes
+    Raid6 {
+        stripes: u32,
+    },
+    /// stripes data stripes, each mirrored once
+    Raid10 {
+        stripes: u32,
+    },
+    /// Thin pool with the default chunk size, its metadata LV and the
+    /// spare metadata LV lvm keeps for repairs
+    ThinPool,
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Round bytes up to a whole number of extents, the size lvm would
+    /// actually allocate for an LV of that size
+    pub fn round_size_to_extent(&self, bytes: u64) -> u64 {
+        let extent_size = self.get_extent_size();
+        if extent_size == 0 {
+            return bytes;
+        }
+        let remainder = bytes % extent_size;
+        if remainder == 0 {
+            return bytes;
+        }
+        // Sizes too close to u64::MAX round down to the last whole extent
+        bytes
+            .checked_add(extent_size - remainder)
+            .unwrap_or(bytes - remainder)
+    }
+
+    /// Largest LV of for_type, in bytes, that could be created with the
+    /// VG's current free space.  Zero if there aren't enough PVs with free
+    /// space for the layout.
+    pub fn max_lv_size(&self, for_type: &LvType) -> LvmResult<u64> {
+        let extent_size = self.get_extent_size();
+        if extent_size == 0 {
+            return Ok(0);
+        }
+        let mut free: Vec<u64> = self
+            .list_pvs()?
+            .iter()
+            .map(|pv| pv.get_free() / extent_size)
+            .filter(|extents| *extents > 0)
+            .collect();
+        free.sort_by_key(|extents| Reverse(*extents));
+        let total: u64 = free.iter().sum();
+
+        // Each image of a striped or raid LV needs its own PV, so the
+        // smallest of the largest n PVs limits the size of every image
+        let per_image = |images: u32| -> u64 {
+            match free.get(images as usize - 1) {
+                Some(extents) => *extents,
+                None => 0,
+            }
+        };
+        // raid images also carry a one extent metadata sub-LV
+        let raid_image = |images: u32| per_image(images).saturating_sub(1);
+
+        let extents = match for_type {
+            LvType::Linear => total,
+            LvType::Striped { stripes } => u64::from(*stripes) * per_image((*stripes).max(1)),
+            LvType::Raid1 { mirrors } => raid_image(mirrors + 1),
+            LvType::Raid5 { stripes } => u64::from(*stripes) * raid_image(stripes + 1),
+            LvType::Raid6 { stripes } => u64::from(*stripes) * raid_image(stripes + 2),
+            LvType::Raid10 { stripes } => u64::from(*stripes) * raid_image((stripes * 2).max(2)),
+            LvType::ThinPool => {
+                let metadata = |data: u64| {
+                    let bytes = (data * extent_size / DEFAULT_THIN_CHUNK_SIZE * 64)
+                        .clamp(MIN_THIN_METADATA_SIZE, MAX_THIN_METADATA_SIZE);
+                    let extents = bytes / extent_size;
+                    if extents * extent_size < bytes {
+                        extents + 1
+                    } else {
+                        extents
+                    }
+                };
+                // Metadata for the whole VG is an upper bound on what the
+                // pool will need, leaving room for it and the spare
+                total.saturating_sub(2 * metadata(total))
+            }
+        };
+        Ok(extents * extent_size)
+    }
+}
@@ -0,0 +1,289 @@
+//! Consistently named snapshots and retention based cleanup for backup
+//! tools.  Snapshots are named <prefix>-YYYYMMDD-HHMMSS in UTC and the
+//! timestamp in the name, not the LV's creation time, decides what gets
+//! pruned.
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs;
+use std::ops::Range;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use errno::Errno;
+
+use crate::{
+    blockdev, civil_from_days, days_from_civil, CreatedLv, LogicalVolume, Lvm, LvmError, LvmResult,
+    VolumeGroup,
+};
+
+/// Which snapshots to keep.  A snapshot survives pruning if any rule keeps
+/// it.
+#[derive(Clone, Debug, Default)]
+pub struct RetentionPolicy {
+    /// Keep the newest keep_last snapshots
+    pub keep_last: usize,
+    /// Keep the newest snapshot of each of the last keep_daily days that
+    /// have one
+    pub keep_daily: usize,
+    /// Keep the newest snapshot of each of the last keep_weekly weeks,
+    /// starting on Monday, that have one
+    pub keep_weekly: usize,
+}
+
+/// Format secs since the epoch as YYYYMMDD-HHMMSS
+fn format_timestamp(secs: u64) -> String {
+    let secs = secs as i64;
+    let (year, month, day) = civil_from_days(secs / 86_400);
+    let rem = secs % 86_400;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Parse YYYYMMDD-HHMMSS back into secs since the epoch
+fn parse_timestamp(s: &str) -> Option<i64> {
+    if s.len() != 15 || s.as_bytes()[8] != b'-' {
+        return None;
+    }
+    let field = |range: Range<usize>| -> Option<i64> {
+        let part = s.get(range)?;
+        if !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        part.parse().ok()
+    };
+    let days = days_from_civil(field(0..4)?, field(4..6)?, field(6..8)?);
+    Some(days * 86_400 + field(9..11)? * 3600 + field(11..13)? * 60 + field(13..15)?)
+}
+
+/// Pick which of snapshots, (name, timestamp) sorted newest first, to keep
+fn retained(snapshots: &[(String, i64)], policy: &RetentionPolicy) -> HashSet<String> {
+    let mut keep: HashSet<String> = snapshots
+        .iter()
+        .take(policy.keep_last)
+        .map(|(name, _)| name.clone())
+        .collect();
+    let mut keep_newest_per = |count: usize, period: &dyn Fn(i64) -> i64| {
+        let mut seen = HashSet::new();
+        for (name, secs) in snapshots {
+            if seen.len() >= count {
+                break;
+            }
+            if seen.insert(period(*secs)) {
+                keep.insert(name.clone());
+            }
+        }
+    };
+    keep_newest_per(policy.keep_daily, &|secs| secs.div_euclid(86_400));
+    // The epoch was a Thursday, shift so weeks start on Monday
+    keep_newest_per(policy.keep_weekly, &|secs| {
+        (secs.div_euclid(86_400) + 3).div_euclid(7)
+    });
+    keep
+}
+
+/// Directories liblvm writes to while making a snapshot: the system dir,
+/// holding the metadata archive and backup, and the locking dir
+fn lvm_write_dirs(lvm: &Lvm) -> Vec<PathBuf> {
+    vec![lvm.system_dir(), lvm.locking_dir()]
+}
+
+/// Check if path, or its closest existing ancestor if it doesn't exist
+/// yet, is on the filesystem mounted at mount_point
+fn on_filesystem(path: &Path, mount_point: &Path) -> bool {
+    let existing = match path.ancestors().find(|dir| dir.exists()) {
+        Some(existing) => existing,
+        None => return false,
+    };
+    match (fs::metadata(existing), fs::metadata(mount_point)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false,
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Snapshot this LV as <prefix>-YYYYMMDD-HHMMSS using the current UTC
+    /// time.  max_snap_size is passed on to snapshot.
+    pub fn create_timestamped_snapshot(
+        &mut self,
+        prefix: &str,
+        max_snap_size: u64,
+    ) -> LvmResult<CreatedLv<'_, '_>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| LvmError::new((Errno(libc::EINVAL), e.to_string())))?;
+        let name = format!("{}-{}", prefix, format_timestamp(now.as_secs()));
+        self.snapshot(&name, max_snap_size)
+    }
+
+    /// Snapshot this LV with its filesystem frozen, if it's mounted, so the
+    /// snapshot holds everything written before the call, as after a clean
+    /// crash.  Writes to the filesystem block until the snapshot is made.
+    /// max_snap_size is passed on to snapshot.
+    ///
+    /// liblvm writes the metadata archive and backup under the system dir
+    /// and takes lock files in the locking dir while it snapshots, which
+    /// would block forever on the frozen filesystem, so LVs holding either
+    /// are refused with EBUSY.  Snapshot those with snapshot instead, the
+    /// device-mapper suspend it does flushes the mounted filesystem too.
+    pub fn snapshot_consistent(
+        &mut self,
+        snap_name: &str,
+        max_snap_size: u64,
+    ) -> LvmResult<CreatedLv<'_, '_>> {
+        let frozen = match self.mounted_at()? {
+            Some(mount_point) if self.lvm.is_test_mode() => {
+                info!("test mode, not freezing {}", mount_point.display());
+                None
+            }
+            Some(mount_point) => {
+                if let Some(dir) = lvm_write_dirs(self.lvm)
+                    .into_iter()
+                    .find(|dir| on_filesystem(dir, &mount_point))
+                {
+                    return Err(LvmError::new((
+                        Errno(libc::EBUSY),
+                        format!(
+                            "Can't freeze {}, lvm writes to {} on it while snapshotting",
+                            mount_point.display(),
+                            dir.display()
+                        ),
+                    )));
+                }
+                Some(blockdev::freeze(&mount_point)?)
+            }
+            None => None,
+        };
+        let snapshot = self.snapshot(snap_name, max_snap_size);
+        drop(frozen);
+        snapshot
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// List the snapshots created with create_timestamped_snapshot using
+    /// prefix along with their timestamps, newest first
+    pub fn timestamped_snapshots(&self, prefix: &str) -> LvmResult<Vec<(String, SystemTime)>> {
+        Ok(self
+            .prefixed_snapshots(prefix)?
+            .into_iter()
+            .map(|(name, secs)| (name, UNIX_EPOCH + Duration::from_secs(secs as u64)))
+            .collect())
+    }
+
+    fn prefixed_snapshots(&self, prefix: &str) -> LvmResult<Vec<(String, i64)>> {
+        let start = format!("{}-", prefix);
+        let mut snapshots: Vec<(String, i64)> = self
+            .list_lvs()?
+            .iter()
+            .filter(|lv| lv.get_origin().is_some())
+            .filter_map(|lv| {
+                let name = lv.try_name().ok()?;
+                let secs = parse_timestamp(name.strip_prefix(&start)?)?;
+                if secs < 0 {
+                    return None;
+                }
+                Some((name, secs))
+            })
+            .collect();
+        snapshots.sort_by_key(|snapshot| Reverse(snapshot.1));
+        Ok(snapshots)
+    }
+
+    /// Remove the snapshots named with prefix that policy doesn't keep and
+    /// return their names
+    pub fn prune_snapshots(
+        &mut self,
+        prefix: &str,
+        policy: &RetentionPolicy,
+    ) -> LvmResult<Vec<String>> {
+        let snapshots = self.prefixed_snapshots(prefix)?;
+        let keep = retained(&snapshots, policy);
+        let mut removed = vec![];
+        for (name, _) in snapshots {
+            if keep.contains(&name) {
+                continue;
+            }
+            self.lv_from_name(&name)?.remove()?;
+            removed.push(name);
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(1_709_210_096), "20240229-123456");
+        assert_eq!(parse_timestamp("20240229-123456"), Some(1_709_210_096));
+        assert_eq!(parse_timestamp("19700101-000000"), Some(0));
+        for s in &[
+            "20240229123456",
+            "20240229-12345",
+            "2024022a-123456",
+            "20240229-1234+6",
+            "",
+        ] {
+            assert_eq!(parse_timestamp(s), None, "{}", s);
+        }
+    }
+
+    fn snapshots() -> Vec<(String, i64)> {
+        // Newest first, 20240304 is a Monday
+        [
+            "20240304-100000",
+            "20240304-080000",
+            "20240303-230000",
+            "20240303-120000",
+            "20240229-123456",
+            "20240220-000000",
+        ]
+        .iter()
+        .map(|s| (s.to_string(), parse_timestamp(s).unwrap()))
+        .collect()
+    }
+
+    fn kept(policy: RetentionPolicy) -> Vec<String> {
+        let mut kept: Vec<String> = retained(&snapshots(), &policy).into_iter().collect();
+        kept.sort();
+        kept
+    }
+
+    #[test]
+    fn retention() {
+        assert!(kept(RetentionPolicy::default()).is_empty());
+        let policy = RetentionPolicy {
+            keep_last: 2,
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(kept(policy), vec!["20240304-080000", "20240304-100000"]);
+        let policy = RetentionPolicy {
+            keep_daily: 3,
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(
+            kept(policy),
+            vec!["20240229-123456", "20240303-230000", "20240304-100000"]
+        );
+        // Sunday the 3rd is in the week starting Monday February 26th
+        let policy = RetentionPolicy {
+            keep_weekly: 3,
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(
+            kept(policy),
+            vec!["20240220-000000", "20240303-230000", "20240304-100000"]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! Hot spares for raid LVs.  lvm has no spare PVs of its own, a spare here
+//! is a PV of the VG carrying SPARE_TAG that raid repairs are pointed at.
+//! Nothing keeps normal allocation off a spare, create LVs on named PVs to
+//! keep spares free.
+
+use errno::Errno;
+
+use crate::{cli, LvmError, LvmResult, VolumeGroup};
+
+/// Tag marking a PV as a spare
+pub const SPARE_TAG: &str = "raid_spare";
+
+/// A raid LV that lost an image or needs attention
+#[derive(Debug, Clone, PartialEq)]
+pub struct DegradedRaid {
+    pub name: String,
+    /// The segment type, ie: raid1 or raid5
+    pub segtype: String,
+    /// lvm's lv_health_status, ie: partial or refresh needed
+    pub health: String,
+}
+
+/// What spare_rows reads about a spare PV
+struct PvRow {
+    name: String,
+    used: u64,
+    missing: bool,
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// PVs of this VG tagged with SPARE_TAG
+    fn spare_rows(&self) -> LvmResult<Vec<PvRow>> {
+        let vg_name = self.get_name()?;
+        let rows = cli::report(
+            self.lvm,
+            "pvs",
+            &["pv_name", "vg_name", "pv_tags", "pv_used", "pv_attr"],
+            "",
+        )?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| match row.as_slice() {
+                [name, vg, tags, used, attr]
+                    if *vg == vg_name && tags.split(',').any(|t| t == SPARE_TAG) =>
+                {
+                    Some(PvRow {
+                        name: name.clone(),
+                        used: used.parse().unwrap_or(0),
+                        missing: attr.chars().nth(2) == Some('m'),
+                    })
+                }
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Mark a PV of this VG as a spare for raid repairs
+    pub fn add_spare(&mut self, pv_name: &str) -> LvmResult<()> {
+        self.pv_from_name(pv_name)?.add_tag(SPARE_TAG)
+    }
+
+    /// Stop a PV being used as a spare
+    pub fn remove_spare(&mut self, pv_name: &str) -> LvmResult<()> {
+        self.pv_from_name(pv_name)?.remove_tag(SPARE_TAG)
+    }
+
+    /// List the device names of this VG's spare PVs that are present
+    pub fn spares(&self) -> LvmResult<Vec<String>> {
+        Ok(self
+            .spare_rows()?
+            .into_iter()
+            .filter(|pv| !pv.missing)
+            .map(|pv| pv.name)
+            .collect())
+    }
+
+    /// List the raid LVs of this VG that report a health problem
+    pub fn degraded_raid_lvs(&self) -> LvmResult<Vec<DegradedRaid>> {
+        let rows = cli::report(
+            self.lvm,
+            "lvs",
+            &["lv_name", "segtype", "lv_health_status"],
+            &self.get_name()?,
+        )?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| match row.as_slice() {
+                [name, segtype, health] if segtype.starts_with("raid") && !health.is_empty() => {
+                    Some(DegradedRaid {
+                        name: name.clone(),
+                        segtype: segtype.clone(),
+                        health: health.clone(),
+                    })
+                }
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Rebuild the failed images of a raid LV onto the VG's spares, like
+    /// lvconvert --repair limited to the spare PVs.  Spares the rebuild
+    /// allocated from lose their tag, and their names are returned.  Fails
+    /// with ENOSPC when the VG has no spares.
+    pub fn repair_with_spare(&mut self, lv_name: &str) -> LvmResult<Vec<String>> {
+        let before = self.spare_rows()?;
+        let spares: Vec<&str> = before
+            .iter()
+            .filter(|pv| !pv.missing)
+            .map(|pv| pv.name.as_str())
+            .collect();
+        if spares.is_empty() {
+            return Err(LvmError::new((
+                Errno(libc::ENOSPC),
+                format!(
+                    "{} has no spare PVs to repair {} onto",
+                    self.get_name()?,
+                    lv_name
+                ),
+            )));
+        }
+        let lv_path = format!("{}/{}", self.get_name()?, lv_name);
+        let mut args = vec!["lvconvert", "--yes", "--repair", &lv_path];
+        args.extend_from_slice(&spares);
+        self.run_command(&args)?;
+
+        let mut used = vec![];
+        for pv in self.spare_rows()? {
+            let was_used = before
+                .iter()
+                .find(|b| b.name == pv.name)
+                .map(|b| b.used)
+                .unwrap_or(0);
+            if pv.used > was_used {
+                used.push(pv.name);
+            }
+        }
+        for pv_name in &used {
+            self.remove_spare(pv_name)?;
+        }
+        Ok(used)
+    }
+}
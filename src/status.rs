@@ -0,0 +1,358 @@
+//! Kernel status of active LVs, read with the device-mapper STATUS ioctl
+//! and parsed per target type.  All sizes are in the units the kernel
+//! reports them in: 512 byte sectors or the target's own block size.
+
+use crate::{dm, LogicalVolume, LvmResult};
+
+/// Status of a thin pool
+#[derive(Debug)]
+pub struct ThinPoolStatus {
+    pub transaction_id: u64,
+    pub used_metadata_blocks: u64,
+    pub total_metadata_blocks: u64,
+    pub used_data_blocks: u64,
+    pub total_data_blocks: u64,
+    /// Root of a userspace held metadata snapshot, if any
+    pub held_metadata_root: Option<u64>,
+    pub read_only: bool,
+    pub out_of_data_space: bool,
+    pub discard_passdown: bool,
+    /// Whether IO errors (true) or queues (false) when the pool is full
+    pub error_if_no_space: bool,
+    pub needs_check: bool,
+}
+
+/// Status of a thin LV
+#[derive(Debug)]
+pub struct ThinStatus {
+    pub mapped_sectors: u64,
+    /// None if nothing has been mapped yet
+    pub highest_mapped_sector: Option<u64>,
+}
+
+/// Status of a raid LV
+#[derive(Debug)]
+pub struct RaidStatus {
+    pub raid_type: String,
+    pub devices: u32,
+    /// One character per device: A alive and in sync, a alive but not in
+    /// sync, D dead
+    pub health: String,
+    pub synced_sectors: u64,
+    pub total_sectors: u64,
+    /// idle, frozen, resync, recover, check, repair or reshape
+    pub sync_action: String,
+    pub mismatch_count: u64,
+}
+
+impl RaidStatus {
+    /// Whether any device of the array has failed
+    pub fn is_degraded(&self) -> bool {
+        self.health.contains('D')
+    }
+}
+
+/// Status of a cached LV
+#[derive(Debug)]
+pub struct CacheStatus {
+    pub metadata_block_size: u64,
+    pub used_metadata_blocks: u64,
+    pub total_metadata_blocks: u64,
+    pub cache_block_size: u64,
+    pub used_cache_blocks: u64,
+    pub total_cache_blocks: u64,
+    pub read_hits: u64,
+    pub read_misses: u64,
+    pub write_hits: u64,
+    pub write_misses: u64,
+    pub demotions: u64,
+    pub promotions: u64,
+    pub dirty_blocks: u64,
+    pub features: Vec<String>,
+    pub policy: String,
+    pub read_only: bool,
+    pub needs_check: bool,
+}
+
+/// Status of a classic copy-on-write snapshot
+#[derive(Debug)]
+pub enum SnapshotStatus {
+    Active {
+        allocated_sectors: u64,
+        total_sectors: u64,
+        metadata_sectors: u64,
+    },
+    /// The snapshot ran out of space and was dropped
+    Invalid,
+    Overflow,
+    MergeFailed,
+}
+
+/// Parsed status of one target
+#[derive(Debug)]
+pub enum TargetStatus {
+    ThinPool(ThinPoolStatus),
+    Thin(ThinStatus),
+    Raid(RaidStatus),
+    Cache(CacheStatus),
+    Snapshot(SnapshotStatus),
+    /// The target reported a failure
+    Failed,
+    /// Any other target, or a status line that couldn't be parsed
+    Other(String),
+}
+
+/// One segment of an active LV's device-mapper table
+#[derive(Debug)]
+pub struct Segment {
+    /// Start of the segment in 512 byte sectors
+    pub start: u64,
+    /// Length of the segment in 512 byte sectors
+    pub length: u64,
+    pub target_type: String,
+    pub status: TargetStatus,
+}
+
+fn ratio(s: &str) -> Option<(u64, u64)> {
+    let mut parts = s.splitn(2, '/');
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+fn parse_thin_pool(fields: &[&str]) -> Option<ThinPoolStatus> {
+    let (used_metadata_blocks, total_metadata_blocks) = ratio(fields.get(1)?)?;
+    let (used_data_blocks, total_data_blocks) = ratio(fields.get(2)?)?;
+    let has = |flag: &str| fields.iter().skip(4).any(|f| *f == flag);
+    Some(ThinPoolStatus {
+        transaction_id: fields.first()?.parse().ok()?,
+        used_metadata_blocks,
+        total_metadata_blocks,
+        used_data_blocks,
+        total_data_blocks,
+        held_metadata_root: fields.get(3)?.parse().ok(),
+        read_only: has("ro"),
+        out_of_data_space: has("out_of_data_space"),
+        discard_passdown: has("discard_passdown"),
+        error_if_no_space: has("error_if_no_space"),
+        needs_check: has("needs_check"),
+    })
+}
+
+fn parse_thin(fields: &[&str]) -> Option<ThinStatus> {
+    Some(ThinStatus {
+        mapped_sectors: fields.first()?.parse().ok()?,
+        highest_mapped_sector: fields.get(1).and_then(|f| f.parse().ok()),
+    })
+}
+
+fn parse_raid(fields: &[&str]) -> Option<RaidStatus> {
+    let (synced_sectors, total_sectors) = ratio(fields.get(3)?)?;
+    Some(RaidStatus {
+        raid_type: fields.first()?.to_string(),
+        devices: fields.get(1)?.parse().ok()?,
+        health: fields.get(2)?.to_string(),
+        synced_sectors,
+        total_sectors,
+        sync_action: fields.get(4).map(|f| f.to_string()).unwrap_or_default(),
+        mismatch_count: fields.get(5).and_then(|f| f.parse().ok()).unwrap_or(0),
+    })
+}
+
+fn parse_cache(fields: &[&str]) -> Option<CacheStatus> {
+    let num = |i: usize| -> Option<u64> { fields.get(i)?.parse().ok() };
+    let (used_metadata_blocks, total_metadata_blocks) = ratio(fields.get(1)?)?;
+    let (used_cache_blocks, total_cache_blocks) = ratio(fields.get(3)?)?;
+    let feature_count = num(11)? as usize;
+    let features: Vec<String> = fields
+        .get(12..12 + feature_count)?
+        .iter()
+        .map(|f| f.to_string())
+        .collect();
+    let mut i = 12 + feature_count;
+    let core_args = num(i)? as usize;
+    i += 1 + core_args;
+    let policy = fields.get(i)?.to_string();
+    let policy_args = num(i + 1)? as usize;
+    i += 2 + policy_args;
+    Some(CacheStatus {
+        metadata_block_size: num(0)?,
+        used_metadata_blocks,
+        total_metadata_blocks,
+        cache_block_size: num(2)?,
+        used_cache_blocks,
+        total_cache_blocks,
+        read_hits: num(4)?,
+        read_misses: num(5)?,
+        write_hits: num(6)?,
+        write_misses: num(7)?,
+        demotions: num(8)?,
+        promotions: num(9)?,
+        dirty_blocks: num(10)?,
+        features,
+        policy,
+        read_only: fields.get(i) == Some(&"ro"),
+        needs_check: fields.get(i + 1) == Some(&"needs_check"),
+    })
+}
+
+fn parse_snapshot(params: &str, fields: &[&str]) -> Option<SnapshotStatus> {
+    match params.trim() {
+        "Invalid" => return Some(SnapshotStatus::Invalid),
+        "Overflow" => return Some(SnapshotStatus::Overflow),
+        "Merge failed" => return Some(SnapshotStatus::MergeFailed),
+        _ => {}
+    }
+    let (allocated_sectors, total_sectors) = ratio(fields.first()?)?;
+    Some(SnapshotStatus::Active {
+        allocated_sectors,
+        total_sectors,
+        metadata_sectors: fields.get(1)?.parse().ok()?,
+    })
+}
+
+/// Parse the status line of a target
+pub(crate) fn parse_status(target_type: &str, params: &str) -> TargetStatus {
+    let fields: Vec<&str> = params.split_whitespace().collect();
+    if fields.first() == Some(&"Fail") {
+        return TargetStatus::Failed;
+    }
+    let status = match target_type {
+        "thin-pool" => parse_thin_pool(&fields).map(TargetStatus::ThinPool),
+        "thin" => parse_thin(&fields).map(TargetStatus::Thin),
+        "raid" => parse_raid(&fields).map(TargetStatus::Raid),
+        "cache" => parse_cache(&fields).map(TargetStatus::Cache),
+        "snapshot" | "snapshot-merge" => {
+            parse_snapshot(params, &fields).map(TargetStatus::Snapshot)
+        }
+        _ => None,
+    };
+    status.unwrap_or_else(|| TargetStatus::Other(params.to_string()))
+}
+
+/// Get the status of every segment of an active device by its dm name
+pub fn dm_status(dm_name: &str) -> LvmResult<Vec<Segment>> {
+    Ok(dm::table_status(dm_name)?
+        .into_iter()
+        .map(|line| Segment {
+            start: line.start,
+            length: line.length,
+            status: parse_status(&line.target_type, &line.params),
+            target_type: line.target_type,
+        })
+        .collect())
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Get the kernel status of every segment of an active LV
+    pub fn dm_status(&self) -> LvmResult<Vec<Segment>> {
+        dm_status(&self.dm_name()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thin_pool() {
+        let status = parse_status(
+            "thin-pool",
+            "1 125/4096 1024/65536 - rw discard_passdown queue_if_no_space - 1024",
+        );
+        match status {
+            TargetStatus::ThinPool(pool) => {
+                assert_eq!(pool.transaction_id, 1);
+                assert_eq!(pool.used_metadata_blocks, 125);
+                assert_eq!(pool.total_metadata_blocks, 4096);
+                assert_eq!(pool.used_data_blocks, 1024);
+                assert_eq!(pool.total_data_blocks, 65536);
+                assert_eq!(pool.held_metadata_root, None);
+                assert!(!pool.read_only);
+                assert!(pool.discard_passdown);
+                assert!(!pool.error_if_no_space);
+                assert!(!pool.needs_check);
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse_status(
+            "thin-pool",
+            "3 1/4096 0/65536 42 ro error_if_no_space needs_check",
+        ) {
+            TargetStatus::ThinPool(pool) => {
+                assert_eq!(pool.held_metadata_root, Some(42));
+                assert!(pool.read_only);
+                assert!(pool.error_if_no_space);
+                assert!(pool.needs_check);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn cache() {
+        let status = parse_status(
+            "cache",
+            "8 32/4096 128 10/1000 5 6 7 8 0 1 2 1 writethrough 2 migration_threshold 2048 \
+             smq 0 rw -",
+        );
+        match status {
+            TargetStatus::Cache(cache) => {
+                assert_eq!(cache.metadata_block_size, 8);
+                assert_eq!(cache.used_metadata_blocks, 32);
+                assert_eq!(cache.total_metadata_blocks, 4096);
+                assert_eq!(cache.cache_block_size, 128);
+                assert_eq!(cache.used_cache_blocks, 10);
+                assert_eq!(cache.total_cache_blocks, 1000);
+                assert_eq!(cache.read_hits, 5);
+                assert_eq!(cache.read_misses, 6);
+                assert_eq!(cache.write_hits, 7);
+                assert_eq!(cache.write_misses, 8);
+                assert_eq!(cache.demotions, 0);
+                assert_eq!(cache.promotions, 1);
+                assert_eq!(cache.dirty_blocks, 2);
+                assert_eq!(cache.features, vec!["writethrough".to_string()]);
+                assert_eq!(cache.policy, "smq");
+                assert!(!cache.read_only);
+                assert!(!cache.needs_check);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn raid() {
+        match parse_status("raid", "raid1 2 AD 512/1024 recover 0 0 -") {
+            TargetStatus::Raid(raid) => {
+                assert_eq!(raid.raid_type, "raid1");
+                assert_eq!(raid.devices, 2);
+                assert_eq!(raid.health, "AD");
+                assert!(raid.is_degraded());
+                assert_eq!(raid.synced_sectors, 512);
+                assert_eq!(raid.total_sectors, 1024);
+                assert_eq!(raid.sync_action, "recover");
+                assert_eq!(raid.mismatch_count, 0);
+            }
+            other => panic!("{:?}", other),
+        }
+        // Old kernels stop after the sync ratio
+        match parse_status("raid", "raid5_ls 3 AAA 2048/2048") {
+            TargetStatus::Raid(raid) => {
+                assert!(!raid.is_degraded());
+                assert_eq!(raid.sync_action, "");
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn failed_and_unparsable() {
+        assert!(matches!(
+            parse_status("thin-pool", "Fail"),
+            TargetStatus::Failed
+        ));
+        assert!(matches!(
+            parse_status("raid", "raid1 2"),
+            TargetStatus::Other(_)
+        ));
+        assert!(matches!(parse_status("linear", ""), TargetStatus::Other(_)));
+    }
+}
@@ -0,0 +1,87 @@
+//! LVs used as swap space.  A swap LV is a plain linear LV with a swap
+//! signature written by mkswap, enabled and disabled with the swapon and
+//! swapoff syscalls.  See boot::BootEntry to enable it at boot.
+
+use std::ffi::CString;
+use std::fs;
+use std::io::Error as IOError;
+use std::os::unix::ffi::OsStrExt;
+
+use errno::Errno;
+
+use crate::{cli, CreatedLv, LogicalVolume, LvmError, LvmResult, VolumeGroup};
+
+impl<'a> VolumeGroup<'a> {
+    /// Create an active linear LV of size bytes and format it as swap.  It
+    /// isn't in use until swapon is called on it.  If mkswap fails the LV
+    /// is removed again.  In test mode mkswap isn't run.
+    pub fn create_swap_lv(&mut self, name: &str, size: u64) -> LvmResult<CreatedLv<'_, '_>> {
+        let lvm = self.lvm;
+        let mut created = self.create_lv_linear(name, size)?;
+        if !created.try_is_active()? {
+            created.activate()?;
+        }
+        let device = created.path.to_string_lossy().into_owned();
+        if lvm.is_test_mode() {
+            info!("test mode, not running mkswap on {}", device);
+            return Ok(created);
+        }
+        let params = [("args", format!("mkswap {}", device))];
+        let formatted = lvm.audited("mkswap", &device, &params, || {
+            cli::run_program("mkswap", &[&device])
+        });
+        if let Err(e) = formatted {
+            if let Err(remove_err) = created.lv.remove() {
+                warn!("Removing {} after mkswap failed: {}", device, remove_err);
+            }
+            return Err(e);
+        }
+        Ok(created)
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    fn swap_call<F>(&self, op: &'static str, f: F) -> LvmResult<()>
+    where
+        F: FnOnce(*const libc::c_char) -> libc::c_int,
+    {
+        let device = self.path()?;
+        let c_device = CString::new(device.as_os_str().as_bytes())?;
+        self.lvm.audited(op, &device.to_string_lossy(), &[], || {
+            if f(c_device.as_ptr()) < 0 {
+                let err = IOError::last_os_error();
+                return Err(LvmError::new((
+                    Errno(err.raw_os_error().unwrap_or(libc::EIO)),
+                    format!("{} {} failed: {}", op, device.display(), err),
+                )));
+            }
+            Ok(())
+        })
+    }
+
+    /// Start swapping to the LV, which must hold a swap signature
+    pub fn swapon(&mut self) -> LvmResult<()> {
+        self.swap_call("swapon", |device| unsafe { libc::swapon(device, 0) })
+    }
+
+    /// Stop swapping to the LV.  The kernel moves its pages back into
+    /// memory first, which fails with ENOMEM if there isn't room.
+    pub fn swapoff(&mut self) -> LvmResult<()> {
+        self.swap_call("swapoff", |device| unsafe { libc::swapoff(device) })
+    }
+
+    /// Check if the LV is in use as swap
+    pub fn is_swap_on(&self) -> LvmResult<bool> {
+        if !self.try_is_active()? {
+            return Ok(false);
+        }
+        // /proc/swaps names devices by their canonical path, /dev/dm-N
+        let device = fs::canonicalize(self.path()?)?;
+        let swaps = fs::read_to_string("/proc/swaps")?;
+        Ok(swaps
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().next())
+            .any(|name| fs::canonicalize(name).ok().as_ref() == Some(&device)))
+    }
+}
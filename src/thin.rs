@@ -0,0 +1,74 @@
+//! Thin snapshots.  Unlike classic COW snapshots these take no size, share
+//! the pool with their origin and can themselves be snapshotted, so a thin
+//! LV may sit at the end of a chain of origins.
+
+use std::collections::HashSet;
+
+use crate::{CreatedLv, LogicalVolume, LvmResult, VolumeGroup};
+
+impl<'a> VolumeGroup<'a> {
+    /// Create a thin snapshot called name of the thin LV origin.  Thin
+    /// snapshots skip activation by default, activate them with
+    /// `lvchange -K` or by clearing activation skip.
+    pub fn create_thin_snapshot(
+        &mut self,
+        origin: &str,
+        name: &str,
+    ) -> LvmResult<CreatedLv<'_, '_>> {
+        let origin_path = format!("{}/{}", self.get_name()?, origin);
+        self.run_command(&["lvcreate", "--snapshot", "--name", name, &origin_path])?;
+        CreatedLv::new(self.lv_from_name(name)?)
+    }
+
+    /// Create a thin snapshot called name in thin_pool whose reads fall
+    /// through to origin, any LV in this VG.  The external origin is never
+    /// written to and must be read only or inactive.
+    pub fn create_thin_snapshot_external(
+        &mut self,
+        origin: &str,
+        thin_pool: &str,
+        name: &str,
+    ) -> LvmResult<CreatedLv<'_, '_>> {
+        let vg_name = self.get_name()?;
+        let origin_path = format!("{}/{}", vg_name, origin);
+        let pool_path = format!("{}/{}", vg_name, thin_pool);
+        self.run_command(&[
+            "lvcreate",
+            "--snapshot",
+            "--thinpool",
+            &pool_path,
+            "--name",
+            name,
+            &origin_path,
+        ])?;
+        CreatedLv::new(self.lv_from_name(name)?)
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Get the chain of origins this LV was snapshotted from, starting with
+    /// its direct origin and ending with the oldest ancestor, which for a
+    /// thin snapshot with an external origin is the external LV.  The chain
+    /// stops at an origin that can't be found in the VG.
+    pub fn thin_ancestry(&self) -> LvmResult<Vec<String>> {
+        let mut ancestry = vec![];
+        let mut seen = HashSet::new();
+        let mut origin = self.try_origin()?;
+        while let Some(name) = origin {
+            if !seen.insert(name.clone()) {
+                break;
+            }
+            let exists = self
+                .vg
+                .list_lvs()?
+                .iter()
+                .any(|lv| lv.try_name().ok().as_ref() == Some(&name));
+            ancestry.push(name.clone());
+            if !exists {
+                break;
+            }
+            origin = self.vg.lv_from_name(&name)?.try_origin()?;
+        }
+        Ok(ancestry)
+    }
+}
@@ -0,0 +1,263 @@
+//! thin_check, thin_dump and thin_restore from thin-provisioning-tools,
+//! run against thin pool metadata.  The pool's own metadata LV is hidden
+//! and changes under a running pool, so swap it out to a visible LV first
+//! with VolumeGroup::swap_out_thin_pool_metadata and activate that.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+
+use errno::Errno;
+
+use crate::{cli, LogicalVolume, LvmError, LvmResult};
+
+/// What thin_check made of some metadata
+#[derive(Clone, Debug)]
+pub struct ThinCheckReport {
+    /// No damage was found
+    pub ok: bool,
+    /// Everything thin_check printed, one entry per line
+    pub messages: Vec<String>,
+}
+
+/// The superblock of thin pool metadata
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThinSuperblock {
+    pub uuid: String,
+    pub time: u64,
+    pub transaction: u64,
+    /// Size of a data block in 512 byte sectors
+    pub data_block_size: u64,
+    pub nr_data_blocks: u64,
+}
+
+/// A thin LV as the pool's metadata knows it, by device id
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThinDevice {
+    pub dev_id: u64,
+    pub mapped_blocks: u64,
+    pub transaction: u64,
+    pub creation_time: u64,
+    pub snap_time: u64,
+}
+
+/// thin_dump's description of thin pool metadata, without the block
+/// mappings
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThinMetadata {
+    pub superblock: ThinSuperblock,
+    pub devices: Vec<ThinDevice>,
+}
+
+/// Parse the attributes of an XML element on one line, ie:
+/// <device dev_id="1" mapped_blocks="10">
+fn attributes(line: &str) -> HashMap<&str, &str> {
+    let mut attrs = HashMap::new();
+    let mut rest = line;
+    while let Some(eq) = rest.find("=\"") {
+        let key = rest[..eq].rsplit(' ').next().unwrap_or("");
+        let value_start = eq + 2;
+        let value_len = match rest[value_start..].find('"') {
+            Some(len) => len,
+            None => break,
+        };
+        attrs.insert(key, &rest[value_start..value_start + value_len]);
+        rest = &rest[value_start + value_len + 1..];
+    }
+    attrs
+}
+
+fn parse_dump(xml: &str) -> LvmResult<ThinMetadata> {
+    let invalid = |what: &str| {
+        LvmError::new((
+            Errno(libc::EINVAL),
+            format!("Unexpected thin_dump output: {}", what),
+        ))
+    };
+    let number = |attrs: &HashMap<&str, &str>, name: &str| -> LvmResult<u64> {
+        attrs
+            .get(name)
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| invalid(&format!("missing or invalid {}", name)))
+    };
+    let mut superblock = None;
+    let mut devices = vec![];
+    for line in xml.lines().map(|l| l.trim()) {
+        if line.starts_with("<superblock ") {
+            let attrs = attributes(line);
+            superblock = Some(ThinSuperblock {
+                uuid: attrs.get("uuid").unwrap_or(&"").to_string(),
+                time: number(&attrs, "time")?,
+                transaction: number(&attrs, "transaction")?,
+                data_block_size: number(&attrs, "data_block_size")?,
+                nr_data_blocks: number(&attrs, "nr_data_blocks")?,
+            });
+        } else if line.starts_with("<device ") {
+            let attrs = attributes(line);
+            devices.push(ThinDevice {
+                dev_id: number(&attrs, "dev_id")?,
+                mapped_blocks: number(&attrs, "mapped_blocks")?,
+                transaction: number(&attrs, "transaction")?,
+                creation_time: number(&attrs, "creation_time")?,
+                snap_time: number(&attrs, "snap_time")?,
+            });
+        }
+    }
+    Ok(ThinMetadata {
+        superblock: superblock.ok_or_else(|| invalid("no superblock"))?,
+        devices,
+    })
+}
+
+/// Run thin_check on the metadata on device.  Damage is reported in the
+/// result, an error means thin_check couldn't be run.
+pub fn check(device: &Path) -> LvmResult<ThinCheckReport> {
+    debug!("running thin_check {}", device.display());
+    let output = Command::new("thin_check").arg(device).output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Ok(ThinCheckReport {
+        ok: output.status.success(),
+        messages: stdout
+            .lines()
+            .chain(stderr.lines())
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .collect(),
+    })
+}
+
+/// Read the superblock and devices of the metadata on device with
+/// thin_dump, skipping the block mappings
+pub fn dump(device: &Path) -> LvmResult<ThinMetadata> {
+    let device = device.to_string_lossy();
+    parse_dump(&cli::run_program(
+        "thin_dump",
+        &["--skip-mappings", &device],
+    )?)
+}
+
+/// Dump the metadata on device, mappings and all, to an XML file for
+/// thin_restore
+pub fn dump_to(device: &Path, output: &Path) -> LvmResult<()> {
+    let device = device.to_string_lossy();
+    let output = output.to_string_lossy();
+    cli::run_program("thin_dump", &["--output", &output, &device])?;
+    Ok(())
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Fail with EINVAL unless the LV is active, the tools need its device
+    fn active_path(&self) -> LvmResult<String> {
+        if !self.try_is_active()? {
+            return Err(LvmError::new((
+                Errno(libc::EINVAL),
+                format!("{} must be active", self.try_name()?),
+            )));
+        }
+        Ok(self.path()?.to_string_lossy().into_owned())
+    }
+
+    /// Run thin_check on the thin pool metadata this LV holds, see check
+    pub fn thin_check(&self) -> LvmResult<ThinCheckReport> {
+        check(Path::new(&self.active_path()?))
+    }
+
+    /// Read the thin pool metadata this LV holds, see dump
+    pub fn thin_dump(&self) -> LvmResult<ThinMetadata> {
+        dump(Path::new(&self.active_path()?))
+    }
+
+    /// Overwrite this LV with thin pool metadata restored from an XML file
+    /// written by dump_to or edited by hand
+    pub fn thin_restore(&mut self, input: &Path) -> LvmResult<()> {
+        let device = self.active_path()?;
+        let input = input.to_string_lossy().into_owned();
+        let args = ["--input", input.as_str(), "--output", device.as_str()];
+        let params = [
+            ("input", input.clone()),
+            ("args", format!("thin_restore {}", args.join(" "))),
+        ];
+        self.vg.lvm.audited("thin_restore", &device, &params, || {
+            cli::run_program("thin_restore", &args)
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = r#"<superblock uuid="" time="3" transaction="5" flags="0" version="2" data_block_size="128" nr_data_blocks="16384">
+  <device dev_id="1" mapped_blocks="10" transaction="0" creation_time="0" snap_time="2">
+    <range_mapping origin_begin="0" data_begin="0" length="10" time="0"/>
+  </device>
+  <device dev_id="2" mapped_blocks="0" transaction="4" creation_time="2" snap_time="2">
+  </device>
+</superblock>
+"#;
+
+    #[test]
+    fn parses_attributes() {
+        let attrs = attributes(r#"<device dev_id="1" mapped_blocks="10">"#);
+        assert_eq!(attrs.len(), 2);
+        assert_eq!(attrs["dev_id"], "1");
+        assert_eq!(attrs["mapped_blocks"], "10");
+
+        let attrs = attributes(r#"<superblock uuid="" note="a=b c" time="3"/>"#);
+        assert_eq!(attrs["uuid"], "");
+        assert_eq!(attrs["note"], "a=b c");
+        assert_eq!(attrs["time"], "3");
+
+        // An unterminated value ends the attributes
+        let attrs = attributes(r#"<device dev_id="1" mapped_blocks="10"#);
+        assert_eq!(attrs.len(), 1);
+        assert!(attributes("</device>").is_empty());
+    }
+
+    #[test]
+    fn parses_a_dump() {
+        let metadata = parse_dump(DUMP).unwrap();
+        assert_eq!(
+            metadata.superblock,
+            ThinSuperblock {
+                uuid: String::new(),
+                time: 3,
+                transaction: 5,
+                data_block_size: 128,
+                nr_data_blocks: 16384,
+            }
+        );
+        assert_eq!(
+            metadata.devices,
+            vec![
+                ThinDevice {
+                    dev_id: 1,
+                    mapped_blocks: 10,
+                    transaction: 0,
+                    creation_time: 0,
+                    snap_time: 2,
+                },
+                ThinDevice {
+                    dev_id: 2,
+                    mapped_blocks: 0,
+                    transaction: 4,
+                    creation_time: 2,
+                    snap_time: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_dumps() {
+        assert!(parse_dump("").is_err());
+        assert!(parse_dump(r#"<device dev_id="1">"#).is_err());
+        let missing = DUMP.replace(r#" nr_data_blocks="16384""#, "");
+        assert!(parse_dump(&missing).is_err());
+        let invalid = DUMP.replace(r#"mapped_blocks="10""#, r#"mapped_blocks="ten""#);
+        assert!(parse_dump(&invalid).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+//! Recovering thin pools whose metadata is damaged.  A pool's metadata LV
+//! is hidden and can only be reached by swapping it with a visible LV,
+//! which is how it's examined or repaired by hand with thin_check and
+//! thin_repair.  Both need the pool and every thin LV in it inactive.
+
+use errno::Errno;
+
+use crate::{LogicalVolume, LvmError, LvmResult, VolumeGroup};
+
+impl<'a> VolumeGroup<'a> {
+    /// Fail with EBUSY if an LV of the VG is active
+    fn check_inactive(&self, lv_names: &[&str]) -> LvmResult<()> {
+        for name in lv_names {
+            if self.lv_from_name(name)?.try_is_active()? {
+                return Err(LvmError::new((
+                    Errno(libc::EBUSY),
+                    format!("{}/{} is active", self.get_name()?, name),
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn lv_names(&self) -> LvmResult<Vec<String>> {
+        self.list_lvs()?.iter().map(|lv| lv.try_name()).collect()
+    }
+
+    /// Repair the metadata of a thin pool, lvconvert --repair.  lvm runs
+    /// thin_repair into fresh metadata and keeps the damaged copy as a new
+    /// visible LV, ie: pool_meta0, whose name is returned so it can be
+    /// examined and removed once the pool is known to be good.
+    pub fn repair_thin_pool(&mut self, pool: &str) -> LvmResult<Option<String>> {
+        self.check_inactive(&[pool])?;
+        let before = self.lv_names()?;
+        let pool_path = format!("{}/{}", self.get_name()?, pool);
+        self.run_command(&["lvconvert", "--yes", "--repair", &pool_path])?;
+        Ok(self
+            .lv_names()?
+            .into_iter()
+            .find(|name| !before.contains(name)))
+    }
+
+    /// Swap the metadata LV of a thin pool with metadata_lv, an inactive
+    /// LV of the VG, lvconvert --poolmetadata.  Afterwards metadata_lv holds
+    /// the pool's old metadata and can be activated and examined, swapping
+    /// the same two again puts it back.  metadata_lv must be at least as
+    /// big as what it replaces.
+    pub fn swap_thin_pool_metadata(&mut self, pool: &str, metadata_lv: &str) -> LvmResult<()> {
+        self.check_inactive(&[pool, metadata_lv])?;
+        let vg_name = self.get_name()?;
+        let pool_path = format!("{}/{}", vg_name, pool);
+        let metadata_path = format!("{}/{}", vg_name, metadata_lv);
+        self.run_command(&[
+            "lvconvert",
+            "--yes",
+            "--thinpool",
+            &pool_path,
+            "--poolmetadata",
+            &metadata_path,
+        ])?;
+        Ok(())
+    }
+
+    /// Create an inactive LV called name the size of a thin pool's
+    /// metadata and swap it in, leaving the pool's metadata in name for
+    /// thin_check or thin_dump.  Swap back with swap_thin_pool_metadata.
+    pub fn swap_out_thin_pool_metadata(&mut self, pool: &str, name: &str) -> LvmResult<()> {
+        self.check_inactive(&[pool])?;
+        let size = self.lv_from_name(pool)?.thin_pool_metadata_size()?;
+        let vg_name = self.get_name()?;
+        // Nothing reads the new LV before the swap, so there's no need to
+        // zero it, and zeroing needs it active
+        self.run_command(&[
+            "lvcreate",
+            "--yes",
+            "--activate",
+            "n",
+            "--zero",
+            "n",
+            "--size",
+            &format!("{}b", size),
+            "--name",
+            name,
+            &vg_name,
+        ])?;
+        self.swap_thin_pool_metadata(pool, name)
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Check if the LV is a thin pool
+    pub fn is_thin_pool(&self) -> LvmResult<bool> {
+        Ok(self.try_attributes()?.starts_with('t'))
+    }
+
+    /// Get the size in bytes of a thin pool's metadata LV
+    pub fn thin_pool_metadata_size(&self) -> LvmResult<u64> {
+        if !self.is_thin_pool()? {
+            return Err(LvmError::new((
+                Errno(libc::EINVAL),
+                format!("{} is not a thin pool", self.try_name()?),
+            )));
+        }
+        Ok(self.get_integer_property("lv_metadata_size")? as u64)
+    }
+}
@@ -0,0 +1,95 @@
+//! Instrumentation of calls into liblvm and the lvm tools.  With the
+//! tracing feature every call runs inside a span recording the operation,
+//! the VG/LV/PV it targets, how long it took and the errno it failed with.
+//! Without it calls are logged at debug level through log.  Failed liblvm
+//! calls made through Lvm::ffi_call also get the call and its arguments
+//! added to their message.
+
+use std::time::Instant;
+
+use crate::{Lvm, LvmError, LvmResult};
+
+/// Run f, an FFI call or command operating on object, inside a span
+#[cfg(feature = "tracing")]
+pub(crate) fn call<T, F>(op: &'static str, object: &str, f: F) -> LvmResult<T>
+where
+    F: FnOnce() -> LvmResult<T>,
+{
+    let span = tracing::debug_span!(
+        "lvm",
+        op,
+        object,
+        duration_us = tracing::field::Empty,
+        errno = tracing::field::Empty,
+    );
+    let _enter = span.enter();
+    let start = Instant::now();
+    let result = f();
+    span.record("duration_us", &(start.elapsed().as_micros() as u64));
+    if let Err(ref e) = result {
+        span.record("errno", &e.errno());
+        tracing::debug!(error = %e, "{} failed", op);
+    }
+    result
+}
+
+/// Run f, an FFI call or command operating on object, logging how long it
+/// took and the errno it failed with
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn call<T, F>(op: &'static str, object: &str, f: F) -> LvmResult<T>
+where
+    F: FnOnce() -> LvmResult<T>,
+{
+    let start = Instant::now();
+    let result = f();
+    match result {
+        Ok(_) => debug!("{} {} took {:?}", op, object, start.elapsed()),
+        Err(ref e) => debug!(
+            "{} {} failed after {:?} with errno {}: {}",
+            op,
+            object,
+            start.elapsed(),
+            e.errno(),
+            e
+        ),
+    }
+    result
+}
+
+/// Add the failing call to an error's message, ie:
+/// lvm_lv_resize(data01, size=1073741824) failed: ...
+fn with_context(err: LvmError, op: &str, object: &str, params: &[(&str, String)]) -> LvmError {
+    match err {
+        LvmError::Error((errno, msg)) => {
+            let mut args: Vec<String> = vec![];
+            if !object.is_empty() {
+                args.push(object.to_string());
+            }
+            args.extend(params.iter().map(|(k, v)| format!("{}={}", k, v)));
+            LvmError::Error((
+                errno,
+                format!("{}({}) failed: {}", op, args.join(", "), msg),
+            ))
+        }
+        other => other,
+    }
+}
+
+impl Lvm {
+    /// Run f, a liblvm call operating on object with params, through call.
+    /// liblvm's error is noted first so a stale one isn't blamed on this
+    /// call and a failure says which call it was.
+    pub(crate) fn ffi_call<T, F>(
+        &self,
+        op: &'static str,
+        object: &str,
+        params: &[(&str, String)],
+        f: F,
+    ) -> LvmResult<T>
+    where
+        F: FnOnce() -> LvmResult<T>,
+    {
+        self.clear_error();
+        call(op, object, f).map_err(|e| with_context(e, op, object, params))
+    }
+}
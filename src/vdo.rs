@@ -0,0 +1,115 @@
+//! VDO (deduplicated and compressed) logical volumes.  liblvm has no VDO
+//! support so these are created and queried through the lvm tools.
+
+use errno::Errno;
+
+use crate::{cli, LogicalVolume, LvmError, LvmResult, VolumeGroup};
+
+/// Settings for a new VDO pool
+#[derive(Debug)]
+pub struct VdoConfig {
+    pub compression: bool,
+    pub deduplication: bool,
+}
+
+impl Default for VdoConfig {
+    fn default() -> Self {
+        VdoConfig {
+            compression: true,
+            deduplication: true,
+        }
+    }
+}
+
+/// Space usage of a VDO LV
+#[derive(Debug)]
+pub struct VdoStats {
+    /// Percentage of space saved by deduplication and compression
+    pub saving_percent: f64,
+    /// Percentage of the pool's physical space in use
+    pub used_percent: f64,
+}
+
+fn yes_no(b: bool) -> &'static str {
+    if b {
+        "y"
+    } else {
+        "n"
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Create a VDO pool using size bytes of physical space
+    pub fn create_vdo_pool(&mut self, name: &str, size: u64, config: &VdoConfig) -> LvmResult<()> {
+        let vg_name = self.get_name()?;
+        self.run_command(&[
+            "lvcreate",
+            "--yes",
+            "--type",
+            "vdo-pool",
+            "--size",
+            &format!("{}b", size),
+            "--compression",
+            yes_no(config.compression),
+            "--deduplication",
+            yes_no(config.deduplication),
+            "--name",
+            name,
+            &vg_name,
+        ])?;
+        Ok(())
+    }
+
+    /// Create a VDO LV of virtual_size bytes backed by a VDO pool
+    pub fn create_vdo_lv(
+        &mut self,
+        pool: &str,
+        name: &str,
+        virtual_size: u64,
+    ) -> LvmResult<LogicalVolume<'_, '_>> {
+        let pool_path = format!("{}/{}", self.get_name()?, pool);
+        self.run_command(&[
+            "lvcreate",
+            "--yes",
+            "--type",
+            "vdo",
+            "--vdopool",
+            &pool_path,
+            "--virtualsize",
+            &format!("{}b", virtual_size),
+            "--name",
+            name,
+        ])?;
+        self.lv_from_name(name)
+    }
+}
+
+impl<'a, 'b> LogicalVolume<'a, 'b> {
+    /// Get the space savings and usage of a VDO LV
+    pub fn vdo_stats(&self) -> LvmResult<VdoStats> {
+        let full_name = self.full_name()?;
+        let rows = cli::report(
+            self.lvm,
+            "lvs",
+            &["vdo_saving_percent", "data_percent"],
+            &full_name,
+        )?;
+        let parse = |field: Option<&String>| field.and_then(|f| f.parse::<f64>().ok());
+        match rows.first() {
+            Some(row) => match (parse(row.first()), parse(row.get(1))) {
+                (Some(saving_percent), Some(used_percent)) => Ok(VdoStats {
+                    saving_percent,
+                    used_percent,
+                }),
+                _ => Err(LvmError::new((
+                    Errno(libc::EINVAL),
+                    format!("{} is not a VDO LV", full_name),
+                ))),
+            },
+            None => Err(LvmError::new((
+                Errno(libc::ENOENT),
+                format!("{} not found", full_name),
+            ))),
+        }
+    }
+}
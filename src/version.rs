@@ -0,0 +1,178 @@
+//! Version information for this crate, liblvm and the device-mapper
+//! kernel driver, and detection of which optional targets the running
+//! kernel supports so callers can check before attempting an operation.
+
+use std::ffi::CStr;
+use std::fs;
+use std::path::Path;
+
+use lvm_sys::lvm_library_get_version;
+
+use crate::{dm, Lvm, LvmResult};
+
+/// Version of this crate
+pub const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Optional features supported by the running system
+#[derive(Debug)]
+pub struct Capabilities {
+    pub thin: bool,
+    pub cache: bool,
+    pub raid: bool,
+    pub vdo: bool,
+    pub mirror: bool,
+    pub snapshot: bool,
+    pub writecache: bool,
+    pub integrity: bool,
+}
+
+/// Device-mapper target types
+#[derive(Debug)]
+pub enum TargetType {
+    Linear,
+    Striped,
+    Zero,
+    Error,
+    Snapshot,
+    Mirror,
+    Raid,
+    ThinPool,
+    Thin,
+    Cache,
+    Writecache,
+    Integrity,
+    Vdo,
+    Crypt,
+}
+
+impl TargetType {
+    /// Name the kernel registers the target under
+    fn name(&self) -> &'static str {
+        match self {
+            TargetType::Linear => "linear",
+            TargetType::Striped => "striped",
+            TargetType::Zero => "zero",
+            TargetType::Error => "error",
+            TargetType::Snapshot => "snapshot",
+            TargetType::Mirror => "mirror",
+            TargetType::Raid => "raid",
+            TargetType::ThinPool => "thin-pool",
+            TargetType::Thin => "thin",
+            TargetType::Cache => "cache",
+            TargetType::Writecache => "writecache",
+            TargetType::Integrity => "integrity",
+            TargetType::Vdo => "vdo",
+            TargetType::Crypt => "crypt",
+        }
+    }
+
+    /// Kernel modules that may provide the target, empty for the targets
+    /// built into dm-mod
+    fn modules(&self) -> &'static [&'static str] {
+        match self {
+            TargetType::Linear | TargetType::Striped | TargetType::Error => &[],
+            TargetType::Zero => &["dm_zero"],
+            TargetType::Snapshot => &["dm_snapshot"],
+            TargetType::Mirror => &["dm_mirror"],
+            TargetType::Raid => &["dm_raid"],
+            TargetType::ThinPool | TargetType::Thin => &["dm_thin_pool"],
+            TargetType::Cache => &["dm_cache"],
+            TargetType::Writecache => &["dm_writecache"],
+            TargetType::Integrity => &["dm_integrity"],
+            TargetType::Vdo => &["dm_vdo", "kvdo"],
+            TargetType::Crypt => &["dm_crypt"],
+        }
+    }
+}
+
+/// Device-mapper targets currently loaded in the kernel with their versions
+pub(crate) fn loaded_targets() -> LvmResult<Vec<(String, String)>> {
+    Ok(dm::list_versions()?
+        .into_iter()
+        .map(|(name, v)| (name, format!("{}.{}.{}", v[0], v[1], v[2])))
+        .collect())
+}
+
+/// Check whether a kernel module is loaded, built in or can be loaded on
+/// demand.  dm targets are usually modules that only load on first use so
+/// a missing target doesn't mean it's unsupported.
+pub(crate) fn module_available(module: &str) -> bool {
+    if Path::new("/sys/module").join(module).exists() {
+        return true;
+    }
+    let release = match fs::read_to_string("/proc/sys/kernel/osrelease") {
+        Ok(release) => release.trim().to_string(),
+        Err(_) => return false,
+    };
+    let modules_dir = Path::new("/lib/modules").join(release);
+    // Module files use either '-' or '_' in their names
+    let dashed = module.replace('_', "-");
+    ["modules.builtin", "modules.dep"].iter().any(|list| {
+        fs::read_to_string(modules_dir.join(list))
+            .map(|contents| {
+                contents.lines().any(|line| {
+                    let path = line.split(':').next().unwrap_or("");
+                    let file = path.rsplit('/').next().unwrap_or("");
+                    let stem = file.split(".ko").next().unwrap_or("");
+                    stem == module || stem == dashed
+                })
+            })
+            .unwrap_or(false)
+    })
+}
+
+impl Lvm {
+    /// Get the version of liblvm, ie: 2.02.176(2) (2017-11-03)
+    pub fn library_version(&self) -> String {
+        unsafe {
+            let version = lvm_library_get_version();
+            CStr::from_ptr(version).to_string_lossy().into_owned()
+        }
+    }
+
+    /// Get the version of the device-mapper kernel driver, ie: 4.39.0
+    pub fn driver_version(&self) -> LvmResult<String> {
+        let v = dm::driver_version()?;
+        Ok(format!("{}.{}.{}", v[0], v[1], v[2]))
+    }
+
+    /// Check whether the kernel supports a device-mapper target, either
+    /// because it's already registered or because its module can be loaded.
+    /// Useful for a clear error, ie: "kernel lacks dm-thin", before
+    /// attempting an operation that needs the target.
+    pub fn has_target(&self, target: &TargetType) -> bool {
+        let loaded = match dm::list_versions() {
+            Ok(targets) => targets.iter().any(|(name, _)| name == target.name()),
+            Err(e) => {
+                debug!("listing dm targets failed: {}", e);
+                false
+            }
+        };
+        loaded || target.modules().iter().any(|m| module_available(m))
+    }
+
+    /// Get the name and version of every device-mapper target loaded in
+    /// the kernel, ie: ("thin-pool", "1.22.0")
+    pub fn target_versions(&self) -> LvmResult<Vec<(String, String)>> {
+        loaded_targets()
+    }
+
+    /// Find out which optional features the running system supports
+    pub fn capabilities(&self) -> LvmResult<Capabilities> {
+        let targets = loaded_targets()?;
+        let has = |target: TargetType| {
+            targets.iter().any(|(name, _)| name == target.name())
+                || target.modules().iter().any(|m| module_available(m))
+        };
+        Ok(Capabilities {
+            thin: has(TargetType::ThinPool),
+            cache: has(TargetType::Cache),
+            raid: has(TargetType::Raid),
+            vdo: has(TargetType::Vdo),
+            mirror: has(TargetType::Mirror),
+            snapshot: has(TargetType::Snapshot),
+            writecache: has(TargetType::Writecache),
+            integrity: has(TargetType::Integrity),
+        })
+    }
+}
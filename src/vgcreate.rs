@@ -0,0 +1,133 @@
+//! VG creation that takes its devices and settings up front, rather than
+//! creating, extending and writing step by step.
+
+use std::ffi::CString;
+use std::path::Path;
+
+use errno::Errno;
+use lvm_sys::*;
+
+use crate::{validate_tag, Lvm, LvmError, LvmResult, VolumeGroup};
+
+/// Settings for a new VG.  Anything left as None uses lvm's default.
+#[derive(Clone, Debug, Default)]
+pub struct VgCreateOptions {
+    /// Physical extent size in bytes, a power of 2 of at least 1KiB
+    pub extent_size: Option<u32>,
+    /// Maximum number of LVs, zero for no limit.  liblvm can't set this
+    /// so, like clustered, it's applied by vgchange right after the first
+    /// write and only by vg_create_with_devices.
+    pub max_lv: Option<u64>,
+    /// Maximum number of PVs, zero for no limit, see max_lv
+    pub max_pv: Option<u64>,
+    /// Number of metadata copies to keep across the PVs, zero for
+    /// unmanaged
+    pub metadata_copies: Option<u32>,
+    /// Tags to add
+    pub tags: Vec<String>,
+    /// Mark the VG as shared by a cluster.  liblvm can't set this so it's
+    /// applied by vgchange right after the first write and only by
+    /// vg_create_with_devices.
+    pub clustered: Option<bool>,
+}
+
+impl Lvm {
+    /// Create a VG in memory with options applied, ready for devices to be
+    /// added.  The settings are included in the VG's first write.  Fails
+    /// with EINVAL if max_lv or max_pv are set, only vg_create_with_devices
+    /// can apply those.
+    pub fn vg_create_with_options(
+        &self,
+        name: &str,
+        options: &VgCreateOptions,
+    ) -> LvmResult<VolumeGroup<'_>> {
+        if options.max_lv.is_some() || options.max_pv.is_some() {
+            return Err(LvmError::new((
+                Errno(libc::EINVAL),
+                format!(
+                    "Can't limit the LVs or PVs of VG {} before it's written, \
+                     use vg_create_with_devices",
+                    name
+                ),
+            )));
+        }
+        for tag in &options.tags {
+            validate_tag(tag)?;
+        }
+        let mut vg = self.vg_create(name)?;
+        if let Some(size) = options.extent_size {
+            unsafe {
+                vg.check_retcode(lvm_vg_set_extent_size(vg.handle()?, size))?;
+            }
+        }
+        if let Some(copies) = options.metadata_copies {
+            vg.set_integer_property("vg_mda_copies", u64::from(copies))?;
+        }
+        for tag in &options.tags {
+            let tag = CString::new(tag.as_str())?;
+            unsafe {
+                vg.check_retcode(lvm_vg_add_tag(vg.handle()?, tag.as_ptr()))?;
+            }
+        }
+        Ok(vg)
+    }
+
+    /// Create a VG on devices with options and return a write handle to
+    /// it.  Nothing is written to disk until every device has been added
+    /// so a failure part way leaves no partial VG behind.
+    pub fn vg_create_with_devices(
+        &self,
+        name: &str,
+        devices: &[&Path],
+        mut options: VgCreateOptions,
+    ) -> LvmResult<VolumeGroup<'_>> {
+        if devices.is_empty() {
+            return Err(LvmError::new((
+                Errno(libc::EINVAL),
+                format!("VG {} needs at least one device", name),
+            )));
+        }
+        let max_lv = options.max_lv.take();
+        let max_pv = options.max_pv.take();
+        let mut vg = self.vg_create_with_options(name, &options)?;
+        for device in devices {
+            vg.extend_uncommitted(device)?;
+        }
+        vg.commit()?;
+        if let Some(clustered) = options.clustered {
+            vg.run_command(&[
+                "vgchange",
+                "--clustered",
+                if clustered { "y" } else { "n" },
+                name,
+            ])?;
+        }
+        if let Some(max_lv) = max_lv {
+            vg.set_max_lv(max_lv)?;
+        }
+        if let Some(max_pv) = max_pv {
+            vg.set_max_pv(max_pv)?;
+        }
+        Ok(vg)
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Set an integer property in memory, it's written by the next commit
+    fn set_integer_property(&mut self, name: &str, value: u64) -> LvmResult<()> {
+        let prop_name = CString::new(name)?;
+        unsafe {
+            let mut prop = lvm_vg_get_property(self.handle()?, prop_name.as_ptr());
+            if prop.is_valid() == 0 {
+                let err = self.lvm.get_error()?;
+                return Err(LvmError::new((err.0, err.1)));
+            }
+            prop.value.integer = value;
+            self.check_retcode(lvm_vg_set_property(
+                self.handle()?,
+                prop_name.as_ptr(),
+                &mut prop,
+            ))
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Opening a VG with more than a mode, for recovery tools that need to
+//! look at VGs the lvm tools would refuse to open normally.
+
+use std::cell::Cell;
+use std::ffi::CString;
+
+use errno::Errno;
+use lvm_sys::*;
+
+use crate::{retry, Lvm, LvmError, LvmResult, OpenMode, VolumeGroup};
+
+/// How to open a VG, see Lvm::vg_open_with_options.
+///
+/// The recovery settings turn on lvm.conf settings for the whole Lvm handle
+/// and stay on after the VG is closed, much like set_test_mode, so use a
+/// handle of its own for recovery.  They only combine with OpenMode::Read:
+/// both keep metadata from being written and opening for write fails with
+/// EINVAL.
+#[derive(Clone, Debug)]
+pub struct VgOpenOptions {
+    mode: OpenMode,
+    flags: u32,
+    ignore_locking_failure: bool,
+    read_only_metadata: bool,
+}
+
+impl Default for VgOpenOptions {
+    fn default() -> Self {
+        VgOpenOptions::new(OpenMode::Read)
+    }
+}
+
+impl VgOpenOptions {
+    pub fn new(mode: OpenMode) -> Self {
+        VgOpenOptions {
+            mode,
+            flags: 0,
+            ignore_locking_failure: false,
+            read_only_metadata: false,
+        }
+    }
+
+    /// Raw flags passed to lvm_vg_open.  liblvm reserves them for future
+    /// use and current releases expect 0.
+    pub fn flags(mut self, flags: u32) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Open the VG without taking any lock, the equivalent of the lvm
+    /// tools' --ignorelockingfailure.  For when the locking directory or
+    /// lock manager is unusable, nothing stops another process changing
+    /// the VG while it's read.
+    pub fn ignore_locking_failure(mut self, ignore: bool) -> Self {
+        self.ignore_locking_failure = ignore;
+        self
+    }
+
+    /// Never write metadata, not even to repair inconsistent copies while
+    /// reading them, the equivalent of the lvm tools' --readonly
+    pub fn read_only_metadata(mut self, read_only: bool) -> Self {
+        self.read_only_metadata = read_only;
+        self
+    }
+
+    fn settings(&self) -> Vec<&'static str> {
+        let mut settings = vec![];
+        if self.ignore_locking_failure {
+            settings.push("global/locking_type=0");
+        }
+        if self.ignore_locking_failure || self.read_only_metadata {
+            settings.push("global/metadata_read_only=1");
+        }
+        settings
+    }
+}
+
+/// Messages lvm_vg_open fails with when reading a device failed
+const IO_ERRORS: &[&str] = &["read failed", "input/output error", "checksum error"];
+
+/// An errno telling apart the ways lvm_vg_open fails: ENOENT for a VG that
+/// doesn't exist, EAGAIN for one another process has locked and EIO when
+/// its metadata couldn't be read.  liblvm often gives a generic errno and
+/// only says which in the message.
+fn vg_open_errno(errno: Errno, msg: &str) -> Errno {
+    let lower = msg.to_lowercase();
+    if lower.contains("not found") {
+        Errno(libc::ENOENT)
+    } else if retry::is_lock_error(&LvmError::new((errno, msg.to_string()))) {
+        Errno(libc::EAGAIN)
+    } else if IO_ERRORS.iter().any(|e| lower.contains(e)) {
+        Errno(libc::EIO)
+    } else {
+        errno
+    }
+}
+
+impl Lvm {
+    /// Open a VG as options describe, see VgOpenOptions.  Failures are
+    /// classified by LvmError::kind as NotFound, Locked or Io where liblvm
+    /// says which, and Locked failures are retried with the lock retry
+    /// policy.
+    pub fn vg_open_with_options(
+        &self,
+        name: &str,
+        options: &VgOpenOptions,
+    ) -> LvmResult<VolumeGroup<'_>> {
+        let settings = options.settings();
+        if !settings.is_empty() && options.mode == OpenMode::Write {
+            return Err(LvmError::new((
+                Errno(libc::EINVAL),
+                format!(
+                    "Can't open {} for writing while ignoring locking failures or with read \
+                     only metadata",
+                    name
+                ),
+            )));
+        }
+        let missing: Vec<&str> = settings
+            .into_iter()
+            .filter(|s| !self.config_overrides.borrow().iter().any(|o| o == s))
+            .collect();
+        if !missing.is_empty() {
+            for setting in missing {
+                self.config_override(setting)?;
+            }
+            self.reload_config()?;
+        }
+
+        let name = CString::new(name)?;
+        let mode = options.mode;
+        let mode_str = CString::new(mode.to_string())?;
+        let mut params = vec![("mode", mode.to_string())];
+        if options.flags != 0 {
+            params.push(("flags", options.flags.to_string()));
+        }
+        retry::retry(self.lock_retry.get(), || {
+            self.ffi_call("lvm_vg_open", &name.to_string_lossy(), &params, || unsafe {
+                let vg_handle = lvm_vg_open(
+                    self.handle(),
+                    name.as_ptr(),
+                    mode_str.as_ptr(),
+                    options.flags,
+                );
+                if vg_handle.is_null() {
+                    let (errno, msg) = self.get_error()?;
+                    return Err(LvmError::new((vg_open_errno(errno, &msg), msg)));
+                }
+                Ok(VolumeGroup {
+                    handle: Cell::new(vg_handle),
+                    lvm: self,
+                    mode,
+                    lock_retry: None,
+                    generation: Cell::new(0),
+                    pins: Cell::new(0),
+                })
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_vg_open_errors() {
+        let generic = Errno(libc::EINVAL);
+        let cases = [
+            ("Volume group \"data\" not found", libc::ENOENT),
+            ("Can't get lock for data", libc::EAGAIN),
+            (
+                "/dev/sdb: read failed after 0 of 4096 at 0: Input/output error",
+                libc::EIO,
+            ),
+            ("Checksum error at offset 4608", libc::EIO),
+            ("Something else went wrong", libc::EINVAL),
+        ];
+        for &(msg, errno) in &cases {
+            assert_eq!(vg_open_errno(generic, msg).0, errno, "{}", msg);
+        }
+        // liblvm's own EAGAIN is kept whatever the message
+        assert_eq!(vg_open_errno(Errno(libc::EAGAIN), "busy").0, libc::EAGAIN);
+    }
+}
@@ -0,0 +1,89 @@
+//! dm-writecache support.  A fast cache LV, ie: on an nvme PV, is attached
+//! to a slower origin LV and absorbs writes before they're written back.
+//! liblvm has no support for this so it's done through the lvm tools.
+
+use crate::{cli, dm_name, LvmResult, VolumeGroup};
+
+/// Tunables for an attached writecache.  None keeps the kernel default.
+#[derive(Debug, Default)]
+pub struct WritecacheSettings {
+    /// Cache block size in bytes, 512 or 4096
+    pub block_size: Option<u32>,
+    /// Percentage of cache use at which writeback starts
+    pub high_watermark: Option<u32>,
+    /// Percentage of cache use at which writeback stops
+    pub low_watermark: Option<u32>,
+    /// Limit on the number of blocks in flight during writeback
+    pub writeback_jobs: Option<u32>,
+}
+
+impl WritecacheSettings {
+    fn cache_settings(&self) -> Option<String> {
+        let settings: Vec<String> = vec![
+            ("high_watermark", self.high_watermark),
+            ("low_watermark", self.low_watermark),
+            ("writeback_jobs", self.writeback_jobs),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|v| format!("{}={}", key, v)))
+        .collect();
+        if settings.is_empty() {
+            None
+        } else {
+            Some(settings.join(" "))
+        }
+    }
+}
+
+impl<'a> VolumeGroup<'a> {
+    /// Attach cache_lv as a writecache in front of lv_name.  cache_lv must
+    /// be an inactive LV in this VG and is hidden once attached.
+    pub fn attach_writecache(
+        &mut self,
+        lv_name: &str,
+        cache_lv: &str,
+        settings: &WritecacheSettings,
+    ) -> LvmResult<()> {
+        let vg_name = self.get_name()?;
+        let lv_path = format!("{}/{}", vg_name, lv_name);
+        let block_size = settings.block_size.map(|b| format!("{}b", b));
+        let cache_settings = settings.cache_settings();
+        let mut args = vec![
+            "lvconvert",
+            "--yes",
+            "--type",
+            "writecache",
+            "--cachevol",
+            cache_lv,
+        ];
+        if let Some(ref block_size) = block_size {
+            args.extend_from_slice(&["--writecacheblocksize", block_size]);
+        }
+        if let Some(ref cache_settings) = cache_settings {
+            args.extend_from_slice(&["--cachesettings", cache_settings]);
+        }
+        args.push(&lv_path);
+        self.run_command(&args)?;
+        Ok(())
+    }
+
+    /// Flush all dirty blocks in lv_name's writecache to the origin
+    pub fn flush_writecache(&self, lv_name: &str) -> LvmResult<()> {
+        let name = dm_name(&self.get_name()?, lv_name);
+        let args = ["message", name.as_str(), "0", "flush"];
+        let params = [("args", format!("dmsetup {}", args.join(" ")))];
+        self.lvm
+            .audited("dmsetup_message_flush", &name, &params, || {
+                cli::run_program("dmsetup", &args)
+            })?;
+        Ok(())
+    }
+
+    /// Flush and detach the writecache from lv_name.  The cache LV is kept
+    /// as a regular LV afterwards.
+    pub fn detach_writecache(&mut self, lv_name: &str) -> LvmResult<()> {
+        let lv_path = format!("{}/{}", self.get_name()?, lv_name);
+        self.run_command(&["lvconvert", "--yes", "--splitcache", &lv_path])?;
+        Ok(())
+    }
+}